@@ -0,0 +1,500 @@
+use std::{
+    cmp::Ordering,
+    fs::canonicalize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use color_eyre::eyre::eyre;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, functions::FunctionFlags, params};
+
+use crate::{
+    model::{is_book, parse_position},
+    opf::OpfDocument,
+};
+
+/// The name of the database a Calibre library keeps its metadata in
+pub const CALIBRE_DATABASE: &str = "metadata.db";
+/// The name of the OPF sidecar Calibre keeps next to the files of every book
+pub const METADATA_OPF: &str = "metadata.opf";
+/// The formats of a book the app edits, in the order they are preferred when a book has
+/// more than one
+const FORMAT_ORDER: [&str; 8] = ["EPUB", "AZW3", "MOBI", "AZW", "FB2", "CBZ", "PDF", "PRC"];
+/// The articles moved to the end of titles when Calibre sorts them
+const TITLE_SORT_ARTICLES: [&str; 3] = ["A", "An", "The"];
+/// How long to wait for Calibre to release the database before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Struct holding a book of a Calibre library, along with the file of it the app edits
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryBook {
+    /// The id of the book in the database
+    pub id: i64,
+    /// The file of the book that is edited, its first format the app can edit
+    pub path: PathBuf,
+    /// The title of the book
+    pub title: String,
+    /// The authors of the book, separated by `&` as Calibre shows them
+    pub authors: String,
+    /// The series of the book, if it is in one
+    pub series: Option<String>,
+    /// The position of the book in its series
+    pub series_index: f64,
+}
+
+impl LibraryBook {
+    /// Get the line the book is listed with, its series and position followed by its title
+    /// and authors
+    pub fn label(&self) -> String {
+        let series = self
+            .series
+            .as_ref()
+            .map(|series| format!("{} [{}] - ", series, self.series_index))
+            .unwrap_or_default();
+
+        format!("{}{} by {}", series, self.title, self.authors)
+    }
+}
+
+/// Struct holding a Calibre library, read from its `metadata.db`
+#[derive(Clone, Debug)]
+pub struct Library {
+    /// The directory of the library
+    pub directory: PathBuf,
+    /// The books of the library that have a format the app can edit, books in a series
+    /// coming first by series and position, then the others by author and title
+    pub books: Vec<LibraryBook>,
+}
+
+impl Library {
+    /// Open the Calibre library in a directory, reading the books it holds
+    pub fn open(directory: &Path) -> color_eyre::Result<Self> {
+        let directory = canonicalize(directory)
+            .map_err(|err| eyre!("Unable to open {}: {}", directory.display(), err))?;
+        let database = directory.join(CALIBRE_DATABASE);
+        if !database.is_file() {
+            return Err(eyre!(
+                "{} is not a Calibre library, it has no {}",
+                directory.display(),
+                CALIBRE_DATABASE
+            ));
+        }
+        let connection = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+
+        let mut statement = connection.prepare(
+            "SELECT books.id, books.title, books.path, books.series_index, series.name,
+                (SELECT group_concat(name, ' & ') FROM (
+                    SELECT authors.name FROM books_authors_link
+                    JOIN authors ON authors.id = books_authors_link.author
+                    WHERE books_authors_link.book = books.id
+                    ORDER BY books_authors_link.id
+                ))
+            FROM books
+            LEFT JOIN books_series_link ON books_series_link.book = books.id
+            LEFT JOIN series ON series.id = books_series_link.series",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<f64>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })?;
+        let mut formats = connection.prepare("SELECT format, name FROM data WHERE book = ?1")?;
+        let mut books = Vec::new();
+        for row in rows {
+            let (id, title, path, series_index, series, authors) = row?;
+            let book_directory = directory.join(&path);
+            let files: Vec<PathBuf> = formats
+                .query_map([id], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .filter_map(|format| format.ok())
+                .filter(|(format, _)| FORMAT_ORDER.contains(&format.to_uppercase().as_str()))
+                .map(|(format, name)| {
+                    book_directory.join(format!("{}.{}", name, format.to_lowercase()))
+                })
+                .filter(|file| file.is_file() && is_book(file))
+                .collect();
+            // Books with none of the formats the app can edit aren't listed
+            let Some(path) = FORMAT_ORDER.iter().find_map(|format| {
+                files.iter().find(|file| {
+                    file.extension()
+                        .is_some_and(|extension| extension.eq_ignore_ascii_case(format))
+                })
+            }) else {
+                continue;
+            };
+            books.push(LibraryBook {
+                id,
+                path: path.to_owned(),
+                title,
+                authors: authors.unwrap_or_default().replace('|', ","),
+                series,
+                series_index: series_index.unwrap_or(1.0),
+            });
+        }
+        books.sort_by(|a, b| match (&a.series, &b.series) {
+            (Some(a_series), Some(b_series)) => a_series
+                .to_lowercase()
+                .cmp(&b_series.to_lowercase())
+                .then(a.series_index.total_cmp(&b.series_index)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a
+                .authors
+                .to_lowercase()
+                .cmp(&b.authors.to_lowercase())
+                .then(a.title.to_lowercase().cmp(&b.title.to_lowercase())),
+        });
+
+        Ok(Library { directory, books })
+    }
+
+    /// Get the book of the library a file belongs to
+    pub fn book(&self, path: &Path) -> Option<&LibraryBook> {
+        self.books
+            .iter()
+            .find(|book| book.path == path)
+            .or_else(|| {
+                let path = canonicalize(path).ok()?;
+                self.books.iter().find(|book| book.path == path)
+            })
+    }
+
+    /// Get the paths of the files of the library's books, in the order they are listed
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.books.iter().map(|book| book.path.to_owned()).collect()
+    }
+
+    /// Write the title, series, position and authors of a package document to the database
+    /// rows of the book a file belongs to
+    ///
+    /// The book's title sort is filled in by the database's triggers, and a series or author
+    /// left without any book is removed as Calibre does. The authors are left as they are
+    /// when the package has none, as Calibre gives every book at least one.
+    pub fn update_book(&mut self, path: &Path, metadata: &OpfDocument) -> color_eyre::Result<()> {
+        let id = self
+            .book(path)
+            .map(|book| book.id)
+            .ok_or_else(|| eyre!("{} is not a book of the Calibre library", path.display()))?;
+        let title = metadata
+            .title()
+            .ok_or_else(|| eyre!("{} has no title", path.display()))?;
+        let series = metadata
+            .series()
+            .filter(|(series, _)| !series.trim().is_empty());
+        let series_index = match series.as_ref().and_then(|(_, index)| index.as_deref()) {
+            Some(index) => parse_position(index)?,
+            None => 1.0,
+        };
+        // Calibre keeps the commas of author names as `|`, as it separates authors with them
+        let authors: Vec<(String, String)> = metadata
+            .creators()
+            .iter()
+            .filter(|creator| creator.is_author() && !creator.name.trim().is_empty())
+            .map(|creator| {
+                (
+                    creator.name.trim().replace(',', "|"),
+                    creator.normalized().file_as.unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        let mut connection = Connection::open(self.directory.join(CALIBRE_DATABASE))?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        // Calibre's triggers call the functions it registers on its connections
+        connection.create_scalar_function(
+            "title_sort",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |context| Ok(title_sort(&context.get::<String>(0)?)),
+        )?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "UPDATE books SET title = ?1, sort = title_sort(?1), series_index = ?2,
+                last_modified = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')
+            WHERE id = ?3",
+            params![title, series_index, id],
+        )?;
+        let old_series: Option<i64> = transaction
+            .query_row(
+                "SELECT series FROM books_series_link WHERE book = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        transaction.execute("DELETE FROM books_series_link WHERE book = ?1", [id])?;
+        if let Some((series, _)) = &series {
+            let series = series.trim();
+            transaction.execute(
+                "INSERT OR IGNORE INTO series (name, sort) VALUES (?1, title_sort(?1))",
+                [series],
+            )?;
+            let series_id: i64 = transaction.query_row(
+                "SELECT id FROM series WHERE name = ?1 COLLATE NOCASE",
+                [series],
+                |row| row.get(0),
+            )?;
+            transaction.execute(
+                "INSERT INTO books_series_link (book, series) VALUES (?1, ?2)",
+                params![id, series_id],
+            )?;
+        }
+        if let Some(old_series) = old_series {
+            transaction.execute(
+                "DELETE FROM series WHERE id = ?1
+                    AND NOT EXISTS (SELECT 1 FROM books_series_link WHERE series = ?1)",
+                [old_series],
+            )?;
+        }
+        if !authors.is_empty() {
+            let old_authors: Vec<i64> = transaction
+                .prepare("SELECT author FROM books_authors_link WHERE book = ?1")?
+                .query_map([id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            transaction.execute("DELETE FROM books_authors_link WHERE book = ?1", [id])?;
+            for (name, sort) in &authors {
+                transaction.execute(
+                    "INSERT OR IGNORE INTO authors (name, sort) VALUES (?1, ?2)",
+                    [name, sort],
+                )?;
+                let author_id: i64 = transaction.query_row(
+                    "SELECT id FROM authors WHERE name = ?1 COLLATE NOCASE",
+                    [name],
+                    |row| row.get(0),
+                )?;
+                transaction.execute(
+                    "INSERT OR IGNORE INTO books_authors_link (book, author) VALUES (?1, ?2)",
+                    params![id, author_id],
+                )?;
+            }
+            let author_sort: Vec<&str> = authors.iter().map(|(_, sort)| sort.as_str()).collect();
+            transaction.execute(
+                "UPDATE books SET author_sort = ?1 WHERE id = ?2",
+                params![author_sort.join(" & "), id],
+            )?;
+            for old_author in old_authors {
+                transaction.execute(
+                    "DELETE FROM authors WHERE id = ?1
+                        AND NOT EXISTS (SELECT 1 FROM books_authors_link WHERE author = ?1)",
+                    [old_author],
+                )?;
+            }
+        }
+        transaction.commit()?;
+
+        if let Some(book) = self.books.iter_mut().find(|book| book.id == id) {
+            book.title = title;
+            book.series = series.map(|(series, _)| series.trim().to_string());
+            book.series_index = series_index;
+            if !authors.is_empty() {
+                let names: Vec<String> = authors
+                    .iter()
+                    .map(|(name, _)| name.replace('|', ","))
+                    .collect();
+                book.authors = names.join(" & ");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the title a book is sorted by, with a leading English article moved to the end as
+/// Calibre does by default (e.g. `Foundation, The`)
+fn title_sort(title: &str) -> String {
+    let title = title.trim();
+    for article in TITLE_SORT_ARTICLES {
+        if let Some(prefix) = title.get(..article.len())
+            && prefix.eq_ignore_ascii_case(article)
+            && title[article.len()..].starts_with(char::is_whitespace)
+        {
+            return format!("{}, {}", title[article.len()..].trim_start(), prefix);
+        }
+    }
+
+    title.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, create_dir_all};
+
+    use tempfile::{TempDir, tempdir};
+
+    use super::*;
+
+    /// The tables of Calibre's schema the app reads and writes, along with the trigger
+    /// keeping the title sort of a book up to date
+    const SCHEMA: &str = "
+        CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT NOT NULL, sort TEXT,
+            path TEXT NOT NULL, series_index REAL NOT NULL DEFAULT 1.0, author_sort TEXT,
+            last_modified TIMESTAMP);
+        CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT NOT NULL COLLATE NOCASE,
+            sort TEXT COLLATE NOCASE, UNIQUE(name));
+        CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL,
+            author INTEGER NOT NULL, UNIQUE(book, author));
+        CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT NOT NULL COLLATE NOCASE,
+            sort TEXT COLLATE NOCASE, UNIQUE(name));
+        CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL,
+            series INTEGER NOT NULL, UNIQUE(book));
+        CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER NOT NULL,
+            format TEXT NOT NULL COLLATE NOCASE, name TEXT NOT NULL, UNIQUE(book, format));
+        CREATE TRIGGER books_update_trg AFTER UPDATE ON books BEGIN
+            UPDATE books SET sort = title_sort(NEW.title)
+            WHERE id = NEW.id AND OLD.title <> NEW.title;
+        END;";
+
+    /// Build a library holding two books of a series by the same author, the first with a
+    /// co-author, and a book with only a PDF
+    fn library() -> TempDir {
+        let directory = tempdir().unwrap();
+        let connection = Connection::open(directory.path().join(CALIBRE_DATABASE)).unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO authors VALUES (1, 'Isaac Asimov', 'Asimov, Isaac'),
+                    (2, 'Ghost Writer', 'Writer, Ghost');
+                INSERT INTO series VALUES (1, 'Foundation', 'Foundation');
+                INSERT INTO books VALUES
+                    (1, 'Foundation', 'Foundation', 'Isaac Asimov/Foundation (1)', 1.0,
+                        'Asimov, Isaac & Writer, Ghost', NULL),
+                    (2, 'Second Foundation', 'Second Foundation',
+                        'Isaac Asimov/Second Foundation (2)', 3.0, 'Asimov, Isaac', NULL),
+                    (3, 'I, Robot', 'I, Robot', 'Isaac Asimov/I, Robot (3)', 1.0,
+                        'Asimov, Isaac', NULL);
+                INSERT INTO books_authors_link (book, author) VALUES (1, 1), (1, 2), (2, 1),
+                    (3, 1);
+                INSERT INTO books_series_link (book, series) VALUES (1, 1), (2, 1);
+                INSERT INTO data (book, format, name) VALUES
+                    (1, 'EPUB', 'Foundation - Isaac Asimov'),
+                    (1, 'PDF', 'Foundation - Isaac Asimov'),
+                    (2, 'EPUB', 'Second Foundation - Isaac Asimov'),
+                    (3, 'PDF', 'I, Robot - Isaac Asimov');",
+            )
+            .unwrap();
+        for (path, file) in [
+            ("Foundation (1)", "Foundation - Isaac Asimov.epub"),
+            ("Foundation (1)", "Foundation - Isaac Asimov.pdf"),
+            (
+                "Second Foundation (2)",
+                "Second Foundation - Isaac Asimov.epub",
+            ),
+            ("I, Robot (3)", "I, Robot - Isaac Asimov.pdf"),
+        ] {
+            let book_directory = directory.path().join("Isaac Asimov").join(path);
+            create_dir_all(&book_directory).unwrap();
+            File::create(book_directory.join(file)).unwrap();
+        }
+
+        directory
+    }
+
+    #[test]
+    fn books_are_listed_by_series_with_their_preferred_format() {
+        let directory = library();
+        let library = Library::open(directory.path()).unwrap();
+
+        let labels: Vec<String> = library.books.iter().map(LibraryBook::label).collect();
+        assert_eq!(
+            labels,
+            [
+                "Foundation [1] - Foundation by Isaac Asimov & Ghost Writer",
+                "Foundation [3] - Second Foundation by Isaac Asimov",
+                "I, Robot by Isaac Asimov",
+            ]
+        );
+        assert_eq!(
+            library.books[0].path.file_name().unwrap(),
+            "Foundation - Isaac Asimov.epub"
+        );
+        assert_eq!(
+            library.books[2].path.file_name().unwrap(),
+            "I, Robot - Isaac Asimov.pdf"
+        );
+    }
+
+    #[test]
+    fn edited_books_are_written_to_the_database() {
+        let directory = library();
+        let mut library = Library::open(directory.path()).unwrap();
+        let path = library.books[0].path.to_owned();
+        let metadata = OpfDocument::from_metadata(&[
+            String::from("<dc:title>The Foundation</dc:title>"),
+            String::from("<dc:creator opf:role=\"aut\">Isaac Asimov</dc:creator>"),
+            String::from("<dc:creator opf:role=\"aut\">Doe, Jane</dc:creator>"),
+            String::from("<dc:creator opf:role=\"trl\">Translator</dc:creator>"),
+            String::from("<meta name=\"calibre:series\" content=\"Foundation Saga\"/>"),
+            String::from("<meta name=\"calibre:series_index\" content=\"1.5\"/>"),
+        ])
+        .unwrap();
+        library.update_book(&path, &metadata).unwrap();
+
+        let book = library.book(&path).unwrap();
+        assert_eq!(
+            book.label(),
+            "Foundation Saga [1.5] - The Foundation by Isaac Asimov & Doe, Jane"
+        );
+        let reopened = Library::open(directory.path()).unwrap();
+        assert_eq!(reopened.book(&path), Some(book));
+
+        let connection = Connection::open(directory.path().join(CALIBRE_DATABASE)).unwrap();
+        let (sort, author_sort): (String, String) = connection
+            .query_row(
+                "SELECT sort, author_sort FROM books WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(sort, "Foundation, The");
+        assert_eq!(author_sort, "Asimov, Isaac & Doe, Jane");
+        let authors: Vec<String> = connection
+            .prepare("SELECT name FROM authors ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // The author left without a book is removed, and the comma of a name kept as `|`
+        assert_eq!(authors, ["Isaac Asimov", "Doe| Jane"]);
+        let series: Vec<String> = connection
+            .prepare("SELECT name FROM series ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // The old series still holds the second book
+        assert_eq!(series, ["Foundation", "Foundation Saga"]);
+    }
+
+    #[test]
+    fn books_without_authors_keep_theirs() {
+        let directory = library();
+        let mut library = Library::open(directory.path()).unwrap();
+        let path = library.books[2].path.to_owned();
+        let metadata =
+            OpfDocument::from_metadata(&[String::from("<dc:title>I, Robot</dc:title>")]).unwrap();
+        library.update_book(&path, &metadata).unwrap();
+
+        let reopened = Library::open(directory.path()).unwrap();
+        assert_eq!(reopened.books[2].label(), "I, Robot by Isaac Asimov");
+    }
+
+    #[test]
+    fn leading_articles_are_moved_to_the_end_of_the_title_sort() {
+        assert_eq!(title_sort("The Foundation"), "Foundation, The");
+        assert_eq!(title_sort(" a Time of Changes "), "Time of Changes, a");
+        assert_eq!(
+            title_sort("An Unexpected Journey"),
+            "Unexpected Journey, An"
+        );
+        assert_eq!(title_sort("Theodora"), "Theodora");
+        assert_eq!(title_sort("The"), "The");
+    }
+}
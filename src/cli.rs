@@ -0,0 +1,425 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+use color_eyre::eyre::eyre;
+
+use crate::{
+    calibre::Library,
+    cover::{CoverImage, CoverSource},
+    creator::{format_creators, parse_creators},
+    format::Template,
+    manifest::{BookManifest, FieldValue, Manifest, SeriesManifest},
+    model::{DEFAULT_FORMAT, DcField, InputField, Model, parse_position},
+    sidecar::sidecar_manifest,
+    storage::BackupMode,
+};
+
+/// Command line arguments for the app, starting the TUI when no command is given
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// The command to run instead of the TUI
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// A series manifest to fill in the TUI's pages from
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+    /// A Calibre library to list the books of by series and author, keeping its
+    /// metadata.db and metadata.opf files in step with the edited books
+    #[arg(long, value_name = "DIRECTORY")]
+    pub library: Option<PathBuf>,
+}
+
+/// Enum of the commands that run without the TUI
+#[derive(Subcommand)]
+pub enum Command {
+    /// Edit the metadata of a series of books without starting the TUI
+    Apply(ApplyArgs),
+    /// Propose the series of the books in a directory as a series manifest
+    Detect(DetectArgs),
+    /// Write the current metadata of books to OPF sidecars next to them
+    Export(ExportArgs),
+    /// Edit books with the metadata of the OPF sidecars next to them
+    Import(ImportArgs),
+}
+
+/// Arguments for the apply command
+#[derive(Args)]
+pub struct ApplyArgs {
+    /// The name of the series the books belong to
+    #[arg(long, required_unless_present = "manifest")]
+    series: Option<String>,
+    /// The format the new book titles follow, see the README for its placeholders
+    #[arg(long, default_value = DEFAULT_FORMAT)]
+    format: String,
+    /// The format of the paths the edited books are moved to, such as
+    /// '${author}/${series}/${position} - ${title}.epub'
+    #[arg(long)]
+    path_format: Option<String>,
+    /// The creators to give every book, written as 'Name [role] {file-as}; ...'
+    #[arg(long, conflicts_with = "manifest")]
+    authors: Option<String>,
+    /// Swap creator names written as 'Last, First' to 'First Last' and fill in their sort names
+    #[arg(long, conflicts_with = "manifest")]
+    normalize_authors: bool,
+    /// Set a Dublin Core field of every book, one of publisher, language, subjects, rights,
+    /// description or date, or the volume of comics, e.g. --field publisher=Gnome
+    #[arg(
+        long = "field",
+        value_name = "FIELD=VALUE",
+        conflicts_with = "manifest"
+    )]
+    fields: Vec<String>,
+    /// Replace the cover of every book with an image file, or with a generated cover showing
+    /// the series name and the book's position when given 'placeholder'
+    #[arg(long, value_name = "IMAGE", conflicts_with = "manifest")]
+    cover: Option<String>,
+    /// A series manifest listing the series and books to edit
+    #[arg(long, conflicts_with_all = ["series", "books"])]
+    manifest: Option<PathBuf>,
+    /// Print the changes that would be made without editing any book
+    #[arg(long)]
+    dry_run: bool,
+    /// Don't keep a backup of the books before editing them
+    #[arg(long, conflicts_with = "backup_dir")]
+    no_backup: bool,
+    /// Keep the backups in this directory instead of next to each book
+    #[arg(long)]
+    backup_dir: Option<PathBuf>,
+    /// The Calibre library the books belong to, whose metadata.db and metadata.opf files
+    /// are kept in step with the edited books
+    #[arg(long, value_name = "DIRECTORY")]
+    library: Option<PathBuf>,
+    /// The books to edit, in the order they come in the series
+    #[arg(required_unless_present = "manifest")]
+    books: Vec<PathBuf>,
+}
+
+/// Arguments for the detect command
+#[derive(Args)]
+pub struct DetectArgs {
+    /// The directory holding the books
+    #[arg(default_value = ".")]
+    directory: PathBuf,
+    /// Save the proposed manifest to this file instead of printing it
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+/// Arguments for the export command
+#[derive(Args)]
+pub struct ExportArgs {
+    /// The books to write the sidecars of
+    #[arg(required = true)]
+    books: Vec<PathBuf>,
+}
+
+/// Arguments for the import command
+#[derive(Args)]
+pub struct ImportArgs {
+    /// Print the changes that would be made without editing any book
+    #[arg(long)]
+    dry_run: bool,
+    /// Don't keep a backup of the books before editing them
+    #[arg(long, conflicts_with = "backup_dir")]
+    no_backup: bool,
+    /// Keep the backups in this directory instead of next to each book
+    #[arg(long)]
+    backup_dir: Option<PathBuf>,
+    /// The Calibre library the books belong to, whose metadata.db and metadata.opf files
+    /// are kept in step with the edited books
+    #[arg(long, value_name = "DIRECTORY")]
+    library: Option<PathBuf>,
+    /// The books to edit, each with a sidecar named after the book with .opf added
+    #[arg(required = true)]
+    books: Vec<PathBuf>,
+}
+
+/// Run the detect command, printing or saving the proposed series manifest
+pub fn detect(args: DetectArgs) -> color_eyre::Result<ExitCode> {
+    if !args.directory.is_dir() {
+        return Err(eyre!("{} is not a directory", args.directory.display()));
+    }
+
+    let detection = Model::new().detect_series(args.directory);
+    match args.output {
+        Some(path) => detection.manifest.save(&path)?,
+        None => print!("{}", toml::to_string_pretty(&detection.manifest)?),
+    }
+    eprintln!("{}", detection.summary());
+    for book in &detection.ungrouped {
+        eprintln!("Not in any series: {}", book.display());
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Run the export command, returning a failure exit code if any sidecar could not be
+/// written
+pub fn export(args: ExportArgs) -> color_eyre::Result<ExitCode> {
+    let model = Model::new();
+    let mut failed = false;
+    for book in &args.books {
+        match model.export_sidecar(book) {
+            Ok(sidecar) => println!("Exported {} to {}", book.display(), sidecar.display()),
+            Err(err) => {
+                eprintln!("Failed {}: {}", book.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Run the import command, editing every book from its sidecar and returning a failure
+/// exit code if any book could not be edited
+pub fn import(args: ImportArgs) -> color_eyre::Result<ExitCode> {
+    let mut model = Model::new();
+    model.backup_mode = backup_mode(args.no_backup, args.backup_dir);
+    if let Some(library) = &args.library {
+        model.library = Some(Library::open(library)?);
+    }
+    let manifest = sidecar_manifest(&args.books)?;
+
+    apply_manifest(model, manifest, args.dry_run)
+}
+
+/// Run the apply command for the given books or manifest, returning a failure exit code if any book could not be edited
+pub fn apply(args: ApplyArgs) -> color_eyre::Result<ExitCode> {
+    let mut model = Model::new();
+    model.backup_mode = backup_mode(args.no_backup, args.backup_dir);
+    if let Some(library) = &args.library {
+        model.library = Some(Library::open(library)?);
+    }
+
+    let authors = args
+        .authors
+        .as_deref()
+        .map(parse_creators)
+        .transpose()
+        .map_err(|err| eyre!("Invalid --authors: {}", err))?;
+    let mut fields = BTreeMap::new();
+    for field in &args.fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| eyre!("Invalid --field \"{}\", expected FIELD=VALUE", field))?;
+        let key = DcField::from_key(key).ok_or_else(|| {
+            eyre!(
+                "Unknown field \"{}\", expected one of: {}",
+                key,
+                DcField::VALUES.map(|field| field.key()).join(", ")
+            )
+        })?;
+        fields.insert(key, FieldValue::Text(value.to_string()));
+    }
+    let manifest = match args.manifest {
+        Some(path) => Manifest::load(&path)?,
+        None => Manifest {
+            series: vec![SeriesManifest {
+                name: args.series.unwrap_or_default(),
+                format: args.format,
+                path_format: args.path_format,
+                authors,
+                normalize_authors: args.normalize_authors,
+                fields,
+                cover: args.cover,
+                books: args
+                    .books
+                    .into_iter()
+                    .map(|path| BookManifest {
+                        path,
+                        title: None,
+                        position: None,
+                        authors: None,
+                        fields: BTreeMap::new(),
+                        cover: None,
+                    })
+                    .collect(),
+            }],
+        },
+    };
+
+    apply_manifest(model, manifest, args.dry_run)
+}
+
+/// Get the way books are backed up from the backup arguments
+fn backup_mode(no_backup: bool, backup_dir: Option<PathBuf>) -> BackupMode {
+    match (no_backup, backup_dir) {
+        (true, _) => BackupMode::Disabled,
+        (false, Some(directory)) => BackupMode::Directory(directory),
+        (false, None) => BackupMode::Sibling,
+    }
+}
+
+/// Edit every book of a manifest, or print the changes when it's a dry run, returning a
+/// failure exit code if any book could not be edited
+fn apply_manifest(
+    mut model: Model,
+    manifest: Manifest,
+    dry_run: bool,
+) -> color_eyre::Result<ExitCode> {
+    // Check every format string, field, position and cover before any book is touched
+    for series in &manifest.series {
+        for cover in series
+            .books
+            .iter()
+            .filter_map(|book| book.cover.as_ref())
+            .chain(&series.cover)
+        {
+            if let Some(CoverSource::Image(path)) = CoverSource::parse(cover) {
+                CoverImage::read(&path).map_err(|err| {
+                    eyre!("Invalid cover for series \"{}\": {}", series.name, err)
+                })?;
+            }
+        }
+        for (field, value) in series
+            .fields
+            .iter()
+            .chain(series.books.iter().flat_map(|book| &book.fields))
+        {
+            field
+                .validate(&value.to_input(*field))
+                .map_err(|err| eyre!("Invalid field for series \"{}\": {}", series.name, err))?;
+        }
+        for position in series
+            .books
+            .iter()
+            .filter_map(|book| book.position.as_deref())
+        {
+            parse_position(position)
+                .map_err(|err| eyre!("Invalid position for series \"{}\": {}", series.name, err))?;
+        }
+        Template::parse(&series.format)
+            .map_err(|err| eyre!("Invalid format for series \"{}\": {}", series.name, err))?;
+        if let Some(path_format) = &series.path_format {
+            Template::parse(path_format).map_err(|err| {
+                eyre!(
+                    "Invalid path format for series \"{}\": {}",
+                    series.name,
+                    err
+                )
+            })?;
+        }
+    }
+
+    let mut failed = false;
+    for series in manifest.series {
+        for (position, book) in series.books.iter().enumerate() {
+            let title = match &book.title {
+                Some(title) => title.to_owned(),
+                None => match model.get_book_title(&book.path) {
+                    Ok(title) => title,
+                    Err(err) => {
+                        eprintln!("Failed {}: {}", book.path.display(), err);
+                        failed = true;
+                        continue;
+                    }
+                },
+            };
+            let creators = match model.manifest_creators(&series, book, &book.path) {
+                Ok(creators) => creators,
+                Err(err) => {
+                    eprintln!("Failed {}: {}", book.path.display(), err);
+                    failed = true;
+                    continue;
+                }
+            };
+            model.all_selected.push(book.path.to_owned());
+            let mut inputs = HashMap::from([
+                (InputField::Format, series.format.clone()),
+                (InputField::BookOrder, position.to_string()),
+                (InputField::BookTitle, title),
+                (
+                    InputField::BookPosition,
+                    book.position
+                        .clone()
+                        .unwrap_or_else(|| (position + 1).to_string()),
+                ),
+                (InputField::Series, series.name.clone()),
+            ]);
+            if let Some(path_format) = &series.path_format {
+                inputs.insert(InputField::PathFormat, path_format.clone());
+            }
+            if let Some(creators) = creators {
+                inputs.insert(InputField::BookAuthors, format_creators(&creators));
+            }
+            for field in DcField::VALUES {
+                // A book's own value is used over the series' value
+                if let Some(value) = book
+                    .fields
+                    .get(&field)
+                    .or(series.fields.get(&field))
+                    .map(|value| value.to_input(field))
+                    .filter(|value| !value.is_empty())
+                {
+                    inputs.insert(InputField::Metadata(field), value);
+                }
+            }
+            if let Some(cover) = book
+                .cover
+                .as_ref()
+                .or(series.cover.as_ref())
+                .filter(|cover| !cover.is_empty())
+            {
+                inputs.insert(InputField::BookCover, cover.to_owned());
+            }
+            model.all_field_values.push(inputs);
+        }
+    }
+
+    if dry_run {
+        model.preview_all();
+        for (book, preview) in model.all_selected.iter().zip(&model.previews) {
+            println!("{}", book.display());
+            match preview {
+                Ok(changes) if changes.is_empty() => println!("  No changes"),
+                Ok(changes) => {
+                    for change in changes {
+                        println!(
+                            "  {}: {} -> {}",
+                            change.field,
+                            change.before.as_deref().unwrap_or("(none)"),
+                            change.after.as_deref().unwrap_or("(none)")
+                        );
+                    }
+                }
+                Err(err) => {
+                    println!("  Error: {}", err);
+                    failed = true;
+                }
+            }
+        }
+    } else {
+        for i in 0..model.all_selected.len() {
+            let book = model.all_selected[i].to_owned();
+            model.current_book = i;
+            match model.edit_epub(&book) {
+                Ok(()) if model.all_selected[i] != book => println!(
+                    "Edited {}, moved to {}",
+                    book.display(),
+                    model.all_selected[i].display()
+                ),
+                Ok(()) => println!("Edited {}", book.display()),
+                Err(err) => {
+                    eprintln!("Failed {}: {}", book.display(), err);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
@@ -0,0 +1,459 @@
+use std::{fmt, path::Path};
+
+use color_eyre::eyre::eyre;
+use quick_xml::escape::escape;
+
+use crate::{
+    opf::OpfDocument,
+    xml::{Element, XmlDocument},
+};
+
+/// The file extension of a comic book archive
+const COMIC_EXTENSION: &str = "cbz";
+/// The path of the ComicInfo file inside a comic book archive
+pub const COMIC_INFO_PATH: &str = "ComicInfo.xml";
+/// The meta carrying a comic's volume through its package document
+pub const VOLUME_META: &str = "comicinfo:volume";
+/// The meta carrying the number of books in a comic's series through its package document
+pub const COUNT_META: &str = "comicinfo:count";
+
+/// The ComicInfo file given to comics that don't have one
+///
+/// It starts with an empty title so that the elements added to it are indented.
+const EMPTY_COMIC_INFO: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">
+  <Title></Title>
+</ComicInfo>
+";
+/// The elements of a ComicInfo file in the order its schema gives them
+const ELEMENT_ORDER: [&str; 44] = [
+    "Title",
+    "Series",
+    "Number",
+    "Count",
+    "Volume",
+    "AlternateSeries",
+    "AlternateNumber",
+    "AlternateCount",
+    "Summary",
+    "Notes",
+    "Year",
+    "Month",
+    "Day",
+    "Writer",
+    "Penciller",
+    "Inker",
+    "Colorist",
+    "Letterer",
+    "CoverArtist",
+    "Editor",
+    "Translator",
+    "Publisher",
+    "Imprint",
+    "Genre",
+    "Tags",
+    "Web",
+    "PageCount",
+    "LanguageISO",
+    "Format",
+    "BlackAndWhite",
+    "Manga",
+    "Characters",
+    "Teams",
+    "Locations",
+    "ScanInformation",
+    "StoryArc",
+    "StoryArcNumber",
+    "SeriesGroup",
+    "AgeRating",
+    "Pages",
+    "CommunityRating",
+    "MainCharacterOrTeam",
+    "Review",
+    "GTIN",
+];
+/// The ComicInfo elements holding creators, along with the MARC relator code of their role
+const CREATOR_ELEMENTS: [(&str, &str); 6] = [
+    ("Writer", "aut"),
+    ("Penciller", "art"),
+    ("Colorist", "clr"),
+    ("CoverArtist", "cov"),
+    ("Editor", "edt"),
+    ("Translator", "trl"),
+];
+/// The ComicInfo elements holding a Dublin Core field, along with the field's element
+const FIELD_ELEMENTS: [(&str, &str); 3] = [
+    ("Publisher", "publisher"),
+    ("Summary", "description"),
+    ("LanguageISO", "language"),
+];
+
+/// Whether the path is a comic book archive (CBZ)
+pub fn is_comic_book(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(COMIC_EXTENSION))
+}
+
+/// Struct holding the `ComicInfo.xml` of a comic book archive
+#[derive(Clone, Debug)]
+pub struct ComicInfo {
+    /// The parsed ComicInfo file
+    document: XmlDocument,
+}
+
+impl ComicInfo {
+    /// Parse a ComicInfo file from a string
+    pub fn parse(source: &str) -> color_eyre::Result<Self> {
+        let document = XmlDocument::parse(source)
+            .map_err(|err| eyre!("Malformed {}: {}", COMIC_INFO_PATH, err))?;
+        if document.root().local_name() != "ComicInfo" {
+            return Err(eyre!(
+                "Malformed {}: expected a <ComicInfo> root element but found <{}>",
+                COMIC_INFO_PATH,
+                document.root().name
+            ));
+        }
+
+        Ok(ComicInfo { document })
+    }
+
+    /// Create an empty ComicInfo file for a comic that doesn't have one
+    pub fn new() -> Self {
+        ComicInfo::parse(EMPTY_COMIC_INFO).unwrap()
+    }
+
+    /// Get the metadata of the comic as an OPF package document, so that it can be edited
+    /// in the same way as an epub's
+    ///
+    /// The series and number become calibre's series metas, and the volume and count get
+    /// metas of their own. Comics without a title are given the fallback title.
+    pub fn metadata(&self, fallback_title: &str) -> color_eyre::Result<OpfDocument> {
+        let mut elements = Vec::new();
+        let title = self
+            .value("Title")
+            .unwrap_or_else(|| fallback_title.to_string());
+        elements.push(format!("<dc:title>{}</dc:title>", escape(&title)));
+        for (name, role) in CREATOR_ELEMENTS {
+            for creator in self.values(name, ',') {
+                elements.push(format!(
+                    "<dc:creator opf:role=\"{}\">{}</dc:creator>",
+                    role,
+                    escape(&creator)
+                ));
+            }
+        }
+        for (name, element) in FIELD_ELEMENTS {
+            if let Some(value) = self.value(name) {
+                elements.push(format!("<dc:{0}>{1}</dc:{0}>", element, escape(&value)));
+            }
+        }
+        for genre in self.values("Genre", ',') {
+            elements.push(format!("<dc:subject>{}</dc:subject>", escape(&genre)));
+        }
+        if let Some(year) = self.value("Year") {
+            let date: Vec<String> = [Some(year), self.value("Month"), self.value("Day")]
+                .into_iter()
+                .map_while(|part| part)
+                .map(|part| format!("{:0>2}", part))
+                .collect();
+            elements.push(format!("<dc:date>{}</dc:date>", escape(date.join("-"))));
+        }
+        for (meta, name) in [
+            ("calibre:series", "Series"),
+            ("calibre:series_index", "Number"),
+            (VOLUME_META, "Volume"),
+            (COUNT_META, "Count"),
+        ] {
+            if let Some(value) = self.value(name) {
+                elements.push(format!(
+                    "<meta name=\"{}\" content=\"{}\"/>",
+                    meta,
+                    escape(&value)
+                ));
+            }
+        }
+
+        OpfDocument::from_metadata(&elements)
+    }
+
+    /// Write the metadata of an OPF package document to the ComicInfo file
+    ///
+    /// Creators without a role are written as writers, and creators whose role has no
+    /// ComicInfo element aren't kept. Elements that have no place in the package document
+    /// are left as they are.
+    pub fn set_metadata(&mut self, metadata: &OpfDocument) {
+        self.set_value("Title", metadata.title());
+        let (series, number) = metadata
+            .series()
+            .map_or((None, None), |(series, number)| (Some(series), number));
+        self.set_value("Series", series);
+        self.set_value("Number", number);
+        self.set_value("Count", metadata.meta_content(COUNT_META));
+        self.set_value("Volume", metadata.meta_content(VOLUME_META));
+
+        let creators = metadata.creators();
+        for (name, role) in CREATOR_ELEMENTS {
+            let names: Vec<String> = creators
+                .iter()
+                .filter(|creator| match role {
+                    "aut" => creator.is_author(),
+                    _ => creator.role.as_deref() == Some(role),
+                })
+                .map(|creator| creator.name.to_owned())
+                .collect();
+            self.set_value(name, Some(names.join(", ")));
+        }
+        for (name, element) in FIELD_ELEMENTS {
+            self.set_value(name, metadata.dc_value(element));
+        }
+        self.set_value("Genre", Some(metadata.dc_values("subject").join(", ")));
+
+        let date = metadata.dc_value("date").unwrap_or_default();
+        let mut parts = date
+            .split('T')
+            .next()
+            .unwrap_or_default()
+            .split('-')
+            .map(|part| part.trim_start_matches('0').to_string());
+        for name in ["Year", "Month", "Day"] {
+            self.set_value(name, parts.next());
+        }
+    }
+
+    /// Get the text of an element, if it has any
+    fn value(&self, name: &str) -> Option<String> {
+        self.document
+            .root()
+            .child(name)
+            .map(|element| element.text().trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Get the values of an element holding a list separated by the given character
+    fn values(&self, name: &str, separator: char) -> Vec<String> {
+        self.value(name)
+            .map(|value| {
+                value
+                    .split(separator)
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Set the text of an element, adding it in the place the schema gives it, or remove the
+    /// element when there is no value
+    fn set_value(&mut self, name: &str, value: Option<String>) {
+        let root = self.document.root_mut();
+        let value = value.map(|value| value.trim().to_string());
+        match value.filter(|value| !value.is_empty()) {
+            Some(value) => match root.child_mut(name) {
+                Some(element) => {
+                    if element.text() != value {
+                        element.set_text(&value)
+                    }
+                }
+                None => {
+                    let later_elements = ELEMENT_ORDER
+                        .iter()
+                        .skip_while(|element| **element != name)
+                        .skip(1)
+                        .collect::<Vec<_>>();
+                    root.insert_child_before(Element::new(name).with_text(&value), |element| {
+                        later_elements.contains(&&element.local_name())
+                    });
+                }
+            },
+            None => root.remove_children(|element| element.local_name() == name),
+        }
+    }
+}
+
+impl fmt::Display for ComicInfo {
+    /// Serialize the ComicInfo file, reproducing the source for everything left unedited
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::creator::Creator;
+
+    /// A ComicInfo file with an element the package document has no place for
+    const COMIC_INFO: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">
+  <Title>Saga</Title>
+  <Series>Saga</Series>
+  <Number>1</Number>
+  <Year>2012</Year>
+  <Month>3</Month>
+  <Writer>Brian K. Vaughan</Writer>
+  <Penciller>Fiona Staples</Penciller>
+  <Genre>Science Fiction, Fantasy</Genre>
+  <PageCount>2</PageCount>
+  <Pages>
+    <Page Image=\"0\" Type=\"FrontCover\" />
+  </Pages>
+</ComicInfo>
+";
+
+    /// Get the names of the elements of a ComicInfo file in the order they come in
+    fn element_names(comic_info: &ComicInfo) -> Vec<String> {
+        comic_info
+            .document
+            .root()
+            .elements()
+            .map(|element| element.local_name().to_string())
+            .collect()
+    }
+
+    /// Whether the elements come in the order the schema gives them
+    fn in_schema_order(names: &[String]) -> bool {
+        let positions: Vec<usize> = names
+            .iter()
+            .map(|name| {
+                ELEMENT_ORDER
+                    .iter()
+                    .position(|element| element == name)
+                    .unwrap()
+            })
+            .collect();
+
+        positions.is_sorted()
+    }
+
+    #[test]
+    fn metadata_round_trips_through_comic_info() {
+        let mut comic_info = ComicInfo::parse(COMIC_INFO).unwrap();
+        let mut metadata = comic_info.metadata("fallback").unwrap();
+        assert_eq!(metadata.title().as_deref(), Some("Saga"));
+        assert_eq!(
+            metadata.dc_values("subject"),
+            ["Science Fiction", "Fantasy"]
+        );
+        assert_eq!(metadata.dc_value("date").as_deref(), Some("2012-03"));
+
+        metadata.set_title("Saga, Volume Two");
+        metadata.set_series("Saga", "2");
+        metadata.set_meta_content(VOLUME_META, "2");
+        metadata.set_meta_content(COUNT_META, "9");
+        metadata.set_creators(&[
+            Creator {
+                name: String::from("Brian K. Vaughan"),
+                ..Creator::default()
+            },
+            Creator {
+                name: String::from("Fiona Staples"),
+                role: Some(String::from("cov")),
+                file_as: None,
+            },
+        ]);
+        metadata.set_dc_values("publisher", &[String::from("Image Comics")]);
+        metadata.set_dc_values("language", &[String::from("en")]);
+        metadata.set_dc_values("date", &[String::from("2013-06-05")]);
+        comic_info.set_metadata(&metadata);
+
+        let edited = ComicInfo::parse(&comic_info.to_string()).unwrap();
+        let reread = edited.metadata("fallback").unwrap();
+        assert_eq!(reread.title().as_deref(), Some("Saga, Volume Two"));
+        assert_eq!(
+            reread.series(),
+            Some((String::from("Saga"), Some(String::from("2"))))
+        );
+        assert_eq!(reread.meta_content(VOLUME_META).as_deref(), Some("2"));
+        assert_eq!(reread.meta_content(COUNT_META).as_deref(), Some("9"));
+        let creators: Vec<(String, Option<String>)> = reread
+            .creators()
+            .into_iter()
+            .map(|creator| (creator.name, creator.role))
+            .collect();
+        assert_eq!(
+            creators,
+            [
+                (String::from("Brian K. Vaughan"), Some(String::from("aut"))),
+                (String::from("Fiona Staples"), Some(String::from("cov"))),
+            ]
+        );
+        assert_eq!(
+            reread.dc_value("publisher").as_deref(),
+            Some("Image Comics")
+        );
+        assert_eq!(reread.dc_value("language").as_deref(), Some("en"));
+        assert_eq!(reread.dc_value("date").as_deref(), Some("2013-06-05"));
+        assert_eq!(edited.value("Penciller"), None);
+        assert_eq!(edited.value("PageCount").as_deref(), Some("2"));
+        assert!(edited.document.root().child("Pages").is_some());
+    }
+
+    #[test]
+    fn added_elements_follow_the_schema_order() {
+        let mut comic_info = ComicInfo::parse(COMIC_INFO).unwrap();
+        let mut metadata = comic_info.metadata("fallback").unwrap();
+        metadata.set_meta_content(VOLUME_META, "1");
+        metadata.set_meta_content(COUNT_META, "6");
+        metadata.set_dc_values("publisher", &[String::from("Image Comics")]);
+        metadata.set_dc_values("description", &[String::from("Star-crossed lovers")]);
+        metadata.set_dc_values("language", &[String::from("en")]);
+        metadata.set_dc_values("date", &[String::from("2012-03-14")]);
+        comic_info.set_metadata(&metadata);
+
+        let names = element_names(&ComicInfo::parse(&comic_info.to_string()).unwrap());
+        assert!(in_schema_order(&names), "{:?}", names);
+        for name in [
+            "Count",
+            "Volume",
+            "Summary",
+            "Day",
+            "Publisher",
+            "LanguageISO",
+        ] {
+            assert!(names.iter().any(|element| element == name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn new_comic_info_is_filled_in_schema_order() {
+        let mut comic_info = ComicInfo::new();
+        let metadata = ComicInfo::parse(COMIC_INFO)
+            .unwrap()
+            .metadata("fallback")
+            .unwrap();
+        comic_info.set_metadata(&metadata);
+
+        let names = element_names(&comic_info);
+        assert!(in_schema_order(&names), "{:?}", names);
+        assert_eq!(
+            names,
+            [
+                "Title",
+                "Series",
+                "Number",
+                "Year",
+                "Month",
+                "Writer",
+                "Penciller",
+                "Genre"
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_metadata_leaves_the_file_as_it_is() {
+        let mut comic_info = ComicInfo::parse(COMIC_INFO).unwrap();
+        let metadata = comic_info.metadata("fallback").unwrap();
+        comic_info.set_metadata(&metadata);
+
+        assert_eq!(comic_info.to_string(), COMIC_INFO);
+    }
+
+    #[test]
+    fn comics_without_a_title_use_the_fallback() {
+        let metadata = ComicInfo::new().metadata("Saga 01").unwrap();
+
+        assert_eq!(metadata.title().as_deref(), Some("Saga 01"));
+    }
+}
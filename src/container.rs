@@ -0,0 +1,173 @@
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+
+use crate::xml::XmlDocument;
+
+/// The path of the container file inside an epub
+pub const CONTAINER_PATH: &str = "META-INF/container.xml";
+/// The media type of an OPF package document
+const PACKAGE_MEDIA_TYPE: &str = "application/oebps-package+xml";
+
+/// Struct holding a rootfile entry of the container
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rootfile {
+    /// The path of the rootfile relative to the root of the epub
+    pub full_path: String,
+    /// The media type of the rootfile
+    pub media_type: String,
+}
+
+/// Struct holding the parsed `META-INF/container.xml` of an epub
+#[derive(Clone, Debug)]
+pub struct Container {
+    /// The rootfiles listed in the container, one per rendition of the book
+    pub rootfiles: Vec<Rootfile>,
+}
+
+impl Container {
+    /// Parse a container file from a string
+    pub fn parse(source: &str) -> color_eyre::Result<Self> {
+        let document = XmlDocument::parse(source)
+            .map_err(|err| eyre!("Malformed {}: {}", CONTAINER_PATH, err))?;
+        let root = document.root();
+        if root.local_name() != "container" {
+            return Err(eyre!(
+                "Malformed {}: expected a <container> root element but found <{}>",
+                CONTAINER_PATH,
+                root.name
+            ));
+        }
+
+        let mut rootfiles = Vec::new();
+        for rootfile in root
+            .elements()
+            .filter(|element| element.local_name() == "rootfiles")
+            .flat_map(|element| element.elements())
+            .filter(|element| element.local_name() == "rootfile")
+        {
+            let full_path = rootfile
+                .attribute("full-path")
+                .ok_or_else(|| eyre!("Malformed {}: rootfile has no full-path", CONTAINER_PATH))?;
+            rootfiles.push(Rootfile {
+                full_path: full_path.to_string(),
+                media_type: rootfile
+                    .attribute("media-type")
+                    .unwrap_or(PACKAGE_MEDIA_TYPE)
+                    .to_string(),
+            });
+        }
+
+        Ok(Container { rootfiles })
+    }
+
+    /// Get the paths of every OPF package document, the default rendition comes first
+    pub fn package_paths(&self) -> color_eyre::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for rootfile in &self.rootfiles {
+            if rootfile.media_type != PACKAGE_MEDIA_TYPE {
+                continue;
+            }
+            let path = PathBuf::from(&rootfile.full_path);
+            if !is_relative_inside(&path) {
+                return Err(eyre!(
+                    "Malformed {}: rootfile path {} points outside the epub",
+                    CONTAINER_PATH,
+                    rootfile.full_path
+                ));
+            }
+            paths.push(path);
+        }
+
+        if paths.is_empty() {
+            return Err(eyre!(
+                "Malformed {}: no OPF package rootfile listed",
+                CONTAINER_PATH
+            ));
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Whether a path is relative and stays inside the directory it is relative to
+fn is_relative_inside(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a container listing rootfiles with the given paths and media types
+    fn container(rootfiles: &[(&str, &str)]) -> String {
+        let rootfiles: Vec<String> = rootfiles
+            .iter()
+            .map(|(path, media_type)| {
+                format!(
+                    "<rootfile full-path=\"{}\" media-type=\"{}\"/>",
+                    path, media_type
+                )
+            })
+            .collect();
+
+        format!(
+            "<?xml version=\"1.0\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+  <rootfiles>{}</rootfiles>
+</container>",
+            rootfiles.concat()
+        )
+    }
+
+    #[test]
+    fn package_paths_keep_the_order_of_the_renditions() {
+        let container = Container::parse(&container(&[
+            ("OEBPS/content.opf", PACKAGE_MEDIA_TYPE),
+            ("OEBPS/book.pdf", "application/pdf"),
+            ("./fixed/content.opf", PACKAGE_MEDIA_TYPE),
+        ]))
+        .unwrap();
+
+        assert_eq!(container.rootfiles.len(), 3);
+        assert_eq!(
+            container.package_paths().unwrap(),
+            [
+                PathBuf::from("OEBPS/content.opf"),
+                PathBuf::from("./fixed/content.opf")
+            ]
+        );
+    }
+
+    #[test]
+    fn rootfiles_without_a_media_type_are_package_documents() {
+        let container = Container::parse(
+            "<container><rootfiles><rootfile full-path=\"content.opf\"/></rootfiles></container>",
+        )
+        .unwrap();
+
+        assert_eq!(
+            container.package_paths().unwrap(),
+            [PathBuf::from("content.opf")]
+        );
+    }
+
+    #[test]
+    fn rootfiles_pointing_outside_the_epub_are_rejected() {
+        for path in ["../content.opf", "OEBPS/../../content.opf", "/content.opf"] {
+            let container = Container::parse(&container(&[(path, PACKAGE_MEDIA_TYPE)])).unwrap();
+            assert!(container.package_paths().is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn containers_without_a_package_are_rejected() {
+        let container = Container::parse(&container(&[("book.pdf", "application/pdf")])).unwrap();
+        assert!(container.package_paths().is_err());
+        assert!(
+            Container::parse("<container><rootfiles><rootfile/></rootfiles></container>").is_err()
+        );
+        assert!(Container::parse("<package/>").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    fs::read,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+/// The value of a book's cover input that gives it a generated placeholder cover
+pub const PLACEHOLDER_COVER: &str = "placeholder";
+
+/// The size of generated placeholder covers in pixels
+const PLACEHOLDER_SIZE: (u32, u32) = (600, 900);
+/// The background colours of placeholder covers, picked from by the series name
+const PLACEHOLDER_COLORS: [[u8; 3]; 6] = [
+    [38, 70, 83],
+    [42, 87, 61],
+    [94, 48, 35],
+    [68, 45, 96],
+    [36, 59, 99],
+    [97, 75, 30],
+];
+
+/// Enum of the places a book's new cover can come from
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoverSource {
+    /// An image file
+    Image(PathBuf),
+    /// A generated cover showing the series name and the book's position
+    Placeholder,
+}
+
+impl CoverSource {
+    /// Parse the value of a book's cover input, an empty value keeps the current cover
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" => None,
+            PLACEHOLDER_COVER => Some(CoverSource::Placeholder),
+            path => Some(CoverSource::Image(PathBuf::from(path))),
+        }
+    }
+
+    /// Load the cover, rendering a placeholder for the given series and position
+    pub fn load(&self, series: &str, position: &str) -> color_eyre::Result<CoverImage> {
+        match self {
+            CoverSource::Image(path) => CoverImage::read(path),
+            CoverSource::Placeholder => CoverImage::placeholder(series, position),
+        }
+    }
+}
+
+/// Struct holding an encoded cover image
+#[derive(Clone, Debug)]
+pub struct CoverImage {
+    /// The encoded image
+    pub data: Vec<u8>,
+    /// The format the image is encoded in
+    pub format: ImageFormat,
+}
+
+impl CoverImage {
+    /// Read a cover from an image file, which has to be a JPEG, PNG or GIF
+    pub fn read(path: &Path) -> color_eyre::Result<Self> {
+        let data =
+            read(path).map_err(|err| eyre!("Unable to read cover {}: {}", path.display(), err))?;
+        let format = image::guess_format(&data)
+            .ok()
+            .filter(|format| media_type(*format).is_some())
+            .ok_or_else(|| {
+                eyre!(
+                    "Unsupported cover {}, expected a JPEG, PNG or GIF image",
+                    path.display()
+                )
+            })?;
+        image::load_from_memory_with_format(&data, format)
+            .map_err(|err| eyre!("Invalid cover {}: {}", path.display(), err))?;
+
+        Ok(CoverImage { data, format })
+    }
+
+    /// Render a placeholder cover showing the series name and the book's position
+    pub fn placeholder(series: &str, position: &str) -> color_eyre::Result<Self> {
+        let (width, height) = PLACEHOLDER_SIZE;
+        let seed = series.bytes().fold(0usize, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as usize)
+        });
+        let background = Rgb(PLACEHOLDER_COLORS[seed % PLACEHOLDER_COLORS.len()]);
+        let foreground = Rgb([235, 230, 215]);
+        let mut image = RgbImage::from_pixel(width, height, background);
+
+        // A thin frame inset from the edges
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let inset = [x, y, width - 1 - x, height - 1 - y];
+            if inset.iter().any(|&distance| (28..32).contains(&distance))
+                && inset.iter().all(|&distance| distance >= 28)
+            {
+                *pixel = foreground;
+            }
+        }
+
+        let series_scale = 8;
+        let max_chars = ((width - 120) / (6 * series_scale)) as usize;
+        let series_lines = wrap_text(series, max_chars);
+        let line_height = 10 * series_scale;
+        let mut y = 160;
+        for line in series_lines.iter().take(4) {
+            draw_text(&mut image, line, y, series_scale, foreground);
+            y += line_height;
+        }
+        draw_text(&mut image, "BOOK", 560, 6, foreground);
+        draw_text(&mut image, position, 630, 20, foreground);
+
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(image).write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+
+        Ok(CoverImage {
+            data,
+            format: ImageFormat::Png,
+        })
+    }
+
+    /// Get the media type of the image
+    pub fn media_type(&self) -> &'static str {
+        media_type(self.format).unwrap_or("image/png")
+    }
+
+    /// Get the file extension of the image
+    pub fn extension(&self) -> &'static str {
+        self.format.extensions_str()[0]
+    }
+
+    /// Decode the image
+    pub fn decode(&self) -> color_eyre::Result<DynamicImage> {
+        Ok(image::load_from_memory_with_format(
+            &self.data,
+            self.format,
+        )?)
+    }
+
+    /// Get the image encoded in the format of the given media type, re-encoding it if the
+    /// formats differ, or nothing if images of that media type can't be written
+    pub fn encoded_as(&self, media_type: &str) -> color_eyre::Result<Option<Vec<u8>>> {
+        let Some(format) = ImageFormat::from_mime_type(media_type)
+            .filter(|format| self::media_type(*format).is_some())
+        else {
+            return Ok(None);
+        };
+        if format == self.format {
+            return Ok(Some(self.data.to_owned()));
+        }
+
+        let mut image = self.decode()?;
+        if format == ImageFormat::Jpeg {
+            // JPEG has no alpha channel
+            image = DynamicImage::ImageRgb8(image.to_rgb8());
+        }
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format)?;
+
+        Ok(Some(data))
+    }
+}
+
+/// Get the contents of an XHTML page showing the cover image at the given href
+pub fn cover_page(image_href: &str, is_epub3: bool) -> String {
+    let doctype = if is_epub3 {
+        "<!DOCTYPE html>"
+    } else {
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.1//EN\" \"http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd\">"
+    };
+    let image_href = image_href
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;");
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+{}
+<html xmlns=\"http://www.w3.org/1999/xhtml\">
+  <head>
+    <title>Cover</title>
+    <style type=\"text/css\">
+      body {{ margin: 0; padding: 0; text-align: center; }}
+      img {{ max-width: 100%; max-height: 100%; }}
+    </style>
+  </head>
+  <body>
+    <div><img src=\"{}\" alt=\"Cover\"/></div>
+  </body>
+</html>
+",
+        doctype, image_href
+    )
+}
+
+/// Get the media type of the image formats covers can be written in
+fn media_type(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Gif => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// Split text into lines of at most the given number of characters, breaking between words
+/// where possible
+fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > max_chars {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..max_chars).collect());
+        }
+        let word: String = word.into_iter().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Draw a line of text centred horizontally with its top at the given height, each pixel of
+/// the font being drawn as a square of the given scale
+fn draw_text(image: &mut RgbImage, text: &str, top: u32, scale: u32, color: Rgb<u8>) {
+    let glyphs: Vec<[u8; 7]> = text.chars().map(glyph).collect();
+    let advance = 6 * scale;
+    let text_width = (glyphs.len() as u32 * advance).saturating_sub(scale);
+    let left = image.width().saturating_sub(text_width) / 2;
+    for (i, rows) in glyphs.iter().enumerate() {
+        let glyph_left = left + i as u32 * advance;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0b10000 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = glyph_left + column * scale + dx;
+                        let y = top + row as u32 * scale + dy;
+                        if x < image.width() && y < image.height() {
+                            image.put_pixel(x, y, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Get the rows of the 5x7 glyph of a character, letters are drawn in upper case and
+/// characters the font doesn't have are drawn as `?`
+fn glyph(character: char) -> [u8; 7] {
+    let character = match character.to_uppercase().next().unwrap_or(character) {
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => 'A',
+        'Ç' => 'C',
+        'È' | 'É' | 'Ê' | 'Ë' => 'E',
+        'Ì' | 'Í' | 'Î' | 'Ï' => 'I',
+        'Ñ' => 'N',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' => 'O',
+        'Ù' | 'Ú' | 'Û' | 'Ü' => 'U',
+        'Ý' | 'Ÿ' => 'Y',
+        character => character,
+    };
+
+    match character {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '\'' | '’' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::{self, Display};
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+/// Struct holding a creator of a book, such as an author or translator
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Creator {
+    /// The name of the creator as it is displayed
+    pub name: String,
+    /// The MARC relator code of the creator's role (e.g. `aut` or `trl`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// The name the creator is sorted by (e.g. `Asimov, Isaac`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_as: Option<String>,
+}
+
+impl Creator {
+    /// Whether the creator is an author, creators without a role are taken to be authors
+    pub fn is_author(&self) -> bool {
+        self.role.as_deref().is_none_or(|role| role == "aut")
+    }
+
+    /// Normalise the creator's name
+    ///
+    /// Names written as `Last, First` are swapped to `First Last`, and the sort name is
+    /// filled in as `Last, First` when the creator doesn't have one.
+    pub fn normalized(&self) -> Self {
+        let name = self.name.trim();
+        let (name, file_as) = match name.split_once(',') {
+            Some((last, first)) if !first.contains(',') && !first.trim().is_empty() => (
+                format!("{} {}", first.trim(), last.trim()),
+                format!("{}, {}", last.trim(), first.trim()),
+            ),
+            _ => (name.to_string(), sort_name(name)),
+        };
+
+        Creator {
+            name,
+            role: self.role.clone(),
+            file_as: self.file_as.clone().or(Some(file_as)),
+        }
+    }
+}
+
+impl Display for Creator {
+    /// Write the creator in its text form, `Name [role] {file-as}`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(role) = &self.role {
+            write!(f, " [{}]", role)?;
+        }
+        if let Some(file_as) = &self.file_as {
+            write!(f, " {{{}}}", file_as)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the text form of a list of creators
+///
+/// Creators are separated by `;` and written as `Name [role] {file-as}`, where the role
+/// and sort name are optional, e.g. `Isaac Asimov {Asimov, Isaac}; Jane Doe [trl]`.
+pub fn parse_creators(text: &str) -> color_eyre::Result<Vec<Creator>> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(parse_creator)
+        .collect()
+}
+
+/// Write a list of creators in their text form
+pub fn format_creators(creators: &[Creator]) -> String {
+    creators
+        .iter()
+        .map(Creator::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Get the first creator that is an author, or the first creator if none are
+pub fn first_author(creators: &[Creator]) -> Option<&Creator> {
+    creators
+        .iter()
+        .find(|creator| creator.is_author())
+        .or(creators.first())
+}
+
+/// Parse a single creator written as `Name [role] {file-as}`
+fn parse_creator(entry: &str) -> color_eyre::Result<Creator> {
+    let mut rest = entry;
+    let mut file_as = None;
+    if let Some(stripped) = rest.strip_suffix('}') {
+        let (before, value) = stripped
+            .rsplit_once('{')
+            .ok_or_else(|| eyre!("Unopened {{ in creator \"{}\"", entry))?;
+        file_as = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        rest = before.trim_end();
+    }
+    let mut role = None;
+    if let Some(stripped) = rest.strip_suffix(']') {
+        let (before, value) = stripped
+            .rsplit_once('[')
+            .ok_or_else(|| eyre!("Unopened [ in creator \"{}\"", entry))?;
+        let value = value.trim();
+        if value.len() != 3 || !value.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(eyre!(
+                "Invalid role \"{}\" in creator \"{}\", expected a three letter relator code such as aut or trl",
+                value,
+                entry
+            ));
+        }
+        role = Some(value.to_string());
+        rest = before.trim_end();
+    }
+    if rest.contains(['[', ']', '{', '}']) {
+        return Err(eyre!(
+            "Invalid creator \"{}\", expected Name [role] {{file-as}}",
+            entry
+        ));
+    }
+    if rest.is_empty() {
+        return Err(eyre!("Creator \"{}\" has no name", entry));
+    }
+
+    Ok(Creator {
+        name: rest.to_string(),
+        role,
+        file_as,
+    })
+}
+
+/// Work out the sort name of a name written as `First Last`
+fn sort_name(name: &str) -> String {
+    match name.rsplit_once(' ') {
+        Some((first, last)) => format!("{}, {}", last, first.trim()),
+        None => name.to_string(),
+    }
+}
@@ -0,0 +1,480 @@
+use std::{cmp::Ordering, collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    manifest::{BookManifest, Manifest, SeriesManifest},
+    model::{DEFAULT_FORMAT, file_stem},
+};
+
+/// Words that don't make a shared title prefix a series name on their own
+const STOPWORDS: [&str; 8] = ["a", "an", "and", "of", "the", "in", "to", "on"];
+/// Words that can come between a series name and the book's number in a filename
+const NUMBER_WORDS: [&str; 5] = ["book", "vol", "vol.", "volume", "part"];
+/// Numbers written as words, starting from one
+const CARDINALS: [&str; 12] = [
+    "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven",
+    "twelve",
+];
+/// Ordinals written as words, starting from first
+const ORDINALS: [&str; 12] = [
+    "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth", "tenth",
+    "eleventh", "twelfth",
+];
+
+/// Struct holding what is known about a book before it is sorted into a series
+#[derive(Clone, Debug, Default)]
+pub struct BookInfo {
+    /// The path of the book
+    pub path: PathBuf,
+    /// The current title of the book
+    pub title: Option<String>,
+    /// The series in the book's metadata
+    pub series: Option<String>,
+    /// The position in the series in the book's metadata
+    pub position: Option<String>,
+    /// The publication date in the book's metadata
+    pub date: Option<String>,
+}
+
+/// Struct holding the series proposed by a detection pass
+#[derive(Clone, Debug, Default)]
+pub struct Detection {
+    /// The proposed series along with their books in order
+    pub manifest: Manifest,
+    /// The books that could not be put in any series
+    pub ungrouped: Vec<PathBuf>,
+}
+
+impl Detection {
+    /// Describe the proposed series in a single line
+    pub fn summary(&self) -> String {
+        if self.manifest.series.is_empty() {
+            return String::from("No series detected");
+        }
+
+        let series: Vec<String> = self
+            .manifest
+            .series
+            .iter()
+            .map(|series| format!("{} ({} books)", series.name, series.books.len()))
+            .collect();
+        let mut summary = format!(
+            "Detected {} series: {}",
+            self.manifest.series.len(),
+            series.join(", ")
+        );
+        if !self.ungrouped.is_empty() {
+            summary.push_str(&format!(
+                "; {} books not in any series",
+                self.ungrouped.len()
+            ));
+        }
+
+        summary
+    }
+}
+
+/// Struct holding the order proposed for the books of a series
+#[derive(Clone, Debug, Default)]
+pub struct ProposedOrder {
+    /// The indices of the books in their proposed order
+    pub order: Vec<usize>,
+    /// The indices of the books whose position could not be worked out for certain
+    pub ambiguous: Vec<usize>,
+}
+
+/// Struct holding a book while it is sorted into a series
+struct Candidate {
+    /// The information read from the book
+    book: BookInfo,
+    /// The book's position in the series, if known
+    position: Option<f64>,
+    /// The title to use instead of the book's current title
+    title: Option<String>,
+}
+
+/// Propose series for the given books
+///
+/// Books are grouped by the series in their metadata first, then by filenames such as
+/// `Series 03 - Title.epub` or `Series - 03 - Title.epub`, and finally by titles sharing
+/// a common prefix. Groups with the same name (ignoring case) are merged.
+pub fn detect_series(books: Vec<BookInfo>) -> Detection {
+    let mut groups: BTreeMap<String, (String, Vec<Candidate>)> = BTreeMap::new();
+    let mut add = |name: &str, candidate: Candidate| {
+        groups
+            .entry(name.trim().to_lowercase())
+            .or_insert_with(|| (name.trim().to_string(), Vec::new()))
+            .1
+            .push(candidate);
+    };
+
+    let mut remaining = Vec::new();
+    for book in books {
+        if let Some(series) = book.series.clone()
+            && !series.trim().is_empty()
+        {
+            let position = book.position.as_deref().and_then(parse_number);
+            add(
+                &series,
+                Candidate {
+                    book,
+                    position,
+                    title: None,
+                },
+            );
+        } else if let Some((series, position, title)) = parse_filename(&file_stem(&book.path)) {
+            let title = book.title.is_none().then_some(title);
+            add(
+                &series,
+                Candidate {
+                    book,
+                    position: Some(position),
+                    title,
+                },
+            );
+        } else {
+            remaining.push(book);
+        }
+    }
+
+    let mut ungrouped = Vec::new();
+    for (series, members) in group_by_title_prefix(remaining) {
+        match series {
+            Some(series) => {
+                for book in members {
+                    let position = book.title.as_deref().and_then(text_number);
+                    add(
+                        &series,
+                        Candidate {
+                            book,
+                            position,
+                            title: None,
+                        },
+                    );
+                }
+            }
+            None => ungrouped.extend(members.into_iter().map(|book| book.path)),
+        }
+    }
+
+    let mut manifest = Manifest::default();
+    for (_, (name, mut candidates)) in groups {
+        candidates.sort_by(|a, b| match (a.position, b.position) {
+            (Some(a_position), Some(b_position)) => a_position
+                .partial_cmp(&b_position)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.book.path.cmp(&b.book.path)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.book.path.cmp(&b.book.path),
+        });
+        manifest.series.push(SeriesManifest {
+            name,
+            format: DEFAULT_FORMAT.to_string(),
+            path_format: None,
+            authors: None,
+            normalize_authors: false,
+            fields: BTreeMap::new(),
+            cover: None,
+            books: candidates
+                .into_iter()
+                .enumerate()
+                .map(|(row, candidate)| BookManifest {
+                    path: candidate.book.path,
+                    title: candidate.title,
+                    // Only keep positions that differ from the book's place in the list
+                    position: candidate
+                        .position
+                        .filter(|&position| position != (row + 1) as f64)
+                        .map(|position| position.to_string()),
+                    authors: None,
+                    fields: BTreeMap::new(),
+                    cover: None,
+                })
+                .collect(),
+        });
+    }
+    ungrouped.sort();
+
+    Detection {
+        manifest,
+        ungrouped,
+    }
+}
+
+/// Get the series, position and title from a filename such as `Series 03 - Title` or
+/// `Series - 03 - Title`
+pub fn parse_filename(stem: &str) -> Option<(String, f64, String)> {
+    let stem = stem.replace('_', " ");
+    let parts: Vec<&str> = stem.split(" - ").map(str::trim).collect();
+    if parts.len() < 2 {
+        return None;
+    }
+
+    if parts.len() >= 3
+        && let Some(position) = parse_number(parts[1])
+        && !parts[0].is_empty()
+    {
+        return Some((parts[0].to_string(), position, parts[2..].join(" - ")));
+    }
+
+    let mut words: Vec<&str> = parts[0].split_whitespace().collect();
+    let position = parse_number(words.pop()?)?;
+    if words
+        .last()
+        .is_some_and(|word| NUMBER_WORDS.contains(&word.to_lowercase().as_str()))
+    {
+        words.pop();
+    }
+    let series = words.join(" ");
+    let series = series.trim_end_matches([',', ':', '-']).trim();
+    if series.is_empty() {
+        return None;
+    }
+
+    Some((series.to_string(), position, parts[1..].join(" - ")))
+}
+
+/// Parse a series position such as `3`, `03`, `#3` or `2.5`
+pub fn parse_number(value: &str) -> Option<f64> {
+    let value = value.trim().trim_start_matches('#');
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+
+    value.parse().ok()
+}
+
+/// Propose the order of the books of a series
+///
+/// The books are sorted by the first of these that is known for every book, or else for
+/// the most books: the series index in their metadata, the number in their filename, the
+/// number in their title and their publication date. A book is ambiguous when it has no
+/// value for the chosen key or shares its value with another book.
+pub fn order_books(books: &[BookInfo]) -> ProposedOrder {
+    let keys: [Vec<Option<f64>>; 4] = [
+        books
+            .iter()
+            .map(|book| book.position.as_deref().and_then(parse_number))
+            .collect(),
+        books.iter().map(filename_number).collect(),
+        books
+            .iter()
+            .map(|book| book.title.as_deref().and_then(text_number))
+            .collect(),
+        books
+            .iter()
+            .map(|book| book.date.as_deref().and_then(parse_date))
+            .collect(),
+    ];
+    // Earlier keys win ties since max_by_key keeps the last maximum
+    let key = keys
+        .iter()
+        .rev()
+        .max_by_key(|key| key.iter().filter(|value| value.is_some()).count())
+        .unwrap();
+
+    let mut order: Vec<usize> = (0..books.len()).collect();
+    order.sort_by(|&a, &b| match (key[a], key[b]) {
+        (Some(a_value), Some(b_value)) => a_value.partial_cmp(&b_value).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    let ambiguous = (0..books.len())
+        .filter(|&i| match key[i] {
+            Some(value) => key
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && *other == Some(value)),
+            None => true,
+        })
+        .collect();
+
+    ProposedOrder { order, ambiguous }
+}
+
+/// Get the number of a book from its filename, such as `Series 03 - Title` or
+/// `03 - Title`
+fn filename_number(book: &BookInfo) -> Option<f64> {
+    let stem = file_stem(&book.path).replace('_', " ");
+    if let Some((_, position, _)) = parse_filename(&stem) {
+        return Some(position);
+    }
+    if let Some(position) = stem.split_whitespace().next().and_then(parse_number) {
+        return Some(position);
+    }
+
+    text_number(&stem)
+}
+
+/// Get the number of a book from text such as `Dune 2`, `Book Two`, `Part IV` or
+/// `The Second Book`
+fn text_number(text: &str) -> Option<f64> {
+    let words: Vec<String> = text.split_whitespace().map(normalise_word).collect();
+    for (i, word) in words.iter().enumerate() {
+        if NUMBER_WORDS.contains(&word.as_str())
+            && let Some(next) = words.get(i + 1)
+            && let Some(number) = parse_number(next)
+                .or_else(|| word_number(next))
+                .or_else(|| roman_number(next))
+        {
+            return Some(number);
+        }
+    }
+    for (i, word) in words.iter().enumerate() {
+        if words
+            .get(i + 1)
+            .is_some_and(|next| NUMBER_WORDS.contains(&next.as_str()))
+            && let Some(position) = ORDINALS.iter().position(|ordinal| ordinal == word)
+        {
+            return Some((position + 1) as f64);
+        }
+    }
+
+    let last = words.last()?;
+    parse_number(last).or_else(|| {
+        if words.len() > 1 {
+            roman_number(last)
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse a number written as a word such as `two` or `second`
+fn word_number(word: &str) -> Option<f64> {
+    CARDINALS
+        .iter()
+        .position(|cardinal| *cardinal == word)
+        .or_else(|| ORDINALS.iter().position(|ordinal| *ordinal == word))
+        .map(|i| (i + 1) as f64)
+}
+
+/// Parse a Roman numeral such as `iv` or `XII`, up to 399
+///
+/// Only numerals written the standard way are accepted so words such as `civil` aren't
+/// mistaken for numbers.
+fn roman_number(word: &str) -> Option<f64> {
+    const NUMERALS: [(u32, &str); 9] = [
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let word = word.to_lowercase();
+    let mut rest = word.as_str();
+    let mut total = 0;
+    for (value, numeral) in NUMERALS {
+        // Only c, x and i can repeat, up to three times
+        let repeats = if numeral.len() == 1 && "cxi".contains(numeral) {
+            3
+        } else {
+            1
+        };
+        for _ in 0..repeats {
+            match rest.strip_prefix(numeral) {
+                Some(stripped) => {
+                    rest = stripped;
+                    total += value;
+                }
+                None => break,
+            }
+        }
+    }
+
+    (rest.is_empty() && total > 0).then_some(total as f64)
+}
+
+/// Turn a date such as `1951`, `1951-05` or `1951-05-01T00:00:00Z` into a sortable number
+fn parse_date(date: &str) -> Option<f64> {
+    let mut parts = date.trim().get(..10.min(date.trim().len()))?.split('-');
+    let year: f64 = parts.next()?.parse().ok()?;
+    let month: f64 = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .unwrap_or(0.0);
+    let day: f64 = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .unwrap_or(0.0);
+
+    Some(year * 10000.0 + month * 100.0 + day)
+}
+
+/// Group books whose titles share a common prefix, the prefix being the series name
+///
+/// Titles are compared word by word after sorting so books of the same series are next to
+/// each other. Books that share no prefix with any other book are returned without a name.
+fn group_by_title_prefix(mut books: Vec<BookInfo>) -> Vec<(Option<String>, Vec<BookInfo>)> {
+    let title_words = |book: &BookInfo| -> Vec<String> {
+        book.title
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    };
+    books.sort_by_key(|book| book.title.as_deref().unwrap_or_default().to_lowercase());
+
+    // Each group keeps the words all of its titles start with
+    let mut groups: Vec<(Vec<String>, Vec<BookInfo>)> = Vec::new();
+    for book in books {
+        let words = title_words(&book);
+        if let Some((prefix, members)) = groups.last_mut() {
+            let common = common_prefix(prefix, &words);
+            if series_name(&common).is_some() {
+                *prefix = common;
+                members.push(book);
+                continue;
+            }
+        }
+        groups.push((words, vec![book]));
+    }
+
+    groups
+        .into_iter()
+        .map(|(prefix, members)| match members.len() {
+            1 => (None, members),
+            _ => (series_name(&prefix), members),
+        })
+        .collect()
+}
+
+/// Get the words two titles start with, ignoring case and trailing punctuation
+fn common_prefix(a: &[String], b: &[String]) -> Vec<String> {
+    a.iter()
+        .zip(b)
+        .take_while(|(a_word, b_word)| normalise_word(a_word) == normalise_word(b_word))
+        .map(|(a_word, _)| a_word.to_owned())
+        .collect()
+}
+
+/// Turn a shared title prefix into a series name, dropping trailing stopwords and
+/// punctuation, if anything meaningful is left
+fn series_name(prefix: &[String]) -> Option<String> {
+    let mut words = prefix.to_vec();
+    while words
+        .last()
+        .is_some_and(|word| STOPWORDS.contains(&normalise_word(word).as_str()))
+    {
+        words.pop();
+    }
+    let name = words.join(" ");
+    let name = name.trim_end_matches([',', ':', '-', ';']).trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(name.to_string())
+}
+
+/// Lowercase a word and strip the punctuation around it for comparing titles
+fn normalise_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
@@ -0,0 +1,793 @@
+use std::path::Path;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use color_eyre::eyre::eyre;
+use encoding_rs::{Encoding, UTF_8};
+use quick_xml::escape::escape;
+
+use crate::{
+    creator::Creator,
+    opf::OpfDocument,
+    xml::{Element, XmlDocument, local_name},
+};
+
+/// The file extension of a FictionBook
+const FB2_EXTENSION: &str = "fb2";
+/// The file extension of a zipped FictionBook
+pub const ZIPPED_FB2_EXTENSION: &str = "fb2.zip";
+/// The byte order mark some UTF-8 FictionBooks start with
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+/// The children of `<description>` in the order the schema gives them
+const DESCRIPTION_ORDER: [&str; 6] = [
+    "title-info",
+    "src-title-info",
+    "document-info",
+    "publish-info",
+    "custom-info",
+    "output",
+];
+/// The children of `<title-info>` in the order the schema gives them
+const TITLE_INFO_ORDER: [&str; 11] = [
+    "genre",
+    "author",
+    "book-title",
+    "annotation",
+    "keywords",
+    "date",
+    "coverpage",
+    "lang",
+    "src-lang",
+    "translator",
+    "sequence",
+];
+/// The children of `<publish-info>` in the order the schema gives them
+const PUBLISH_INFO_ORDER: [&str; 6] =
+    ["book-name", "publisher", "city", "year", "isbn", "sequence"];
+/// The title info elements holding creators, along with the MARC relator code of their role
+const CREATOR_ELEMENTS: [(&str, &str); 2] = [("author", "aut"), ("translator", "trl")];
+
+/// Whether the path is a FictionBook, either plain (FB2) or zipped (FB2.zip)
+pub fn is_fictionbook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(FB2_EXTENSION))
+        || is_zipped_fictionbook(path)
+}
+
+/// Whether the path is a zipped FictionBook (FB2.zip)
+pub fn is_zipped_fictionbook(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name.to_string_lossy()
+            .to_lowercase()
+            .ends_with(&format!(".{}", ZIPPED_FB2_EXTENSION))
+    })
+}
+
+/// Struct holding a FictionBook, its metadata kept in the `<title-info>` and
+/// `<publish-info>` of its `<description>`
+#[derive(Clone, Debug)]
+pub struct FictionBook {
+    /// The parsed FictionBook
+    document: XmlDocument,
+    /// The encoding the book is written in, which it is written back out in
+    encoding: &'static Encoding,
+    /// Whether the book starts with a byte order mark
+    bom: bool,
+}
+
+impl FictionBook {
+    /// Parse a FictionBook, decoding it from the encoding its XML declaration gives
+    pub fn parse(data: &[u8]) -> color_eyre::Result<Self> {
+        let bom = data.starts_with(UTF8_BOM);
+        let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+        let encoding = declared_encoding(data)?;
+        let (source, had_errors) = encoding.decode_without_bom_handling(data);
+        if had_errors {
+            return Err(eyre!(
+                "Malformed FictionBook: the text isn't valid {}",
+                encoding.name()
+            ));
+        }
+        let document =
+            XmlDocument::parse(&source).map_err(|err| eyre!("Malformed FictionBook: {}", err))?;
+        let root = document.root();
+        if root.local_name() != "FictionBook" {
+            return Err(eyre!(
+                "Malformed FictionBook: expected a <FictionBook> root element but found <{}>",
+                root.name
+            ));
+        }
+        if root
+            .child("description")
+            .and_then(|description| description.child("title-info"))
+            .is_none()
+        {
+            return Err(eyre!("Malformed FictionBook: no <title-info> found"));
+        }
+
+        Ok(FictionBook {
+            document,
+            encoding,
+            bom,
+        })
+    }
+
+    /// Get the metadata of the book as an OPF package document, so that it can be edited in
+    /// the same way as an epub's
+    ///
+    /// The first sequence becomes calibre's series metas. Books without a title are given
+    /// the fallback title.
+    pub fn metadata(&self, fallback_title: &str) -> color_eyre::Result<OpfDocument> {
+        let title_info = self.title_info();
+        let mut elements = Vec::new();
+        let title = value(title_info, "book-title").unwrap_or_else(|| fallback_title.to_string());
+        elements.push(format!("<dc:title>{}</dc:title>", escape(&title)));
+        for (name, role) in CREATOR_ELEMENTS {
+            for creator in title_info
+                .elements()
+                .filter(|element| element.local_name() == name)
+                .filter_map(creator)
+            {
+                let file_as = creator
+                    .file_as
+                    .map(|file_as| format!(" opf:file-as=\"{}\"", escape(&file_as)))
+                    .unwrap_or_default();
+                elements.push(format!(
+                    "<dc:creator opf:role=\"{}\"{}>{}</dc:creator>",
+                    role,
+                    file_as,
+                    escape(&creator.name)
+                ));
+            }
+        }
+        let publisher = self
+            .description()
+            .child("publish-info")
+            .and_then(|publish_info| value(publish_info, "publisher"));
+        for (element, value) in [
+            ("description", annotation(title_info)),
+            ("language", value(title_info, "lang")),
+            ("date", date(title_info)),
+            ("publisher", publisher),
+        ] {
+            if let Some(value) = value {
+                elements.push(format!("<dc:{0}>{1}</dc:{0}>", element, escape(&value)));
+            }
+        }
+        for keyword in keywords(title_info) {
+            elements.push(format!("<dc:subject>{}</dc:subject>", escape(&keyword)));
+        }
+        if let Some(sequence) = title_info.child("sequence")
+            && let Some(series) = sequence
+                .attribute("name")
+                .map(str::trim)
+                .filter(|series| !series.is_empty())
+        {
+            elements.push(format!(
+                "<meta name=\"calibre:series\" content=\"{}\"/>",
+                escape(series)
+            ));
+            if let Some(number) = sequence.attribute("number") {
+                elements.push(format!(
+                    "<meta name=\"calibre:series_index\" content=\"{}\"/>",
+                    escape(number.trim())
+                ));
+            }
+        }
+
+        OpfDocument::from_metadata(&elements)
+    }
+
+    /// Write the metadata of an OPF package document to the book's description
+    ///
+    /// Creators whose role is neither author nor translator aren't kept, and authors and
+    /// translators whose name is unchanged keep their element as it is. Elements that have
+    /// no place in the package document, such as the genres, are left as they are.
+    pub fn set_metadata(&mut self, metadata: &OpfDocument) {
+        let prefix = self.prefix();
+        let title_info = self.title_info_mut();
+        if let Some(title) = metadata.title() {
+            set_value(
+                title_info,
+                &prefix,
+                "book-title",
+                Some(title),
+                &TITLE_INFO_ORDER,
+            );
+        }
+        let creators = metadata.creators();
+        for (name, role) in CREATOR_ELEMENTS {
+            let creators: Vec<&Creator> = creators
+                .iter()
+                .filter(|creator| match role {
+                    "aut" => creator.is_author(),
+                    _ => creator.role.as_deref() == Some(role),
+                })
+                .collect();
+            set_creators(title_info, &prefix, name, &creators);
+        }
+
+        let description = metadata.dc_value("description");
+        if annotation(title_info) != description {
+            title_info.remove_children(|element| element.local_name() == "annotation");
+            if let Some(description) = description {
+                let mut annotation = Element::new(&format!("{}annotation", prefix));
+                for paragraph in description.lines().map(str::trim) {
+                    if !paragraph.is_empty() {
+                        let paragraph = Element::new(&format!("{}p", prefix)).with_text(paragraph);
+                        annotation.append_child(paragraph);
+                    }
+                }
+                insert_in_order(title_info, annotation, &TITLE_INFO_ORDER);
+            }
+        }
+        let keywords = metadata.dc_values("subject").join(", ");
+        set_value(
+            title_info,
+            &prefix,
+            "keywords",
+            Some(keywords),
+            &TITLE_INFO_ORDER,
+        );
+        // Dates are kept without their time, with the value attribute only holding full dates
+        let new_date = metadata
+            .dc_value("date")
+            .and_then(|date| date.split('T').next().map(String::from));
+        if date(title_info) != new_date {
+            set_value(
+                title_info,
+                &prefix,
+                "date",
+                new_date.clone(),
+                &TITLE_INFO_ORDER,
+            );
+            if let Some(element) = title_info.child_mut("date") {
+                match new_date.filter(|date| date.len() == 10) {
+                    Some(date) => element.set_attribute("value", &date),
+                    None => element.remove_attribute("value"),
+                }
+            }
+        }
+        set_value(
+            title_info,
+            &prefix,
+            "lang",
+            metadata.dc_value("language"),
+            &TITLE_INFO_ORDER,
+        );
+
+        match metadata.series() {
+            Some((series, number)) => {
+                if title_info.child("sequence").is_none() {
+                    let sequence = Element::new(&format!("{}sequence", prefix));
+                    insert_in_order(title_info, sequence, &TITLE_INFO_ORDER);
+                }
+                let sequence = title_info.child_mut("sequence").unwrap();
+                sequence.set_attribute("name", series.trim());
+                match number {
+                    Some(number) => sequence.set_attribute("number", number.trim()),
+                    None => sequence.remove_attribute("number"),
+                }
+            }
+            None => title_info.remove_children(|element| element.local_name() == "sequence"),
+        }
+
+        let publisher = metadata.dc_value("publisher");
+        let description = self.description_mut();
+        match description.child_mut("publish-info") {
+            Some(publish_info) => set_value(
+                publish_info,
+                &prefix,
+                "publisher",
+                publisher,
+                &PUBLISH_INFO_ORDER,
+            ),
+            None => {
+                if let Some(publisher) = publisher {
+                    let mut publish_info = Element::new(&format!("{}publish-info", prefix));
+                    publish_info.append_child(
+                        Element::new(&format!("{}publisher", prefix)).with_text(&publisher),
+                    );
+                    insert_in_order(description, publish_info, &DESCRIPTION_ORDER);
+                }
+            }
+        }
+    }
+
+    /// Get the data of the book's cover image, the binary its `<coverpage>` links to
+    pub fn cover(&self) -> color_eyre::Result<Option<Vec<u8>>> {
+        let href = self
+            .title_info()
+            .child("coverpage")
+            .and_then(|coverpage| coverpage.child("image"))
+            .and_then(|image| {
+                image
+                    .attributes()
+                    .iter()
+                    .find(|(key, _)| local_name(key) == "href")
+                    .map(|(_, href)| href.to_owned())
+            });
+        let Some(id) = href.as_deref().and_then(|href| href.strip_prefix('#')) else {
+            return Ok(None);
+        };
+        let Some(binary) = self.document.root().elements().find(|element| {
+            element.local_name() == "binary" && element.attribute("id") == Some(id)
+        }) else {
+            return Ok(None);
+        };
+        let data: String = binary
+            .text()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let data = STANDARD
+            .decode(data)
+            .map_err(|err| eyre!("Malformed cover image {}: {}", id, err))?;
+
+        Ok(Some(data))
+    }
+
+    /// Write the book back out in the encoding it was read in
+    ///
+    /// Characters the encoding can't hold are written as character references.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = if self.bom {
+            UTF8_BOM.to_vec()
+        } else {
+            Vec::new()
+        };
+        let source = self.document.to_string();
+        let (encoded, _, _) = self.encoding.encode(&source);
+        data.extend_from_slice(&encoded);
+
+        data
+    }
+
+    /// Get the namespace prefix the book's elements are written with, along with its colon
+    fn prefix(&self) -> String {
+        self.document
+            .root()
+            .prefix()
+            .map(|prefix| format!("{}:", prefix))
+            .unwrap_or_default()
+    }
+
+    /// Get the `<description>` of the book
+    fn description(&self) -> &Element {
+        self.document.root().child("description").unwrap()
+    }
+
+    /// Get the `<description>` of the book mutably
+    fn description_mut(&mut self) -> &mut Element {
+        self.document.root_mut().child_mut("description").unwrap()
+    }
+
+    /// Get the `<title-info>` of the book
+    fn title_info(&self) -> &Element {
+        self.description().child("title-info").unwrap()
+    }
+
+    /// Get the `<title-info>` of the book mutably
+    fn title_info_mut(&mut self) -> &mut Element {
+        self.description_mut().child_mut("title-info").unwrap()
+    }
+}
+
+/// Get the encoding the XML declaration at the start of a FictionBook gives, UTF-8 when it
+/// doesn't give one
+///
+/// Only encodings that ASCII text can be read in are supported, which leaves out UTF-16.
+fn declared_encoding(data: &[u8]) -> color_eyre::Result<&'static Encoding> {
+    if !data.starts_with(b"<?xml") {
+        return Ok(UTF_8);
+    }
+    let Some(end) = data.windows(2).position(|window| window == b"?>") else {
+        return Ok(UTF_8);
+    };
+    let declaration = String::from_utf8_lossy(&data[..end]);
+    let Some((_, rest)) = declaration.split_once("encoding") else {
+        return Ok(UTF_8);
+    };
+    let label = rest
+        .trim_start()
+        .trim_start_matches('=')
+        .trim_start()
+        .trim_start_matches(['"', '\''])
+        .split(['"', '\''])
+        .next()
+        .unwrap_or_default();
+
+    Encoding::for_label(label.as_bytes())
+        .filter(|encoding| encoding.output_encoding() == *encoding)
+        .ok_or_else(|| eyre!("Unsupported FictionBook encoding \"{}\"", label))
+}
+
+/// Get the text of a child element, if it has any
+fn value(parent: &Element, name: &str) -> Option<String> {
+    parent
+        .child(name)
+        .map(|element| element.text().trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Get the paragraphs of the title info's annotation, one per line
+fn annotation(title_info: &Element) -> Option<String> {
+    let annotation = title_info.child("annotation")?;
+    let paragraphs: Vec<String> = annotation
+        .elements()
+        .map(|paragraph| paragraph.text().trim().to_string())
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect();
+    if paragraphs.is_empty() {
+        return value(title_info, "annotation");
+    }
+
+    Some(paragraphs.join("\n"))
+}
+
+/// Get the date of the title info, from its value attribute when it has one
+fn date(title_info: &Element) -> Option<String> {
+    title_info
+        .child("date")
+        .and_then(|date| date.attribute("value"))
+        .map(|date| date.trim().to_string())
+        .filter(|date| !date.is_empty())
+        .or_else(|| value(title_info, "date"))
+}
+
+/// Get the comma separated keywords of the title info
+fn keywords(title_info: &Element) -> Vec<String> {
+    value(title_info, "keywords")
+        .map(|keywords| {
+            keywords
+                .split(',')
+                .map(str::trim)
+                .filter(|keyword| !keyword.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get the name and sort name of an author or translator element, its nickname being used
+/// when it has no first, middle or last name
+fn creator(element: &Element) -> Option<Creator> {
+    let last_name = value(element, "last-name");
+    let given_names: Vec<String> = [value(element, "first-name"), value(element, "middle-name")]
+        .into_iter()
+        .flatten()
+        .collect();
+    let names: Vec<String> = given_names
+        .iter()
+        .cloned()
+        .chain(last_name.clone())
+        .collect();
+    if names.is_empty() {
+        return value(element, "nickname").map(|nickname| Creator {
+            name: nickname,
+            ..Default::default()
+        });
+    }
+    let file_as = last_name.map(|last_name| match given_names.is_empty() {
+        true => last_name,
+        false => format!("{}, {}", last_name, given_names.join(" ")),
+    });
+
+    Some(Creator {
+        name: names.join(" "),
+        role: None,
+        file_as,
+    })
+}
+
+/// Build an author or translator element for a creator
+///
+/// The last name is taken from the creator's sort name when their name ends with it, and is
+/// otherwise the last word of their name, the first word being the first name and the rest
+/// the middle name. Names of a single word are written as a nickname.
+fn creator_element(prefix: &str, name: &str, creator: &Creator) -> Element {
+    let full_name = creator.name.trim();
+    let last_name = creator
+        .file_as
+        .as_deref()
+        .and_then(|file_as| file_as.split_once(','))
+        .map(|(last_name, _)| last_name.trim())
+        .filter(|last_name| full_name.len() > last_name.len() && full_name.ends_with(last_name))
+        .or_else(|| full_name.rsplit_once(' ').map(|(_, last_name)| last_name));
+
+    let mut element = Element::new(&format!("{}{}", prefix, name));
+    let parts = match last_name {
+        Some(last_name) => {
+            let given_names = full_name[..full_name.len() - last_name.len()].trim();
+            let (first_name, middle_name) = given_names
+                .split_once(' ')
+                .map_or((given_names, ""), |(first, middle)| (first, middle.trim()));
+            vec![
+                ("first-name", first_name),
+                ("middle-name", middle_name),
+                ("last-name", last_name),
+            ]
+        }
+        None => vec![("nickname", full_name)],
+    };
+    for (part, value) in parts {
+        if !value.is_empty() {
+            element.append_child(Element::new(&format!("{}{}", prefix, part)).with_text(value));
+        }
+    }
+
+    element
+}
+
+/// Replace the author or translator elements of the title info with ones for the given
+/// creators, leaving them as they are when the creators are unchanged
+fn set_creators(title_info: &mut Element, prefix: &str, name: &str, creators: &[&Creator]) {
+    let old_elements: Vec<(Element, Option<Creator>)> = title_info
+        .elements()
+        .filter(|element| element.local_name() == name)
+        .map(|element| (element.clone(), creator(element)))
+        .collect();
+    // Creators match when their names do and their sort names don't differ
+    let matches = |old: &Option<Creator>, new: &Creator| {
+        old.as_ref().is_some_and(|old| {
+            old.name == new.name.trim()
+                && (old.file_as.is_none() || new.file_as.is_none() || old.file_as == new.file_as)
+        })
+    };
+    if old_elements.len() == creators.len()
+        && old_elements
+            .iter()
+            .zip(creators)
+            .all(|((_, old), new)| matches(old, new))
+    {
+        return;
+    }
+
+    let elements: Vec<Element> = creators
+        .iter()
+        .map(|new| {
+            old_elements
+                .iter()
+                .find(|(_, old)| matches(old, new))
+                .map(|(element, _)| element.clone())
+                .unwrap_or_else(|| creator_element(prefix, name, new))
+        })
+        .collect();
+    title_info.remove_children(|element| element.local_name() == name);
+    for element in elements {
+        insert_in_order(title_info, element, &TITLE_INFO_ORDER);
+    }
+}
+
+/// Set the text of a child element, adding it in the place the schema gives it, or remove
+/// the element when there is no value
+fn set_value(
+    parent: &mut Element,
+    prefix: &str,
+    name: &str,
+    value: Option<String>,
+    order: &[&str],
+) {
+    let value = value.map(|value| value.trim().to_string());
+    match value.filter(|value| !value.is_empty()) {
+        Some(value) => match parent.child_mut(name) {
+            Some(element) => {
+                if element.text().trim() != value {
+                    element.set_text(&value)
+                }
+            }
+            None => {
+                let element = Element::new(&format!("{}{}", prefix, name)).with_text(&value);
+                insert_in_order(parent, element, order);
+            }
+        },
+        None => parent.remove_children(|element| element.local_name() == name),
+    }
+}
+
+/// Insert an element into its parent in the place the schema's order of the parent's
+/// children gives it
+fn insert_in_order(parent: &mut Element, element: Element, order: &[&str]) {
+    let later_elements: Vec<&str> = order
+        .iter()
+        .skip_while(|name| **name != element.local_name())
+        .skip(1)
+        .copied()
+        .collect();
+    parent.insert_child_before(element, |child| {
+        later_elements.contains(&child.local_name())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::WINDOWS_1251;
+
+    use super::*;
+
+    /// A FictionBook with a cover, a sequence and a genre the package document has no place for
+    const FICTION_BOOK: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info>
+      <genre>sf</genre>
+      <author><first-name>Isaac</first-name><last-name>Asimov</last-name></author>
+      <book-title>Foundation</book-title>
+      <annotation><p>The first book.</p></annotation>
+      <keywords>Science Fiction, Space</keywords>
+      <date value="1951-05-01">1951-05-01</date>
+      <coverpage><image l:href="#cover.png"/></coverpage>
+      <lang>en</lang>
+      <sequence name="Foundation" number="1"/>
+    </title-info>
+    <document-info><author><nickname>scanner</nickname></author></document-info>
+  </description>
+  <body><section><p>The text of the book</p></section></body>
+  <binary id="cover.png" content-type="image/png">iVBORw==</binary>
+</FictionBook>
+"##;
+
+    /// Get the local names of the children of `<title-info>`
+    fn title_info_names(book: &FictionBook) -> Vec<&str> {
+        book.title_info()
+            .elements()
+            .map(Element::local_name)
+            .collect()
+    }
+
+    #[test]
+    fn metadata_round_trips_through_the_description() {
+        let mut book = FictionBook::parse(FICTION_BOOK.as_bytes()).unwrap();
+        let mut metadata = book.metadata("fallback").unwrap();
+        metadata.set_title("Foundation and Empire");
+        metadata.set_creators(&[
+            Creator {
+                name: String::from("Isaac Asimov"),
+                ..Creator::default()
+            },
+            Creator {
+                name: String::from("Jane Doe"),
+                role: Some(String::from("trl")),
+                file_as: None,
+            },
+        ]);
+        metadata.set_dc_value("description", "The second book.");
+        metadata.set_dc_values(
+            "subject",
+            &[String::from("Science Fiction"), String::from("Empire")],
+        );
+        metadata.set_dc_values("publisher", &[String::from("Gnome Press")]);
+        metadata.set_series("Foundation", "2");
+        book.set_metadata(&metadata);
+
+        let edited = FictionBook::parse(&book.to_bytes()).unwrap();
+        let reread = edited.metadata("fallback").unwrap();
+        assert_eq!(reread.title().as_deref(), Some("Foundation and Empire"));
+        let creators: Vec<(String, Option<String>)> = reread
+            .creators()
+            .into_iter()
+            .map(|creator| (creator.name, creator.role))
+            .collect();
+        assert_eq!(
+            creators,
+            [
+                (String::from("Isaac Asimov"), Some(String::from("aut"))),
+                (String::from("Jane Doe"), Some(String::from("trl"))),
+            ]
+        );
+        assert_eq!(
+            reread.dc_value("description").as_deref(),
+            Some("The second book.")
+        );
+        assert_eq!(reread.dc_values("subject"), ["Science Fiction", "Empire"]);
+        assert_eq!(reread.dc_values("publisher"), ["Gnome Press"]);
+        assert_eq!(reread.dc_value("date").as_deref(), Some("1951-05-01"));
+        assert_eq!(reread.dc_value("language").as_deref(), Some("en"));
+        assert_eq!(
+            reread.series(),
+            Some((String::from("Foundation"), Some(String::from("2"))))
+        );
+        assert_eq!(edited.cover().unwrap(), Some(b"\x89PNG".to_vec()));
+
+        // The unchanged author keeps its element, and the new elements follow the schema
+        let author = edited.title_info().child("author").unwrap();
+        assert_eq!(
+            author.child("last-name").map(Element::text).as_deref(),
+            Some("Asimov")
+        );
+        assert_eq!(
+            title_info_names(&edited),
+            [
+                "genre",
+                "author",
+                "book-title",
+                "annotation",
+                "keywords",
+                "date",
+                "coverpage",
+                "lang",
+                "translator",
+                "sequence",
+            ]
+        );
+        let description: Vec<&str> = edited
+            .description()
+            .elements()
+            .map(Element::local_name)
+            .collect();
+        assert_eq!(description, ["title-info", "document-info", "publish-info"]);
+    }
+
+    #[test]
+    fn unchanged_metadata_leaves_the_book_as_it_is() {
+        let mut book = FictionBook::parse(FICTION_BOOK.as_bytes()).unwrap();
+        book.set_metadata(&book.metadata("fallback").unwrap());
+
+        assert_eq!(String::from_utf8(book.to_bytes()).unwrap(), FICTION_BOOK);
+    }
+
+    #[test]
+    fn books_are_written_back_in_their_encoding() {
+        let source = FICTION_BOOK
+            .replace("UTF-8", "windows-1251")
+            .replace("Isaac", "Айзек")
+            .replace("<last-name>Asimov", "<last-name>Азимов");
+        let (data, _, _) = WINDOWS_1251.encode(&source);
+        let mut book = FictionBook::parse(&data).unwrap();
+        let mut metadata = book.metadata("fallback").unwrap();
+        assert_eq!(metadata.author().as_deref(), Some("Айзек Азимов"));
+
+        metadata.set_title("Основание");
+        book.set_metadata(&metadata);
+        let output = book.to_bytes();
+
+        let (expected, _, _) = WINDOWS_1251.encode("<book-title>Основание</book-title>");
+        assert!(
+            output
+                .windows(expected.len())
+                .any(|window| window == &expected[..])
+        );
+        let reread = FictionBook::parse(&output)
+            .unwrap()
+            .metadata("fallback")
+            .unwrap();
+        assert_eq!(reread.title().as_deref(), Some("Основание"));
+        assert_eq!(reread.author().as_deref(), Some("Айзек Азимов"));
+    }
+
+    #[test]
+    fn prefixed_books_keep_their_prefix() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<fb:FictionBook xmlns:fb="http://www.gribuser.ru/xml/fictionbook/2.0">
+  <fb:description>
+    <fb:title-info><fb:book-title>Foundation</fb:book-title></fb:title-info>
+  </fb:description>
+  <fb:body/>
+</fb:FictionBook>
+"#;
+        let mut book = FictionBook::parse(source.as_bytes()).unwrap();
+        let mut metadata = book.metadata("fallback").unwrap();
+        metadata.set_dc_values("publisher", &[String::from("Gnome Press")]);
+        metadata.set_series("Foundation", "1");
+        book.set_metadata(&metadata);
+
+        let output = String::from_utf8(book.to_bytes()).unwrap();
+        assert!(output.contains("<fb:publish-info><fb:publisher>Gnome Press</fb:publisher>"));
+        assert!(output.contains("<fb:sequence name=\"Foundation\" number=\"1\"/>"));
+        let reread = FictionBook::parse(output.as_bytes())
+            .unwrap()
+            .metadata("fallback")
+            .unwrap();
+        assert_eq!(reread.dc_values("publisher"), ["Gnome Press"]);
+    }
+
+    #[test]
+    fn books_without_a_title_are_given_the_fallback_title() {
+        let source = FICTION_BOOK.replace("<book-title>Foundation</book-title>", "");
+        let book = FictionBook::parse(source.as_bytes()).unwrap();
+
+        assert_eq!(
+            book.metadata("foundation").unwrap().title().as_deref(),
+            Some("foundation")
+        );
+    }
+}
@@ -0,0 +1,548 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+
+use crate::model::{file_stem, is_book};
+
+/// The placeholders a format string can use
+pub const PLACEHOLDERS: [&str; 9] = [
+    "title",
+    "series",
+    "position",
+    "author",
+    "author_sort",
+    "year",
+    "total",
+    "original_title",
+    "filename",
+];
+/// The case transforms a placeholder can be followed by
+const TRANSFORMS: [&str; 3] = ["upper", "lower", "title"];
+/// The characters that can't be used in file names on every platform
+const UNSAFE_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Struct holding a parsed format string
+///
+/// Format strings are made up of text and placeholders:
+/// - `${name}` is replaced with the value of the placeholder
+/// - `${name:03}` pads the value with zeros to three characters, `${name:10}` pads it with
+///   spaces, `${name:<10}` and `${name:>10}` align it to the left or right and `${name:.20}`
+///   cuts it to 20 characters
+/// - `${name|upper}`, `${name|lower}` and `${name|title}` change the case of the value
+/// - `$[...]` is an optional section, left out when any placeholder inside it is empty
+/// - `$$` is a literal `$`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    /// The parts the format string is made of
+    parts: Vec<Part>,
+}
+
+/// Enum of the parts of a format string
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    /// Text copied as is
+    Text(String),
+    /// A placeholder replaced with its value
+    Placeholder(Placeholder),
+    /// A section left out when any placeholder inside it is empty
+    Optional(Vec<Part>),
+}
+
+/// Struct holding a placeholder of a format string
+#[derive(Clone, Debug, PartialEq)]
+struct Placeholder {
+    /// The name of the value to insert
+    name: String,
+    /// How the value is padded and cut
+    spec: Spec,
+    /// The case transforms applied to the value, in order
+    transforms: Vec<String>,
+}
+
+/// Struct holding the padding and width of a placeholder
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Spec {
+    /// Whether the value is padded with zeros instead of spaces
+    zero: bool,
+    /// Whether the value is aligned to the right, by default only numbers are
+    right: Option<bool>,
+    /// The minimum width of the value
+    width: usize,
+    /// The maximum number of characters of the value that are kept
+    max: Option<usize>,
+}
+
+impl Template {
+    /// Parse a format string, giving an error describing the first mistake in it
+    pub fn parse(source: &str) -> color_eyre::Result<Self> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut index = 0;
+        let parts = parse_parts(&chars, &mut index, false)?;
+
+        Ok(Template { parts })
+    }
+
+    /// Render the format string with the given values, missing values are left empty
+    pub fn render(&self, values: &HashMap<&str, String>) -> String {
+        render_required(&self.parts, values)
+    }
+
+    /// Render the format string as a relative file path
+    ///
+    /// The values are made safe to use in file names first, so only the `/` written in the
+    /// format string itself separate directories. Empty, `.` and `..` directories are left
+    /// out, and the file name gets the book's own extension in place of any book extension
+    /// written in the format string.
+    pub fn render_path(
+        &self,
+        values: &HashMap<&str, String>,
+        extension: &str,
+    ) -> color_eyre::Result<PathBuf> {
+        let values = values
+            .iter()
+            .map(|(name, value)| (*name, sanitize_file_name(value)))
+            .collect();
+        let mut components: Vec<String> = self
+            .render(&values)
+            .split('/')
+            .map(sanitize_file_name)
+            .filter(|component| !component.is_empty() && component != "." && component != "..")
+            .collect();
+        let file_name = components
+            .last_mut()
+            .ok_or_else(|| eyre!("The file path format gives an empty path"))?;
+        if is_book(Path::new(file_name.as_str())) {
+            *file_name = file_stem(Path::new(file_name.as_str()));
+        }
+        file_name.push('.');
+        file_name.push_str(extension);
+
+        Ok(components.iter().collect())
+    }
+}
+
+/// Make a value safe to use as a file name, replacing the characters that aren't allowed in
+/// file names with `_` and trimming the spaces and dots some platforms don't allow at the end
+pub fn sanitize_file_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if UNSAFE_CHARACTERS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches(['.', ' '])
+        .to_string()
+}
+
+/// Parse the parts of a format string up to its end, or up to the end of the optional
+/// section being parsed
+fn parse_parts(chars: &[char], index: &mut usize, optional: bool) -> color_eyre::Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    while *index < chars.len() {
+        let c = chars[*index];
+        match (c, chars.get(*index + 1)) {
+            ('$', Some('$')) => {
+                text.push('$');
+                *index += 2;
+            }
+            ('$', Some('{')) => {
+                let start = *index;
+                let end = chars[start..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .map(|offset| start + offset)
+                    .ok_or_else(|| {
+                        eyre!(
+                            "Unclosed placeholder at character {} of the format",
+                            start + 1
+                        )
+                    })?;
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                let inner: String = chars[start + 2..end].iter().collect();
+                parts.push(Part::Placeholder(parse_placeholder(&inner, start)?));
+                *index = end + 1;
+            }
+            ('$', Some('[')) => {
+                let start = *index;
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                *index += 2;
+                let section = parse_parts(chars, index, true)?;
+                if chars.get(*index) != Some(&']') {
+                    return Err(eyre!(
+                        "Unclosed optional section at character {} of the format",
+                        start + 1
+                    ));
+                }
+                *index += 1;
+                parts.push(Part::Optional(section));
+            }
+            (']', _) if optional => break,
+            _ => {
+                text.push(c);
+                *index += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+
+    Ok(parts)
+}
+
+/// Parse the inside of a `${...}` placeholder that starts at the given character
+fn parse_placeholder(inner: &str, start: usize) -> color_eyre::Result<Placeholder> {
+    let mut pieces = inner.split('|');
+    let head = pieces.next().unwrap_or_default().trim();
+    let (name, spec) = match head.split_once(':') {
+        Some((name, spec)) => (name.trim(), Some(spec.trim())),
+        None => (head, None),
+    };
+    if !PLACEHOLDERS.contains(&name) {
+        return Err(eyre!(
+            "Unknown placeholder \"${{{}}}\" at character {} of the format, expected one of: {}",
+            name,
+            start + 1,
+            PLACEHOLDERS.join(", ")
+        ));
+    }
+
+    let spec = match spec {
+        Some(spec) => parse_spec(spec).ok_or_else(|| {
+            eyre!(
+                "Invalid width \"{}\" for ${{{}}} at character {} of the format, expected something like 03, >10 or .20",
+                spec,
+                name,
+                start + 1
+            )
+        })?,
+        // Positions are padded to two digits unless told otherwise
+        None if name == "position" => Spec {
+            zero: true,
+            width: 2,
+            ..Spec::default()
+        },
+        None => Spec::default(),
+    };
+
+    let mut transforms = Vec::new();
+    for transform in pieces {
+        let transform = transform.trim();
+        if !TRANSFORMS.contains(&transform) {
+            return Err(eyre!(
+                "Unknown transform \"{}\" for ${{{}}} at character {} of the format, expected one of: {}",
+                transform,
+                name,
+                start + 1,
+                TRANSFORMS.join(", ")
+            ));
+        }
+        transforms.push(transform.to_string());
+    }
+
+    Ok(Placeholder {
+        name: name.to_string(),
+        spec,
+        transforms,
+    })
+}
+
+/// Parse a width specifier such as `03`, `10`, `>10`, `<10` or `.20`
+fn parse_spec(spec: &str) -> Option<Spec> {
+    let mut result = Spec::default();
+    let mut rest = spec;
+    if let Some(stripped) = rest.strip_prefix('>') {
+        result.right = Some(true);
+        rest = stripped;
+    } else if let Some(stripped) = rest.strip_prefix('<') {
+        result.right = Some(false);
+        rest = stripped;
+    }
+    if let Some(stripped) = rest.strip_prefix('0') {
+        result.zero = true;
+        rest = stripped;
+    }
+    let (width, max) = match rest.split_once('.') {
+        Some((width, max)) => (width, Some(max)),
+        None => (rest, None),
+    };
+    if !width.is_empty() {
+        result.width = width.parse().ok()?;
+    }
+    if let Some(max) = max {
+        result.max = Some(max.parse().ok()?);
+    }
+
+    Some(result)
+}
+
+/// Render parts of a format string, giving nothing if an optional section's placeholder is
+/// empty
+fn render_parts(parts: &[Part], values: &HashMap<&str, String>) -> Option<String> {
+    let mut out = String::new();
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Placeholder(placeholder) => {
+                let value = values
+                    .get(placeholder.name.as_str())
+                    .map(|value| value.trim())
+                    .unwrap_or_default();
+                if value.is_empty() {
+                    return None;
+                }
+                out.push_str(&placeholder.render(value));
+            }
+            Part::Optional(section) => {
+                if let Some(section) = render_parts(section, values) {
+                    out.push_str(&section);
+                }
+            }
+        }
+    }
+
+    Some(out)
+}
+
+/// Render parts of a format string outside any optional section, where empty placeholders
+/// are simply left empty
+fn render_required(parts: &[Part], values: &HashMap<&str, String>) -> String {
+    let mut out = String::new();
+    for part in parts {
+        match part {
+            Part::Placeholder(placeholder) => {
+                let value = values
+                    .get(placeholder.name.as_str())
+                    .map(|value| value.trim())
+                    .unwrap_or_default();
+                if !value.is_empty() {
+                    out.push_str(&placeholder.render(value));
+                }
+            }
+            Part::Text(text) => out.push_str(text),
+            Part::Optional(section) => {
+                if let Some(section) = render_parts(section, values) {
+                    out.push_str(&section);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+impl Placeholder {
+    /// Apply the transforms and width specifier to a value
+    fn render(&self, value: &str) -> String {
+        let mut value = value.to_string();
+        for transform in &self.transforms {
+            value = match transform.as_str() {
+                "upper" => value.to_uppercase(),
+                "lower" => value.to_lowercase(),
+                _ => title_case(&value),
+            };
+        }
+        if let Some(max) = self.spec.max {
+            value = value.chars().take(max).collect();
+        }
+
+        let length = value.chars().count();
+        let is_number = value.parse::<f64>().is_ok();
+        if self.spec.zero {
+            // Numbers are padded on their whole part so that 2.5 becomes 02.5
+            let whole_length = match value.split_once('.') {
+                Some((whole, _)) if is_number => whole.chars().count(),
+                _ => length,
+            };
+            let padding = self.spec.width.saturating_sub(whole_length);
+            return format!("{}{}", "0".repeat(padding), value);
+        }
+
+        let padding = " ".repeat(self.spec.width.saturating_sub(length));
+        if self.spec.right.unwrap_or(is_number) {
+            format!("{}{}", padding, value)
+        } else {
+            format!("{}{}", value, padding)
+        }
+    }
+}
+
+/// Capitalise the first letter of every word
+fn title_case(value: &str) -> String {
+    value
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render a format string with the given values
+    fn render(format: &str, values: &[(&str, &str)]) -> String {
+        let values = values
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect();
+
+        Template::parse(format).unwrap().render(&values)
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_their_values() {
+        let values = [
+            ("series", "Foundation"),
+            ("position", "2"),
+            ("title", "Foundation and Empire"),
+        ];
+
+        assert_eq!(
+            render("${series} ${position} - ${title}", &values),
+            "Foundation 02 - Foundation and Empire"
+        );
+        // Missing values are left empty
+        assert_eq!(
+            render("${title} (${year})", &values),
+            "Foundation and Empire ()"
+        );
+    }
+
+    #[test]
+    fn dollars_are_escaped_by_doubling_them() {
+        assert_eq!(render("$$${total} $$$$", &[("total", "7")]), "$7 $$");
+        assert_eq!(render("$5 and $", &[]), "$5 and $");
+    }
+
+    #[test]
+    fn positions_are_padded_to_two_digits_by_default() {
+        assert_eq!(render("${position}", &[("position", "3")]), "03");
+        assert_eq!(render("${position}", &[("position", "2.5")]), "02.5");
+        assert_eq!(render("${position}", &[("position", "120")]), "120");
+        assert_eq!(render("${position:1}", &[("position", "3")]), "3");
+        assert_eq!(render("${total}", &[("total", "3")]), "3");
+    }
+
+    #[test]
+    fn widths_pad_align_and_cut_values() {
+        let values = [("title", "Foundation"), ("position", "7")];
+
+        assert_eq!(render("${position:03}", &values), "007");
+        assert_eq!(render("[${title:12}]", &values), "[Foundation  ]");
+        assert_eq!(render("[${title:>12}]", &values), "[  Foundation]");
+        assert_eq!(render("[${position:3}]", &values), "[  7]");
+        assert_eq!(render("[${position:<3}]", &values), "[7  ]");
+        assert_eq!(render("${title:.5}", &values), "Found");
+        assert_eq!(render("[${title:8.4}]", &values), "[Foun    ]");
+    }
+
+    #[test]
+    fn transforms_change_the_case_of_values() {
+        let values = [("title", "the end of eTERNITY")];
+
+        assert_eq!(render("${title|upper}", &values), "THE END OF ETERNITY");
+        assert_eq!(render("${title|lower}", &values), "the end of eternity");
+        assert_eq!(render("${title|title}", &values), "The End Of Eternity");
+        assert_eq!(render("${title:.7|upper}", &values), "THE END");
+        assert_eq!(
+            render("${ title | lower | upper }", &values),
+            "THE END OF ETERNITY"
+        );
+    }
+
+    #[test]
+    fn optional_sections_are_left_out_when_a_value_is_empty() {
+        let format = "${title}$[ (${year})]$[ - ${series} ${position}]";
+
+        assert_eq!(
+            render(format, &[("title", "Foundation"), ("year", "1951")]),
+            "Foundation (1951)"
+        );
+        assert_eq!(
+            render(
+                format,
+                &[
+                    ("title", "Foundation"),
+                    ("series", "Foundation"),
+                    ("position", "1")
+                ]
+            ),
+            "Foundation - Foundation 01"
+        );
+        // Sections can be nested
+        assert_eq!(
+            render(
+                "$[${series}$[ #${position}]: ]${title}",
+                &[("series", "Foundation"), ("title", "Prelude")]
+            ),
+            "Foundation: Prelude"
+        );
+        // Whitespace counts as empty
+        assert_eq!(render("$[(${year})]x", &[("year", "  ")]), "x");
+    }
+
+    #[test]
+    fn mistakes_in_the_format_are_errors() {
+        for (format, error) in [
+            ("${name}", "Unknown placeholder \"${name}\" at character 1"),
+            ("${title", "Unclosed placeholder at character 1"),
+            ("ab$[${title}", "Unclosed optional section at character 3"),
+            ("${title:x}", "Invalid width \"x\" for ${title}"),
+            ("${title:.}", "Invalid width \".\" for ${title}"),
+            ("${title|shout}", "Unknown transform \"shout\" for ${title}"),
+        ] {
+            let err = Template::parse(format).unwrap_err().to_string();
+            assert!(err.starts_with(error), "{} gave {}", format, err);
+        }
+    }
+
+    #[test]
+    fn paths_are_made_safe_and_keep_the_book_extension() {
+        let values = [
+            ("author", "Isaac Asimov"),
+            ("series", "Foundation: The Saga"),
+            ("position", "1"),
+            ("title", "../Foundation?"),
+        ];
+        let template =
+            Template::parse("${author}/$[${series}/]${position} - ${title}.epub").unwrap();
+        let values = values
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect();
+
+        assert_eq!(
+            template.render_path(&values, "pdf").unwrap(),
+            PathBuf::from("Isaac Asimov/Foundation_ The Saga/01 - .._Foundation_.pdf")
+        );
+        assert!(
+            Template::parse("$[${year}]")
+                .unwrap()
+                .render_path(&HashMap::new(), "epub")
+                .is_err()
+        );
+    }
+}
@@ -1,23 +1,67 @@
+mod calibre;
+mod cli;
+mod comic;
+mod container;
+mod cover;
+mod creator;
+mod detect;
+mod fb2;
+mod format;
+mod manifest;
+mod mobi;
 mod model;
+mod opf;
+mod pdf;
+mod session;
+mod sidecar;
+mod storage;
 mod tui;
 mod update;
 mod view;
+mod xml;
 
+use calibre::Library;
+use clap::Parser;
+use cli::{Cli, Command};
+use manifest::Manifest;
 use model::Model;
+use session::{SESSION_PATH, Session};
 use update::{handle_event, update};
 use view::View;
 
 use cli_log::*;
-use std::io;
+use std::{io, path::Path, process::ExitCode};
 
 use ratatui::{Terminal, backend::CrosstermBackend};
 
-fn main() -> color_eyre::Result<()> {
+fn main() -> color_eyre::Result<ExitCode> {
     init_cli_log!("ebook");
-    tui::install_panic_hook();
-    let mut terminal: Terminal<CrosstermBackend<io::Stdout>> = tui::init_terminal()?;
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Apply(args)) => return cli::apply(args),
+        Some(Command::Detect(args)) => return cli::detect(args),
+        Some(Command::Export(args)) => return cli::export(args),
+        Some(Command::Import(args)) => return cli::import(args),
+        None => {}
+    }
 
     let mut model: Model = Model::new();
+    if Path::new(SESSION_PATH).exists() {
+        match Session::load(Path::new(SESSION_PATH)) {
+            Ok(session) => model.saved_session = Some(session),
+            Err(err) => warn!("Ignoring unreadable session {}: {}", SESSION_PATH, err),
+        }
+    }
+    if let Some(library) = cli.library {
+        model.library = Some(Library::open(&library)?);
+    }
+    if let Some(manifest_path) = cli.manifest {
+        model.load_manifest(&Manifest::load(&manifest_path)?)?;
+        model.manifest_path = Some(manifest_path);
+    }
+
+    tui::install_panic_hook();
+    let mut terminal: Terminal<CrosstermBackend<io::Stdout>> = tui::init_terminal()?;
     while model.running {
         terminal.draw(|f| {
             if let Err(err) = View::draw(&mut model, f) {
@@ -30,11 +74,14 @@ fn main() -> color_eyre::Result<()> {
         // If handle_event returned a message then update
         if let Some(msg) = current_msg {
             update(&mut model, msg);
+            if let Err(err) = model.save_session() {
+                warn!("Unable to save the session: {}", err);
+            }
         }
     }
 
     tui::restore_terminal()?;
     terminal.show_cursor()?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cover::CoverSource,
+    creator::Creator,
+    model::{DEFAULT_FORMAT, DcField},
+};
+
+/// Struct holding a series manifest, describing every series to edit and their books
+///
+/// Manifests are stored as TOML, or as JSON when the file has a `.json` extension.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Manifest {
+    /// The series to edit
+    #[serde(default)]
+    pub series: Vec<SeriesManifest>,
+}
+
+/// Struct holding a single series of a manifest
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SeriesManifest {
+    /// The name of the series
+    pub name: String,
+    /// The format the new book titles follow
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// The format the paths the books are moved to follow, the books stay where they are
+    /// when it isn't given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_format: Option<String>,
+    /// The creators given to every book of the series that doesn't list its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<Vec<Creator>>,
+    /// Whether the creators' names are normalised, swapping `Last, First` to `First Last`
+    /// and filling in their sort names
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub normalize_authors: bool,
+    /// The Dublin Core fields set for every book of the series
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<DcField, FieldValue>,
+    /// The cover given to every book of the series that doesn't list its own, an image file
+    /// or `placeholder`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// The books of the series in the order they come in the series
+    #[serde(default)]
+    pub books: Vec<BookManifest>,
+}
+
+/// Struct holding a single book of a series in a manifest
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct BookManifest {
+    /// The path of the book, relative paths are relative to the manifest
+    pub path: PathBuf,
+    /// The title to use instead of the book's current title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The position of the book in the series, when it isn't the book's place in the list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+    /// The creators to use instead of the book's current creators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authors: Option<Vec<Creator>>,
+    /// The Dublin Core fields set for the book instead of the series' values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<DcField, FieldValue>,
+    /// The new cover of the book, an image file or `placeholder`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+}
+
+/// Enum holding the value of a Dublin Core field in a manifest, written as it is typed in
+/// or as a list holding the value of each of the field's elements
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum FieldValue {
+    /// The value as it is typed in, subjects being separated by `;`
+    Text(String),
+    /// The values of the field's elements, such as every subject of a book
+    List(Vec<String>),
+}
+
+impl FieldValue {
+    /// Get the value as it is typed into the inputs
+    pub fn to_input(&self, field: DcField) -> String {
+        match self {
+            FieldValue::Text(value) => value.to_owned(),
+            FieldValue::List(values) => field.join_values(values),
+        }
+    }
+}
+
+impl Manifest {
+    /// Load a manifest from a file, resolving the book paths against the manifest's directory
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let content = read_to_string(path)
+            .map_err(|err| eyre!("Unable to read manifest {}: {}", path.display(), err))?;
+        let mut manifest: Manifest = if is_json(path) {
+            serde_json::from_str(&content)
+                .map_err(|err| eyre!("Invalid manifest {}: {}", path.display(), err))?
+        } else {
+            toml::from_str(&content)
+                .map_err(|err| eyre!("Invalid manifest {}: {}", path.display(), err))?
+        };
+
+        let base_directory = path.parent().unwrap_or(Path::new(""));
+        for series in &mut manifest.series {
+            resolve_cover(&mut series.cover, base_directory);
+            for book in &mut series.books {
+                if book.path.is_relative() {
+                    book.path = base_directory.join(&book.path);
+                }
+                resolve_cover(&mut book.cover, base_directory);
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Save the manifest to a file
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        let content = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)?
+        };
+        write(path, content)?;
+
+        Ok(())
+    }
+}
+
+/// Get the default format string for serde
+fn default_format() -> String {
+    DEFAULT_FORMAT.to_string()
+}
+
+/// Resolve a cover image path against the manifest's directory
+fn resolve_cover(cover: &mut Option<String>, base_directory: &Path) {
+    if let Some(CoverSource::Image(path)) = cover.as_deref().and_then(CoverSource::parse)
+        && path.is_relative()
+    {
+        *cover = Some(base_directory.join(path).to_string_lossy().to_string());
+    }
+}
+
+/// Whether the path is for a JSON manifest
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
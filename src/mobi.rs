@@ -0,0 +1,729 @@
+use std::{fs::read, path::Path};
+
+use color_eyre::eyre::eyre;
+use quick_xml::escape::escape;
+
+use crate::{creator::Creator, opf::OpfDocument, xml::local_name};
+
+/// The file extensions of Kindle books
+pub const KINDLE_EXTENSIONS: [&str; 3] = ["azw3", "azw", "mobi"];
+
+/// The size of the Palm database header before the record list
+const PDB_HEADER_LENGTH: usize = 78;
+/// The size of the PalmDOC header at the start of a MOBI header record
+const PALMDOC_HEADER_LENGTH: usize = 16;
+/// The flag of the MOBI header marking that an EXTH header follows it
+const EXTH_FLAG: u32 = 0x40;
+/// The text encoding number of UTF-8, every other MOBI book is CP1252
+const UTF8_ENCODING: u32 = 65001;
+
+/// The EXTH record holding an author
+const EXTH_AUTHOR: u32 = 100;
+/// The EXTH record holding a creator that isn't an author
+const EXTH_CONTRIBUTOR: u32 = 108;
+/// The EXTH record holding the ISBN
+const EXTH_ISBN: u32 = 104;
+/// The EXTH records holding the ASIN, both are kept in step
+const EXTH_ASINS: [u32; 2] = [113, 504];
+/// The identifier schemes an ASIN is written with in package documents
+const ASIN_SCHEMES: [&str; 3] = ["AMAZON", "ASIN", "MOBI-ASIN"];
+/// The EXTH record holding the record index of the KF8 header in a combined MOBI/KF8 book
+const EXTH_KF8_BOUNDARY: u32 = 121;
+/// The EXTH record holding the offset of the cover from the first image record
+const EXTH_COVER_OFFSET: u32 = 201;
+/// The EXTH record holding the title
+const EXTH_TITLE: u32 = 503;
+/// The Dublin Core elements stored in EXTH records, along with the record type
+const EXTH_FIELDS: [(&str, u32); 6] = [
+    ("publisher", 101),
+    ("description", 103),
+    ("subject", 105),
+    ("date", 106),
+    ("rights", 109),
+    ("language", 524),
+];
+
+/// Whether the path is a Kindle book (AZW3, AZW or MOBI)
+pub fn is_kindle_book(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        KINDLE_EXTENSIONS
+            .iter()
+            .any(|kindle_extension| extension.eq_ignore_ascii_case(kindle_extension))
+    })
+}
+
+/// Struct holding a single EXTH record
+#[derive(Clone, Debug, PartialEq)]
+struct ExthRecord {
+    /// The type of the record, e.g. 503 for the title
+    kind: u32,
+    /// The data of the record
+    data: Vec<u8>,
+}
+
+/// Struct holding a MOBI header along with its EXTH records and full name
+#[derive(Clone, Debug)]
+struct MobiHeader {
+    /// The index of the record the header is in
+    record: usize,
+    /// The text encoding of the book, which the EXTH strings and full name use
+    encoding: u32,
+    /// The EXTH records in the order they were read
+    exth: Vec<ExthRecord>,
+    /// The full name (title) of the book
+    full_name: String,
+}
+
+impl MobiHeader {
+    /// Parse the MOBI header at the start of a record
+    fn parse(record: usize, data: &[u8]) -> color_eyre::Result<Self> {
+        if data.get(16..20) != Some(b"MOBI") {
+            return Err(eyre!("Record {} has no MOBI header", record));
+        }
+        let header_length = read_u32(data, 20)? as usize;
+        // The EXTH flags are the last field every MOBI header has
+        if header_length < 116 {
+            return Err(eyre!("Unsupported MOBI header of {} bytes", header_length));
+        }
+        let encoding = read_u32(data, 28)?;
+        let full_name_offset = read_u32(data, 84)? as usize;
+        let full_name_length = read_u32(data, 88)? as usize;
+        let full_name = data
+            .get(full_name_offset..full_name_offset + full_name_length)
+            .map(|full_name| decode(full_name, encoding))
+            .unwrap_or_default();
+
+        let mut exth = Vec::new();
+        let exth_start = PALMDOC_HEADER_LENGTH + header_length;
+        if read_u32(data, 128)? & EXTH_FLAG != 0
+            && data.get(exth_start..exth_start + 4) == Some(b"EXTH")
+        {
+            let count = read_u32(data, exth_start + 8)?;
+            let mut offset = exth_start + 12;
+            for _ in 0..count {
+                let kind = read_u32(data, offset)?;
+                let length = read_u32(data, offset + 4)? as usize;
+                let record_data = data
+                    .get(offset + 8..offset + length.max(8))
+                    .ok_or_else(|| eyre!("Truncated EXTH record {}", kind))?;
+                exth.push(ExthRecord {
+                    kind,
+                    data: record_data.to_vec(),
+                });
+                offset += length.max(8);
+            }
+        }
+
+        Ok(MobiHeader {
+            record,
+            encoding,
+            exth,
+            full_name,
+        })
+    }
+
+    /// Get the text of every EXTH record of the given type
+    fn strings(&self, kind: u32) -> Vec<String> {
+        self.exth
+            .iter()
+            .filter(|record| record.kind == kind)
+            .map(|record| decode(&record.data, self.encoding))
+            .collect()
+    }
+
+    /// Get the number held by the first EXTH record of the given type
+    fn number(&self, kind: u32) -> Option<u32> {
+        self.exth
+            .iter()
+            .find(|record| record.kind == kind)
+            .and_then(|record| read_u32(&record.data, 0).ok())
+    }
+
+    /// Set the text of the EXTH records of the given type
+    ///
+    /// The records take the place of the first existing record of the type so the order of
+    /// the other records is kept, or go at the end when there was none.
+    fn set_strings(&mut self, kind: u32, values: &[String]) {
+        let new_records: Vec<ExthRecord> = values
+            .iter()
+            .map(|value| ExthRecord {
+                kind,
+                data: encode(value, self.encoding),
+            })
+            .collect();
+        match self.exth.iter().position(|record| record.kind == kind) {
+            Some(index) => {
+                self.exth.retain(|record| record.kind != kind);
+                self.exth.splice(index..index, new_records);
+            }
+            None => self.exth.extend(new_records),
+        }
+    }
+
+    /// Write the header back into its record, giving the new record
+    ///
+    /// Everything before the EXTH header is copied as is and everything after it is kept,
+    /// apart from the full name which is replaced with the new one.
+    fn write(&self, data: &[u8]) -> color_eyre::Result<Vec<u8>> {
+        let header_length = read_u32(data, 20)? as usize;
+        let exth_start = PALMDOC_HEADER_LENGTH + header_length;
+        let old_name_offset = read_u32(data, 84)? as usize;
+        let old_name_length = read_u32(data, 88)? as usize;
+        let mut tail_start = exth_start;
+        if read_u32(data, 128)? & EXTH_FLAG != 0
+            && data.get(exth_start..exth_start + 4) == Some(b"EXTH")
+        {
+            let exth_length = read_u32(data, exth_start + 4)? as usize;
+            tail_start = exth_start + exth_length.next_multiple_of(4);
+        }
+        let tail_start = tail_start.min(data.len());
+
+        let mut record = data
+            .get(..exth_start)
+            .ok_or_else(|| eyre!("Truncated MOBI header"))?
+            .to_vec();
+        let exth_length = 12
+            + self
+                .exth
+                .iter()
+                .map(|record| record.data.len() + 8)
+                .sum::<usize>();
+        record.extend_from_slice(b"EXTH");
+        record.extend_from_slice(&(exth_length as u32).to_be_bytes());
+        record.extend_from_slice(&(self.exth.len() as u32).to_be_bytes());
+        for exth_record in &self.exth {
+            record.extend_from_slice(&exth_record.kind.to_be_bytes());
+            record.extend_from_slice(&(exth_record.data.len() as u32 + 8).to_be_bytes());
+            record.extend_from_slice(&exth_record.data);
+        }
+        record.resize(record.len().next_multiple_of(4), 0);
+
+        let full_name = encode(&self.full_name, self.encoding);
+        let name_offset;
+        if (tail_start..data.len()).contains(&old_name_offset)
+            && old_name_offset + old_name_length <= data.len()
+        {
+            record.extend_from_slice(&data[tail_start..old_name_offset]);
+            name_offset = record.len();
+            record.extend_from_slice(&full_name);
+            let after_name = &data[old_name_offset + old_name_length..];
+            // The full name has to be followed by two zero bytes
+            if !after_name.starts_with(&[0, 0]) {
+                record.extend_from_slice(&[0, 0]);
+            }
+            record.extend_from_slice(after_name);
+        } else {
+            record.extend_from_slice(&data[tail_start..]);
+            name_offset = record.len();
+            record.extend_from_slice(&full_name);
+            record.extend_from_slice(&[0, 0]);
+        }
+        record.resize(record.len().next_multiple_of(4), 0);
+
+        record[84..88].copy_from_slice(&(name_offset as u32).to_be_bytes());
+        record[88..92].copy_from_slice(&(full_name.len() as u32).to_be_bytes());
+        let flags = read_u32(&record, 128)? | EXTH_FLAG;
+        record[128..132].copy_from_slice(&flags.to_be_bytes());
+
+        Ok(record)
+    }
+}
+
+/// Struct holding a Kindle book, a Palm database of records with the metadata in the EXTH
+/// header following each MOBI header
+///
+/// Only the records holding MOBI headers are rewritten, the records of the book's content
+/// are copied over as they are.
+#[derive(Clone, Debug)]
+pub struct MobiBook {
+    /// The contents of the book's file
+    data: Vec<u8>,
+    /// The start and end of every record in the file
+    records: Vec<(usize, usize)>,
+    /// The MOBI headers of the book, the KF8 header of a combined MOBI/KF8 book comes second
+    headers: Vec<MobiHeader>,
+}
+
+impl MobiBook {
+    /// Read a Kindle book from a file
+    pub fn read(path: &Path) -> color_eyre::Result<Self> {
+        let data = read(path)?;
+
+        MobiBook::parse(data)
+            .map_err(|err| eyre!("Invalid Kindle book {}: {}", path.display(), err))
+    }
+
+    /// Parse a Kindle book
+    pub fn parse(data: Vec<u8>) -> color_eyre::Result<Self> {
+        if data.get(60..68) != Some(b"BOOKMOBI") {
+            return Err(eyre!("Not a MOBI book"));
+        }
+        let count = read_u16(&data, 76)? as usize;
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            offsets.push(read_u32(&data, PDB_HEADER_LENGTH + i * 8)? as usize);
+        }
+        let mut records = Vec::with_capacity(count);
+        for i in 0..count {
+            let end = offsets.get(i + 1).copied().unwrap_or(data.len());
+            if offsets[i] > end || end > data.len() {
+                return Err(eyre!("Record {} is out of bounds", i));
+            }
+            records.push((offsets[i], end));
+        }
+        if records.is_empty() {
+            return Err(eyre!("The book has no records"));
+        }
+
+        let mut headers = vec![MobiHeader::parse(0, &data[records[0].0..records[0].1])?];
+        if let Some(boundary) = headers[0]
+            .number(EXTH_KF8_BOUNDARY)
+            .map(|index| index as usize)
+            && boundary > 0
+            && boundary < records.len()
+            && data[records[boundary - 1].0..records[boundary - 1].1].starts_with(b"BOUNDARY")
+        {
+            let (start, end) = records[boundary];
+            headers.push(MobiHeader::parse(boundary, &data[start..end])?);
+        }
+
+        Ok(MobiBook {
+            data,
+            records,
+            headers,
+        })
+    }
+
+    /// Get the metadata of the book as an OPF package document, so that it can be edited
+    /// in the same way as an epub's
+    pub fn metadata(&self) -> color_eyre::Result<OpfDocument> {
+        let header = &self.headers[0];
+        let mut elements = Vec::new();
+        let title = header
+            .strings(EXTH_TITLE)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| header.full_name.to_owned());
+        elements.push(format!("<dc:title>{}</dc:title>", escape(&title)));
+        for author in header.strings(EXTH_AUTHOR) {
+            elements.push(format!("<dc:creator>{}</dc:creator>", escape(&author)));
+        }
+        for contributor in header.strings(EXTH_CONTRIBUTOR) {
+            elements.push(format!(
+                "<dc:creator opf:role=\"ctb\">{}</dc:creator>",
+                escape(&contributor)
+            ));
+        }
+        for (name, kind) in EXTH_FIELDS {
+            for value in header.strings(kind) {
+                elements.push(format!("<dc:{0}>{1}</dc:{0}>", name, escape(&value)));
+            }
+        }
+        for isbn in header.strings(EXTH_ISBN) {
+            elements.push(format!(
+                "<dc:identifier opf:scheme=\"ISBN\">{}</dc:identifier>",
+                escape(&isbn)
+            ));
+        }
+        if let Some(asin) = EXTH_ASINS
+            .iter()
+            .find_map(|&kind| header.strings(kind).into_iter().next())
+        {
+            elements.push(format!(
+                "<dc:identifier opf:scheme=\"AMAZON\">{}</dc:identifier>",
+                escape(&asin)
+            ));
+        }
+
+        OpfDocument::from_metadata(&elements)
+    }
+
+    /// Write the metadata of an OPF package document to the EXTH records of every MOBI
+    /// header
+    ///
+    /// The title also becomes the full name of the book and its database name. Creators
+    /// that are authors become author records and the rest contributor records. The ASIN
+    /// records are set from an identifier with an `AMAZON` or `ASIN` scheme, or kept in step
+    /// when there is none, and the records that have no place in the package document are
+    /// left as they are. MOBI has no standard record for the series, so it is only written
+    /// through the title.
+    pub fn set_metadata(&mut self, metadata: &OpfDocument) {
+        let title = metadata.title().unwrap_or_default();
+        let creators = metadata.creators();
+        let names = |authors: bool| -> Vec<String> {
+            creators
+                .iter()
+                .filter(|creator| creator.is_author() == authors)
+                .map(|creator: &Creator| creator.name.to_owned())
+                .collect()
+        };
+        let (authors, contributors) = (names(true), names(false));
+        let asin = metadata
+            .dublin_core()
+            .into_iter()
+            .find(|entry| {
+                entry.name == "identifier"
+                    && !entry.value.is_empty()
+                    && entry.attributes.iter().any(|(name, value)| {
+                        local_name(name) == "scheme"
+                            && ASIN_SCHEMES
+                                .iter()
+                                .any(|scheme| scheme.eq_ignore_ascii_case(value))
+                    })
+            })
+            .map(|entry| entry.value);
+
+        for header in &mut self.headers {
+            header.full_name = title.to_owned();
+            header.set_strings(EXTH_TITLE, std::slice::from_ref(&title));
+            header.set_strings(EXTH_AUTHOR, &authors);
+            header.set_strings(EXTH_CONTRIBUTOR, &contributors);
+            for (name, kind) in EXTH_FIELDS {
+                header.set_strings(kind, &metadata.dc_values(name));
+            }
+            if let Some(asin) = asin.clone().or_else(|| {
+                EXTH_ASINS
+                    .iter()
+                    .find_map(|&kind| header.strings(kind).into_iter().next())
+            }) {
+                for kind in EXTH_ASINS {
+                    header.set_strings(kind, std::slice::from_ref(&asin));
+                }
+            }
+        }
+    }
+
+    /// Get the cover image of the book, if it has one
+    pub fn cover(&self) -> Option<&[u8]> {
+        let header = &self.headers[0];
+        let (start, end) = self.records[0];
+        let first_image = read_u32(&self.data[start..end], 108).ok()? as usize;
+        let cover_offset = header.number(EXTH_COVER_OFFSET)? as usize;
+        let (start, end) = *self.records.get(first_image.checked_add(cover_offset)?)?;
+
+        Some(&self.data[start..end])
+    }
+
+    /// Write the book back out with its edited MOBI headers
+    pub fn to_bytes(&self) -> color_eyre::Result<Vec<u8>> {
+        let first_record = self.records[0].0;
+        let mut data = self.data[..first_record].to_vec();
+        // The database name is limited to 31 characters of plain ASCII
+        let title = self.headers[0].full_name.to_owned();
+        let mut name: Vec<u8> = title
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c as u8
+                } else {
+                    b'_'
+                }
+            })
+            .take(31)
+            .collect();
+        name.resize(32, 0);
+        data[..32].copy_from_slice(&name);
+
+        for (i, &(start, end)) in self.records.iter().enumerate() {
+            let offset = data.len() as u32;
+            data[PDB_HEADER_LENGTH + i * 8..PDB_HEADER_LENGTH + i * 8 + 4]
+                .copy_from_slice(&offset.to_be_bytes());
+            match self.headers.iter().find(|header| header.record == i) {
+                Some(header) => {
+                    let record = header.write(&self.data[start..end])?;
+                    data.extend_from_slice(&record);
+                }
+                None => data.extend_from_slice(&self.data[start..end]),
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Read a big-endian `u16` at the given offset
+fn read_u16(data: &[u8], offset: usize) -> color_eyre::Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| eyre!("Unexpected end of data at {}", offset))
+}
+
+/// Read a big-endian `u32` at the given offset
+fn read_u32(data: &[u8], offset: usize) -> color_eyre::Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| eyre!("Unexpected end of data at {}", offset))
+}
+
+/// The characters of CP1252 from 0x80 to 0x9F, the rest match Latin-1
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Decode text in the book's encoding
+fn decode(data: &[u8], encoding: u32) -> String {
+    let data = data.split(|&byte| byte == 0).next().unwrap_or_default();
+    if encoding == UTF8_ENCODING {
+        return String::from_utf8_lossy(data).to_string();
+    }
+
+    data.iter()
+        .map(|&byte| match byte {
+            0x80..=0x9F => CP1252_HIGH[(byte - 0x80) as usize],
+            byte => byte as char,
+        })
+        .collect()
+}
+
+/// Encode text in the book's encoding, characters CP1252 doesn't have become `?`
+fn encode(text: &str, encoding: u32) -> Vec<u8> {
+    if encoding == UTF8_ENCODING {
+        return text.as_bytes().to_vec();
+    }
+
+    text.chars()
+        .map(|c| match c as u32 {
+            0..0x80 | 0xA0..0x100 => c as u8,
+            _ => CP1252_HIGH
+                .iter()
+                .position(|&high| high == c)
+                .map_or(b'?', |i| 0x80 + i as u8),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text encoding number of CP1252
+    const CP1252_ENCODING: u32 = 1252;
+
+    /// Build a record holding a PalmDOC header and a MOBI header followed by its EXTH
+    /// records and full name
+    fn header_record(full_name: &str, encoding: u32, exth: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let header_length = 232;
+        let mut record = vec![0; PALMDOC_HEADER_LENGTH + header_length];
+        record[16..20].copy_from_slice(b"MOBI");
+        record[20..24].copy_from_slice(&(header_length as u32).to_be_bytes());
+        record[28..32].copy_from_slice(&encoding.to_be_bytes());
+        // The first image is the third record of the book
+        record[108..112].copy_from_slice(&2u32.to_be_bytes());
+        record[128..132].copy_from_slice(&EXTH_FLAG.to_be_bytes());
+
+        let exth_length = 12 + exth.iter().map(|(_, data)| data.len() + 8).sum::<usize>();
+        record.extend_from_slice(b"EXTH");
+        record.extend_from_slice(&(exth_length as u32).to_be_bytes());
+        record.extend_from_slice(&(exth.len() as u32).to_be_bytes());
+        for (kind, data) in exth {
+            record.extend_from_slice(&kind.to_be_bytes());
+            record.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+            record.extend_from_slice(data);
+        }
+        record.resize(record.len().next_multiple_of(4), 0);
+
+        let full_name = encode(full_name, encoding);
+        let name_offset = record.len() as u32;
+        record[84..88].copy_from_slice(&name_offset.to_be_bytes());
+        record[88..92].copy_from_slice(&(full_name.len() as u32).to_be_bytes());
+        record.extend_from_slice(&full_name);
+        record.extend_from_slice(&[0, 0]);
+        record.resize(record.len().next_multiple_of(4), 0);
+
+        record
+    }
+
+    /// Build a Palm database holding the given records
+    fn palm_database(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; PDB_HEADER_LENGTH];
+        data[..4].copy_from_slice(b"Test");
+        data[60..68].copy_from_slice(b"BOOKMOBI");
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = PDB_HEADER_LENGTH + records.len() * 8 + 2;
+        for (i, record) in records.iter().enumerate() {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(i as u32 * 2).to_be_bytes());
+            offset += record.len();
+        }
+        data.extend_from_slice(&[0, 0]);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+
+        data
+    }
+
+    /// Build a book with a MOBI header, a text record and a cover image record
+    fn mobi_book(encoding: u32) -> Vec<u8> {
+        palm_database(&[
+            header_record(
+                "Foundation",
+                encoding,
+                &[
+                    (EXTH_TITLE, b"Foundation".to_vec()),
+                    (EXTH_AUTHOR, b"Isaac Asimov".to_vec()),
+                    (113, b"B000FC1PJI".to_vec()),
+                    (EXTH_COVER_OFFSET, 0u32.to_be_bytes().to_vec()),
+                    (300, b"unknown record".to_vec()),
+                ],
+            ),
+            b"<html>The text of the book</html>".to_vec(),
+            b"\x89PNG cover image".to_vec(),
+        ])
+    }
+
+    /// Get the bytes of every record of a book
+    fn records(book: &MobiBook) -> Vec<&[u8]> {
+        book.records
+            .iter()
+            .map(|&(start, end)| &book.data[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn metadata_round_trips_through_exth_records() {
+        let mut book = MobiBook::parse(mobi_book(UTF8_ENCODING)).unwrap();
+        let mut metadata = book.metadata().unwrap();
+        metadata.set_title("Foundation and Empire, the Second Book of the Series");
+        metadata.set_creators(&[
+            Creator {
+                name: String::from("Isaac Asimov"),
+                ..Creator::default()
+            },
+            Creator {
+                name: String::from("Jane Doe"),
+                role: Some(String::from("trl")),
+                file_as: None,
+            },
+        ]);
+        metadata.set_dc_values("publisher", &[String::from("Gnome Press")]);
+        metadata.set_dc_values(
+            "subject",
+            &[String::from("Science Fiction"), String::from("Space")],
+        );
+        book.set_metadata(&metadata);
+
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        let header = &edited.headers[0];
+        assert_eq!(
+            header.full_name,
+            "Foundation and Empire, the Second Book of the Series"
+        );
+        assert_eq!(header.strings(EXTH_TITLE), [header.full_name.to_owned()]);
+        assert_eq!(header.strings(EXTH_AUTHOR), ["Isaac Asimov"]);
+        assert_eq!(header.strings(EXTH_CONTRIBUTOR), ["Jane Doe"]);
+        assert_eq!(header.strings(101), ["Gnome Press"]);
+        assert_eq!(header.strings(105), ["Science Fiction", "Space"]);
+        assert_eq!(header.strings(113), ["B000FC1PJI"]);
+        assert_eq!(header.strings(504), ["B000FC1PJI"]);
+        assert_eq!(header.strings(300), ["unknown record"]);
+        assert_eq!(&edited.data[..32], b"Foundation_and_Empire__the_Seco\0");
+
+        let reread = edited.metadata().unwrap();
+        assert_eq!(reread.title(), metadata.title());
+        assert_eq!(reread.dc_values("publisher"), ["Gnome Press"]);
+        assert_eq!(edited.cover(), Some(&b"\x89PNG cover image"[..]));
+    }
+
+    #[test]
+    fn content_records_are_copied_as_they_are() {
+        let original = MobiBook::parse(mobi_book(UTF8_ENCODING)).unwrap();
+        let mut book = original.clone();
+        let mut metadata = book.metadata().unwrap();
+        metadata.set_title("A much longer title that moves the full name further along");
+        book.set_metadata(&metadata);
+
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        let (original_records, edited_records) = (records(&original), records(&edited));
+        assert_eq!(original_records.len(), edited_records.len());
+        assert_ne!(original_records[0], edited_records[0]);
+        assert_eq!(original_records[1..], edited_records[1..]);
+    }
+
+    #[test]
+    fn unchanged_metadata_leaves_the_records_as_they_are() {
+        let data = mobi_book(UTF8_ENCODING);
+        let mut book = MobiBook::parse(data.clone()).unwrap();
+        book.set_metadata(&book.metadata().unwrap());
+        // Only the header record changes, as the ASIN is copied to its second record
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            records(&edited)[1..],
+            records(&MobiBook::parse(data).unwrap())[1..]
+        );
+        assert_eq!(
+            edited.metadata().unwrap().title().as_deref(),
+            Some("Foundation")
+        );
+    }
+
+    #[test]
+    fn asin_is_set_from_an_amazon_identifier() {
+        let mut book = MobiBook::parse(mobi_book(UTF8_ENCODING)).unwrap();
+        let metadata = OpfDocument::from_metadata(&[
+            String::from("<dc:title>Foundation</dc:title>"),
+            String::from("<dc:identifier opf:scheme=\"ISBN\">9780553293357</dc:identifier>"),
+            String::from("<dc:identifier opf:scheme=\"amazon\">B00AAAAAAA</dc:identifier>"),
+        ])
+        .unwrap();
+        book.set_metadata(&metadata);
+
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        let header = &edited.headers[0];
+        assert_eq!(header.strings(113), ["B00AAAAAAA"]);
+        assert_eq!(header.strings(504), ["B00AAAAAAA"]);
+        let identifiers = edited.metadata().unwrap().dc_values("identifier");
+        assert!(identifiers.contains(&String::from("B00AAAAAAA")));
+    }
+
+    #[test]
+    fn kf8_header_of_a_combined_book_is_edited_too() {
+        let boundary = 3u32;
+        let data = palm_database(&[
+            header_record(
+                "Foundation",
+                UTF8_ENCODING,
+                &[(EXTH_KF8_BOUNDARY, (boundary + 1).to_be_bytes().to_vec())],
+            ),
+            b"MOBI text".to_vec(),
+            b"image".to_vec(),
+            b"BOUNDARY".to_vec(),
+            header_record("Foundation", UTF8_ENCODING, &[]),
+            b"KF8 text".to_vec(),
+        ]);
+        let mut book = MobiBook::parse(data).unwrap();
+        assert_eq!(book.headers.len(), 2);
+        let mut metadata = book.metadata().unwrap();
+        metadata.set_title("Second Foundation");
+        book.set_metadata(&metadata);
+
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        for header in &edited.headers {
+            assert_eq!(header.full_name, "Second Foundation");
+            assert_eq!(header.strings(EXTH_TITLE), ["Second Foundation"]);
+        }
+        assert_eq!(records(&edited)[5], b"KF8 text");
+    }
+
+    #[test]
+    fn cp1252_text_round_trips() {
+        let mut book = MobiBook::parse(mobi_book(CP1252_ENCODING)).unwrap();
+        let mut metadata = book.metadata().unwrap();
+        metadata.set_title("Café – “Quoted” €5");
+        book.set_metadata(&metadata);
+
+        let edited = MobiBook::parse(book.to_bytes().unwrap()).unwrap();
+        assert_eq!(edited.headers[0].full_name, "Café – “Quoted” €5");
+        assert_eq!(
+            edited.headers[0].exth[0].data,
+            b"Caf\xE9 \x96 \x93Quoted\x94 \x805".to_vec()
+        );
+    }
+
+    #[test]
+    fn characters_outside_cp1252_become_question_marks() {
+        assert_eq!(encode("Δx", CP1252_ENCODING), b"?x");
+        assert_eq!(decode(b"\x80\x99\0ignored", CP1252_ENCODING), "€™");
+    }
+}
@@ -1,41 +1,75 @@
 use std::{
     collections::{HashMap, HashSet},
-    ffi::OsStr,
-    fs::{File, canonicalize, read, read_dir},
-    io::{Read, Write},
+    env::current_dir,
+    fmt::{self, Display},
+    fs::{self, File, canonicalize, read_dir},
+    io::{BufReader, BufWriter, Read, Seek, Write},
     path::{Path, PathBuf},
 };
 
+use cli_log::*;
+use color_eyre::eyre::eyre;
+use image::DynamicImage;
 use ratatui::widgets::TableState;
-use regex::Regex;
-use tempfile::{TempDir, tempdir};
+use serde::{Deserialize, Serialize};
 use tui_widget_list::ListState;
-use walkdir::WalkDir;
 use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
 
+use crate::{
+    calibre::{Library, METADATA_OPF},
+    comic::{COMIC_INFO_PATH, COUNT_META, ComicInfo, VOLUME_META, is_comic_book},
+    container::{CONTAINER_PATH, Container},
+    cover::{CoverImage, CoverSource, cover_page},
+    creator::{Creator, first_author, format_creators, parse_creators},
+    detect::{BookInfo, Detection, detect_series, order_books},
+    fb2::{FictionBook, ZIPPED_FB2_EXTENSION, is_fictionbook, is_zipped_fictionbook},
+    format::Template,
+    manifest::{BookManifest, FieldValue, Manifest, SeriesManifest},
+    mobi::{MobiBook, is_kindle_book},
+    opf::{FieldChange, OpfDocument},
+    pdf::{PdfBook, is_pdf},
+    session::{SESSION_PATH, Session},
+    sidecar::{has_sidecar, sidecar_path, write_sidecar},
+    storage::{BackupMode, move_file, replace_file, sibling_temp_file, unique_path},
+};
+
+/// The name of the entry holding an epub's media type
+const MIMETYPE_PATH: &str = "mimetype";
+/// The media type of an epub
+const EPUB_MIMETYPE: &str = "application/epub+zip";
+/// The file extension of an epub
+const EPUB_EXTENSION: &str = "epub";
+
+/// The format string new book titles follow by default
+pub const DEFAULT_FORMAT: &str = "${series} (${position}) - ${title}";
+
+/// The file the series manifest is saved to when no manifest was loaded
+pub const DEFAULT_MANIFEST: &str = "series.toml";
+
 /// Enum of pages used in the app
 pub enum Page {
     /// Home page
     Home,
-    /// Quit page
-    Quit,
     /// Page for selecting the number of series to edit
     SeriesData,
     /// Page for selecting the files needed to be edited for that series
     FileSelection,
     /// Page for inputting the data for each book in the series
     BookData,
+    /// Page for reviewing the changes before any book is edited
+    Review,
     /// Loading page shown while metadata is being edited
     Loading,
 }
 
 impl Page {
     /// List of pages used for cycling through the app pages
-    pub const VALUES: [Self; 5] = [
+    pub const VALUES: [Self; 6] = [
         Self::Home,
         Self::SeriesData,
         Self::FileSelection,
         Self::BookData,
+        Self::Review,
         Self::Loading,
     ];
 }
@@ -69,8 +103,188 @@ impl FromIterator<PathBuf> for FileList {
 pub enum InputField {
     Series,
     Format,
+    PathFormat,
+    MetadataFields,
     BookOrder,
     BookTitle,
+    BookPosition,
+    BookAuthors,
+    /// The new cover of a book, an image file or a placeholder
+    BookCover,
+    /// The series-wide value of a Dublin Core field
+    Metadata(DcField),
+    /// The value of a Dublin Core field for a single book, overriding the series' value
+    BookMetadata(DcField),
+}
+
+impl InputField {
+    /// Get the fields holding a value for every book of a series
+    pub fn book_fields() -> Vec<InputField> {
+        let mut fields = vec![
+            InputField::BookTitle,
+            InputField::BookPosition,
+            InputField::BookAuthors,
+            InputField::BookCover,
+        ];
+        fields.extend(DcField::VALUES.map(InputField::BookMetadata));
+
+        fields
+    }
+}
+
+/// Enum of the Dublin Core fields that can be set for every book of a series, along with
+/// the volume of a comic
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DcField {
+    Publisher,
+    Language,
+    Subjects,
+    Rights,
+    Description,
+    Date,
+    /// The volume of a comic's series, which only comics have a place for
+    Volume,
+}
+
+impl DcField {
+    /// List of the fields in the order they are shown
+    pub const VALUES: [Self; 7] = [
+        Self::Publisher,
+        Self::Language,
+        Self::Subjects,
+        Self::Rights,
+        Self::Description,
+        Self::Date,
+        Self::Volume,
+    ];
+
+    /// Get the name the field is given in manifests and on the command line
+    pub fn key(&self) -> &'static str {
+        match self {
+            DcField::Publisher => "publisher",
+            DcField::Language => "language",
+            DcField::Subjects => "subjects",
+            DcField::Rights => "rights",
+            DcField::Description => "description",
+            DcField::Date => "date",
+            DcField::Volume => "volume",
+        }
+    }
+
+    /// Get the field with the given key
+    pub fn from_key(key: &str) -> Option<Self> {
+        DcField::VALUES
+            .into_iter()
+            .find(|field| field.key() == key.trim())
+    }
+
+    /// Whether the field is written to a Dublin Core element
+    pub fn is_dublin_core(&self) -> bool {
+        *self != DcField::Volume
+    }
+
+    /// Get the name of the Dublin Core element the field is written to
+    pub fn element(&self) -> &'static str {
+        match self {
+            DcField::Subjects => "subject",
+            _ => self.key(),
+        }
+    }
+
+    /// Split a value typed in for the field into the values of its elements, subjects being
+    /// separated by `;` with `\;` standing for a `;` within a subject
+    pub fn values(&self, value: &str) -> Vec<String> {
+        match self {
+            DcField::Subjects => {
+                let mut subjects = vec![String::new()];
+                let mut chars = value.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        // `\;` is a `;` within a subject
+                        '\\' if chars.peek() == Some(&';') => {
+                            chars.next();
+                            subjects.last_mut().unwrap().push(';');
+                        }
+                        ';' => subjects.push(String::new()),
+                        _ => subjects.last_mut().unwrap().push(c),
+                    }
+                }
+                subjects
+                    .iter()
+                    .map(|subject| subject.trim())
+                    .filter(|subject| !subject.is_empty())
+                    .map(String::from)
+                    .collect()
+            }
+            _ => vec![value.trim().to_string()],
+        }
+    }
+
+    /// Join the values of the field's elements into a value as it is typed in, the inverse
+    /// of [`DcField::values`]
+    pub fn join_values(&self, values: &[String]) -> String {
+        match self {
+            DcField::Subjects => values
+                .iter()
+                .map(|subject| subject.replace(';', "\\;"))
+                .collect::<Vec<String>>()
+                .join("; "),
+            _ => values.join("; "),
+        }
+    }
+
+    /// Check that a value typed in for the field can be written to its element
+    pub fn validate(&self, value: &str) -> color_eyre::Result<()> {
+        let value = value.trim();
+        let valid = match self {
+            // Language tags such as en, en-GB or zh-Hant
+            DcField::Language => {
+                let (language, subtags) = value.split_once('-').unwrap_or((value, ""));
+                (2..=3).contains(&language.len())
+                    && language.chars().all(|c| c.is_ascii_alphabetic())
+                    && (subtags.is_empty()
+                        || subtags.split('-').all(|subtag| {
+                            (1..=8).contains(&subtag.len())
+                                && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+                        }))
+            }
+            // Dates such as 1951, 1951-05 or 1951-05-01, optionally followed by a time
+            DcField::Date => {
+                let date = value.split('T').next().unwrap_or_default();
+                let parts: Vec<&str> = date.split('-').collect();
+                parts.len() <= 3
+                    && parts[0].len() == 4
+                    && parts[1..].iter().all(|part| part.len() == 2)
+                    && parts
+                        .iter()
+                        .all(|part| part.chars().all(|c| c.is_ascii_digit()))
+            }
+            DcField::Volume => value.chars().all(|c| c.is_ascii_digit()),
+            _ => true,
+        };
+        if value.is_empty() || valid {
+            Ok(())
+        } else {
+            Err(eyre!("Invalid {} \"{}\"", self.key(), value))
+        }
+    }
+}
+
+impl Display for DcField {
+    /// Write the field's name as it is shown in the app
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            DcField::Publisher => "Publisher",
+            DcField::Language => "Language",
+            DcField::Subjects => "Subjects (separated by ;)",
+            DcField::Rights => "Rights",
+            DcField::Description => "Description",
+            DcField::Date => "Date (YYYY-MM-DD)",
+            DcField::Volume => "Volume (comics only)",
+        };
+        write!(f, "{}", label)
+    }
 }
 
 /// Struct to hold input field data
@@ -87,6 +301,8 @@ pub struct Input {
     pub field_values: Vec<HashMap<InputField, Vec<String>>>,
     /// State of the Table of selected book
     pub file_table_states: Vec<TableState>,
+    /// State of the Table of Dublin Core fields
+    pub metadata_table_state: TableState,
 }
 
 impl Input {
@@ -99,10 +315,22 @@ impl Input {
             currently_editing: InputField::Series,
             field_values: Vec::new(),
             file_table_states: Vec::new(),
+            metadata_table_state: TableState::new(),
         }
     }
 }
 
+/// Enum of the covers shown in the cover previews, used as the key of the decoded images
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum CoverKey {
+    /// The current cover of a book
+    Book(PathBuf),
+    /// An image file
+    Image(PathBuf),
+    /// A placeholder cover for a series and position
+    Placeholder(String, String),
+}
+
 /// Struct holding the data for the app
 pub struct Model {
     /// Boolean representing whether the app is running or not
@@ -119,8 +347,36 @@ pub struct Model {
     pub all_field_values: Vec<HashMap<InputField, String>>,
     /// Hashset of the editied books
     pub finished_books: HashSet<PathBuf>,
+    /// The books that could not be edited, along with the reason
+    pub failed_books: HashMap<PathBuf, String>,
     /// Integer representing the index of the current book being edited
     pub current_book: usize,
+    /// How books are backed up before being overwritten
+    pub backup_mode: BackupMode,
+    /// Vec containing the metadata changes, or the error, for every selected book
+    pub previews: Vec<Result<Vec<FieldChange>, String>>,
+    /// State of the list of previews on the review page
+    pub review_state: ListState,
+    /// The path of the series manifest the inputs were loaded from
+    pub manifest_path: Option<PathBuf>,
+    /// The session saved by a previous run of the app that can be resumed
+    pub saved_session: Option<Session>,
+    /// The outcome of the last series detection pass or sidecar import
+    pub detection_summary: Option<String>,
+    /// The books whose position could not be worked out for certain by auto-order
+    pub ambiguous_books: HashSet<PathBuf>,
+    /// The metadata of the books read for the title previews, keyed by their path
+    pub title_metadata: HashMap<PathBuf, OpfDocument>,
+    /// Whether the covers of the highlighted books are shown
+    pub show_covers: bool,
+    /// The Calibre library being edited, whose books are listed in place of the current
+    /// directory's
+    pub library: Option<Library>,
+    /// The decoded covers shown in the cover previews, or the reason they can't be shown
+    cover_previews: HashMap<CoverKey, Result<DynamicImage, String>>,
+    /// The session last written to the session file, so that it is only written again
+    /// once it changes
+    last_session: Option<Session>,
 }
 
 impl Model {
@@ -134,12 +390,29 @@ impl Model {
             all_selected: Vec::new(),
             all_field_values: Vec::new(),
             finished_books: HashSet::new(),
+            failed_books: HashMap::new(),
             current_book: 0,
+            backup_mode: BackupMode::Sibling,
+            previews: Vec::new(),
+            review_state: ListState::default(),
+            manifest_path: None,
+            saved_session: None,
+            detection_summary: None,
+            ambiguous_books: HashSet::new(),
+            title_metadata: HashMap::new(),
+            show_covers: false,
+            library: None,
+            cover_previews: HashMap::new(),
+            last_session: None,
         }
     }
 
-    /// Generate the files for the current directory
+    /// Generate the files for the current directory, or the books of the Calibre library
+    /// when one is being edited
     pub fn get_current_file_list(&self, directory: PathBuf) -> Vec<PathBuf> {
+        if let Some(library) = &self.library {
+            return library.paths();
+        }
         let directory_contents: Vec<PathBuf> = read_dir(directory)
             .unwrap()
             .filter_map(|entry| entry.ok())
@@ -162,12 +435,8 @@ impl Model {
                 {
                     if entry.is_dir() {
                         directories.push(entry);
-                    } else if entry.is_file() {
-                        if let Some(extension) = entry.extension() {
-                            if extension == "epub" {
-                                files.push(entry);
-                            }
-                        }
+                    } else if entry.is_file() && is_book(&entry) {
+                        files.push(entry);
                     }
                 }
             }
@@ -180,117 +449,1647 @@ impl Model {
         files_list
     }
 
+    /// Collect the selected books and their inputs from every series, ready for editing
+    pub fn collect_books(&mut self) {
+        self.all_selected.clear();
+        self.all_field_values.clear();
+        self.finished_books.clear();
+        self.failed_books.clear();
+        self.current_book = 0;
+        for i in 0..self.inputs.file_lists.len() {
+            let file_list = &self.inputs.file_lists[i];
+            for position in 0..file_list.selected.len() {
+                let inputs = self.book_inputs(i, position);
+                self.all_field_values.push(inputs);
+            }
+
+            self.all_selected.extend(file_list.selected.iter().cloned());
+        }
+    }
+
+    /// Get the inputs for the book in the given row of a series
+    fn book_inputs(&self, series_index: usize, row: usize) -> HashMap<InputField, String> {
+        let field_values = &self.inputs.field_values[series_index];
+        let mut inputs = HashMap::from([
+            (
+                InputField::Format,
+                field_values[&InputField::Format][0].to_owned(),
+            ),
+            (InputField::BookOrder, row.to_string()),
+            (
+                InputField::BookTitle,
+                field_values[&InputField::BookTitle][row].to_owned(),
+            ),
+            (
+                InputField::BookPosition,
+                self.book_position(series_index, row),
+            ),
+            (
+                InputField::Series,
+                field_values[&InputField::Series][0].to_owned(),
+            ),
+        ]);
+        if let Some(authors) = field_values
+            .get(&InputField::BookAuthors)
+            .and_then(|authors| authors.get(row))
+        {
+            inputs.insert(InputField::BookAuthors, authors.to_owned());
+        }
+        for field in DcField::VALUES {
+            let book_value = field_values
+                .get(&InputField::BookMetadata(field))
+                .and_then(|values| values.get(row))
+                .filter(|value| !value.is_empty());
+            let series_value = field_values
+                .get(&InputField::Metadata(field))
+                .and_then(|values| values.first())
+                .filter(|value| !value.is_empty());
+            // A book's own value is used over the series' value
+            if let Some(value) = book_value.or(series_value) {
+                inputs.insert(InputField::Metadata(field), value.to_owned());
+            }
+        }
+        if let Some(path_format) = field_values
+            .get(&InputField::PathFormat)
+            .and_then(|values| values.first())
+            .filter(|path_format| !path_format.is_empty())
+        {
+            inputs.insert(InputField::PathFormat, path_format.to_owned());
+        }
+        if let Some(cover) = field_values
+            .get(&InputField::BookCover)
+            .and_then(|covers| covers.get(row))
+            .filter(|cover| !cover.trim().is_empty())
+        {
+            inputs.insert(InputField::BookCover, cover.to_owned());
+        }
+
+        inputs
+    }
+
+    /// Get the position in the series of the book in the given row of a series, which is
+    /// the row's number unless a position was entered for the book
+    pub fn book_position(&self, series_index: usize, row: usize) -> String {
+        self.inputs.field_values[series_index]
+            .get(&InputField::BookPosition)
+            .and_then(|positions| positions.get(row))
+            .filter(|position| !position.is_empty())
+            .cloned()
+            .unwrap_or_else(|| (row + 1).to_string())
+    }
+
+    /// Fill in the inputs for every page from a series manifest
+    pub fn load_manifest(&mut self, manifest: &Manifest) -> color_eyre::Result<()> {
+        if manifest.series.is_empty() {
+            return Err(eyre!("The manifest has no series"));
+        }
+        if manifest.series.len() > i8::MAX as usize {
+            return Err(eyre!("A manifest can have at most {} series", i8::MAX));
+        }
+
+        let current_files = self.get_current_file_list(PathBuf::from("./"));
+        self.inputs = Input::new();
+        self.inputs.series_num = manifest.series.len() as i8;
+        for series in &manifest.series {
+            let mut file_list = FileList::from_iter(current_files.clone());
+            let mut titles = Vec::new();
+            let mut positions = Vec::new();
+            let mut authors = Vec::new();
+            let mut covers = Vec::new();
+            let mut book_fields: HashMap<DcField, Vec<String>> = HashMap::new();
+            for book in &series.books {
+                let path = canonicalize(&book.path)
+                    .map_err(|err| eyre!("Unable to open {}: {}", book.path.display(), err))?;
+                let title = match &book.title {
+                    Some(title) => title.to_owned(),
+                    None => self.get_book_title(&path)?,
+                };
+                let creators = match self.manifest_creators(series, book, &path)? {
+                    Some(creators) => creators,
+                    None => self.get_book_creators(&path)?,
+                };
+                titles.push(title);
+                positions.push(book.position.clone().unwrap_or_default());
+                authors.push(format_creators(&creators));
+                covers.push(
+                    book.cover
+                        .clone()
+                        .or(series.cover.clone())
+                        .unwrap_or_default(),
+                );
+                for field in DcField::VALUES {
+                    book_fields.entry(field).or_default().push(
+                        book.fields
+                            .get(&field)
+                            .map(|value| value.to_input(field))
+                            .unwrap_or_default(),
+                    );
+                }
+                file_list.selected.push(path);
+            }
+
+            self.inputs.file_lists.push(file_list);
+            let mut field_values = HashMap::from([
+                (InputField::BookTitle, titles),
+                (InputField::BookPosition, positions),
+                (InputField::BookAuthors, authors),
+                (InputField::BookCover, covers),
+                (InputField::Series, vec![series.name.to_owned()]),
+                (InputField::Format, vec![series.format.to_owned()]),
+                (
+                    InputField::PathFormat,
+                    vec![series.path_format.clone().unwrap_or_default()],
+                ),
+            ]);
+            for field in DcField::VALUES {
+                field_values.insert(
+                    InputField::Metadata(field),
+                    vec![
+                        series
+                            .fields
+                            .get(&field)
+                            .map(|value| value.to_input(field))
+                            .unwrap_or_default(),
+                    ],
+                );
+                field_values.insert(
+                    InputField::BookMetadata(field),
+                    book_fields.remove(&field).unwrap_or_default(),
+                );
+            }
+            self.inputs.field_values.push(field_values);
+            self.inputs.file_table_states.push(TableState::new());
+        }
+
+        Ok(())
+    }
+
+    /// Create a series manifest from the inputs
+    ///
+    /// Unless all titles are kept only the titles that differ from the books' current titles
+    /// are written to the manifest.
+    pub fn to_manifest(&self, keep_all_titles: bool) -> Manifest {
+        let mut manifest = Manifest::default();
+        for (i, file_list) in self.inputs.file_lists.iter().enumerate() {
+            let field_values = &self.inputs.field_values[i];
+            let field_value = |field: InputField| {
+                field_values
+                    .get(&field)
+                    .and_then(|values| values.first())
+                    .cloned()
+            };
+
+            let books = file_list
+                .selected
+                .iter()
+                .enumerate()
+                .map(|(position, path)| {
+                    let title = field_values
+                        .get(&InputField::BookTitle)
+                        .and_then(|titles| titles.get(position))
+                        .filter(|title| {
+                            keep_all_titles
+                                || self.get_book_title(path).ok().as_ref() != Some(*title)
+                        })
+                        .cloned();
+                    let book_position = field_values
+                        .get(&InputField::BookPosition)
+                        .and_then(|positions| positions.get(position))
+                        .filter(|position| !position.is_empty())
+                        .cloned();
+                    let authors = field_values
+                        .get(&InputField::BookAuthors)
+                        .and_then(|authors| authors.get(position))
+                        .and_then(|authors| parse_creators(authors).ok())
+                        .filter(|authors| {
+                            keep_all_titles
+                                || self.get_book_creators(path).ok().as_ref() != Some(authors)
+                        });
+                    let fields = DcField::VALUES
+                        .into_iter()
+                        .filter_map(|field| {
+                            field_values
+                                .get(&InputField::BookMetadata(field))
+                                .and_then(|values| values.get(position))
+                                .filter(|value| !value.is_empty())
+                                .map(|value| (field, FieldValue::Text(value.to_owned())))
+                        })
+                        .collect();
+                    let cover = field_values
+                        .get(&InputField::BookCover)
+                        .and_then(|covers| covers.get(position))
+                        .filter(|cover| !cover.trim().is_empty())
+                        .cloned();
+                    BookManifest {
+                        path: path.to_owned(),
+                        title,
+                        position: book_position,
+                        authors,
+                        fields,
+                        cover,
+                    }
+                })
+                .collect();
+
+            manifest.series.push(SeriesManifest {
+                name: field_value(InputField::Series).unwrap_or_default(),
+                format: field_value(InputField::Format)
+                    .unwrap_or_else(|| DEFAULT_FORMAT.to_string()),
+                path_format: field_value(InputField::PathFormat)
+                    .filter(|path_format| !path_format.is_empty()),
+                authors: None,
+                normalize_authors: false,
+                fields: DcField::VALUES
+                    .into_iter()
+                    .filter_map(|field| {
+                        field_value(InputField::Metadata(field))
+                            .filter(|value| !value.is_empty())
+                            .map(|value| (field, FieldValue::Text(value)))
+                    })
+                    .collect(),
+                cover: None,
+                books,
+            });
+        }
+
+        manifest
+    }
+
+    /// Save the inputs to the loaded series manifest, or to a new one if none was loaded
+    pub fn save_manifest(&self) -> color_eyre::Result<()> {
+        let path = self
+            .manifest_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_MANIFEST));
+
+        self.to_manifest(false).save(&path)
+    }
+
+    /// Save the inputs and progress to the session file so they can be resumed later
+    ///
+    /// Nothing is saved before any series was set up or while the session is the same as
+    /// the one last saved, and the session is removed once every book has been edited.
+    pub fn save_session(&mut self) -> color_eyre::Result<()> {
+        if self.inputs.file_lists.is_empty() {
+            return Ok(());
+        }
+        if matches!(Page::VALUES[self.current_page], Page::Loading)
+            && self.finished_books.len() == self.all_selected.len()
+        {
+            self.last_session = None;
+            return Session::clear(Path::new(SESSION_PATH));
+        }
+
+        let mut finished_books: Vec<PathBuf> = self.finished_books.iter().cloned().collect();
+        finished_books.sort();
+        let session = Session {
+            current_page: self.current_page,
+            series_num: self.inputs.series_num,
+            current_series_num: self.inputs.current_series_num,
+            series: self.to_manifest(true).series,
+            finished_books,
+        };
+        if self.last_session.as_ref() == Some(&session) {
+            return Ok(());
+        }
+        session.save(Path::new(SESSION_PATH))?;
+        self.last_session = Some(session);
+
+        Ok(())
+    }
+
+    /// Restore the inputs and progress of a saved session
+    pub fn resume_session(&mut self, session: Session) -> color_eyre::Result<()> {
+        if session.series.is_empty() {
+            return Err(eyre!("The saved session has no series"));
+        }
+        self.load_manifest(&Manifest {
+            series: session.series,
+        })?;
+        self.inputs.series_num = session.series_num.max(self.inputs.series_num);
+        self.inputs.current_series_num = session
+            .current_series_num
+            .min(self.inputs.file_lists.len() - 1);
+        self.current_page = session.current_page.min(Page::VALUES.len() - 1);
+
+        if matches!(
+            Page::VALUES[self.current_page],
+            Page::Review | Page::Loading
+        ) {
+            self.collect_books();
+            self.preview_all();
+            self.finished_books = session
+                .finished_books
+                .into_iter()
+                .filter(|book| self.all_selected.contains(book))
+                .collect();
+        }
+
+        Ok(())
+    }
+
     /// Given a reference to a book's path edit the metadata based on the inputs given
+    ///
+    /// Only the package documents are re-encoded, every other entry of the epub is copied
+    /// over as is without being decompressed.
+    ///
+    /// Kindle books have their EXTH records rewritten instead, leaving the records of the
+    /// book's content as they are.
+    ///
+    /// When a file path format is set the book is then moved to the path it gives, and books
+    /// of a Calibre library have their row of its database and their `metadata.opf` edited.
     pub fn edit_epub(&mut self, epub_path: &PathBuf) -> color_eyre::Result<()> {
-        let temp_dir = self.prep_epub(epub_path)?;
-        let (mut meta_file, mut metadata) = self.get_metadata(temp_dir.path())?;
-        metadata = self.edit_metadata(metadata)?;
-        meta_file.write_all(metadata.as_bytes())?;
-        self.repackage_epub(temp_dir.path(), PathBuf::from(epub_path))?;
-
-        temp_dir.close()?;
+        self.check_library_book(epub_path)?;
+        let cover = self.new_cover(self.current_book)?;
+        if !is_epub(epub_path) && cover.is_some() {
+            return Err(eyre!("Covers can only be replaced in epub books"));
+        }
+        let new_path = if is_kindle_book(epub_path) {
+            self.edit_kindle_book(epub_path)?
+        } else if is_pdf(epub_path) {
+            self.edit_pdf_book(epub_path)?
+        } else if is_comic_book(epub_path) {
+            self.edit_comic_book(epub_path)?
+        } else if is_fictionbook(epub_path) {
+            self.edit_fictionbook(epub_path)?
+        } else {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(epub_path)?))?;
+            let packages = self.get_metadata(&mut archive)?;
+            let new_path = match packages.first() {
+                Some((_, metadata)) => self.new_book_path(self.current_book, metadata)?,
+                None => None,
+            };
+            let mut edited_entries: HashMap<String, Vec<u8>> = HashMap::new();
+            for (meta_path, metadata) in packages {
+                let mut metadata = self.edit_metadata(metadata, self.current_book)?;
+                if let Some(cover) = &cover {
+                    self.replace_cover(&mut metadata, &meta_path, cover, &mut edited_entries)?;
+                }
+                edited_entries.insert(meta_path, metadata.to_string().into_bytes());
+            }
+            self.repackage_book(&mut archive, edited_entries, epub_path)?;
+            new_path
+        };
+        if self.library.is_some() {
+            self.edit_library_book(epub_path)?;
+        }
 
-        self.finished_books.insert(epub_path.to_owned());
+        let epub_path = match new_path {
+            Some(new_path) => self.move_book(epub_path, &new_path)?,
+            None => epub_path.to_owned(),
+        };
+        self.finished_books.insert(epub_path);
         self.current_book += 1;
         self.current_book = self.current_book.clamp(0, self.all_selected.len() - 1);
 
         Ok(())
     }
 
-    /// Generate the temperary zip file for the epub's files
-    fn prep_epub(&self, epub_path: &PathBuf) -> color_eyre::Result<TempDir> {
-        let temp_dir = tempdir()?;
+    /// Edit the metadata of a Kindle book based on the inputs given, giving the path the
+    /// file path format gives it if one is set
+    fn edit_kindle_book(&self, book_path: &Path) -> color_eyre::Result<Option<PathBuf>> {
+        let mut book = MobiBook::read(book_path)?;
+        let metadata = book.metadata()?;
+        let new_path = self.new_book_path(self.current_book, &metadata)?;
+        book.set_metadata(&self.edit_metadata(metadata, self.current_book)?);
+
+        let mut temp_file = sibling_temp_file(book_path)?;
+        temp_file.write_all(&book.to_bytes()?)?;
+        replace_file(temp_file, book_path, &self.backup_mode)?;
+
+        Ok(new_path)
+    }
+
+    /// Edit the metadata of a PDF based on the inputs given, appending the edited objects as
+    /// an incremental update, and giving the path the file path format gives it if one is set
+    fn edit_pdf_book(&self, book_path: &Path) -> color_eyre::Result<Option<PathBuf>> {
+        let mut book = PdfBook::read(book_path)?;
+        let metadata = book.metadata(&file_stem(book_path))?;
+        let new_path = self.new_book_path(self.current_book, &metadata)?;
+        book.set_metadata(&self.edit_metadata(metadata, self.current_book)?)?;
+
+        let mut temp_file = sibling_temp_file(book_path)?;
+        temp_file.write_all(&book.save()?)?;
+        replace_file(temp_file, book_path, &self.backup_mode)?;
+
+        Ok(new_path)
+    }
+
+    /// Edit the `ComicInfo.xml` of a comic based on the inputs given, creating it when the
+    /// comic doesn't have one, and giving the path the file path format gives the comic if
+    /// one is set
+    fn edit_comic_book(&self, book_path: &Path) -> color_eyre::Result<Option<PathBuf>> {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(book_path)?))?;
+        let (entry, mut comic_info) = self.get_comic_info(&mut archive)?;
+        let metadata = comic_info.metadata(&file_stem(book_path))?;
+        let new_path = self.new_book_path(self.current_book, &metadata)?;
+        comic_info.set_metadata(&self.edit_comic_metadata(metadata, self.current_book)?);
+        let edited_entries = HashMap::from([(entry, comic_info.to_string().into_bytes())]);
+        self.repackage_book(&mut archive, edited_entries, book_path)?;
+
+        Ok(new_path)
+    }
+
+    /// Edit the description of a FictionBook based on the inputs given, giving the path the
+    /// file path format gives it if one is set
+    ///
+    /// Zipped FictionBooks are repackaged with the edited book replacing the original one.
+    fn edit_fictionbook(&self, book_path: &Path) -> color_eyre::Result<Option<PathBuf>> {
+        let (entry, mut book) = self.read_fictionbook(book_path)?;
+        let metadata = book.metadata(&file_stem(book_path))?;
+        let new_path = self.new_book_path(self.current_book, &metadata)?;
+        book.set_metadata(&self.edit_metadata(metadata, self.current_book)?);
+
+        if is_zipped_fictionbook(book_path) {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(book_path)?))?;
+            let edited_entries = HashMap::from([(entry, book.to_bytes())]);
+            self.repackage_book(&mut archive, edited_entries, book_path)?;
+        } else {
+            let mut temp_file = sibling_temp_file(book_path)?;
+            temp_file.write_all(&book.to_bytes())?;
+            replace_file(temp_file, book_path, &self.backup_mode)?;
+        }
+
+        Ok(new_path)
+    }
+
+    /// Check that a book belongs to the Calibre library being edited, if there is one
+    fn check_library_book(&self, book_path: &Path) -> color_eyre::Result<()> {
+        match &self.library {
+            Some(library) if library.book(book_path).is_none() => Err(eyre!(
+                "{} is not a book of the Calibre library {}",
+                display_path(book_path),
+                library.directory.display()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the edited title and series of a Calibre library book to the library's
+    /// database, and its edited metadata to the `metadata.opf` kept next to its files
+    ///
+    /// Books without a `metadata.opf` are given one made from the edited book's metadata.
+    /// The `metadata.opf` is only replaced once the database has been written, so a failed
+    /// transaction leaves both as they were.
+    fn edit_library_book(&mut self, book_path: &Path) -> color_eyre::Result<()> {
+        let opf_path = book_path.with_file_name(METADATA_OPF);
+        let metadata = if opf_path.is_file() {
+            OpfDocument::parse(&fs::read_to_string(&opf_path)?)?
+        } else {
+            self.get_book_metadata(book_path)?
+                .into_iter()
+                .next()
+                .map(|(_, metadata)| metadata)
+                .ok_or_else(|| eyre!("{} has no metadata", display_path(book_path)))?
+        };
+        let metadata = self.edit_metadata(metadata, self.current_book)?;
+
+        let mut temp_file = sibling_temp_file(&opf_path)?;
+        temp_file.write_all(metadata.to_string().as_bytes())?;
+        if let Some(library) = &mut self.library {
+            library.update_book(book_path, &metadata)?;
+        }
+        replace_file(temp_file, &opf_path, &BackupMode::Disabled)?;
+
+        Ok(())
+    }
+
+    /// Move an edited book to a new path, along with its backup and sidecar, and keep track
+    /// of it under its new path, giving the path it ended up at
+    fn move_book(&mut self, epub_path: &Path, new_path: &Path) -> color_eyre::Result<PathBuf> {
+        let current_path = canonicalize(epub_path)?;
+        let new_path = unique_path(new_path, &current_path);
+        if new_path == current_path {
+            return Ok(epub_path.to_path_buf());
+        }
+
+        move_file(epub_path, &new_path)?;
+        if let Some(backup_path) = self.backup_mode.backup_path(epub_path)
+            && backup_path.is_file()
+            && let Some(new_backup_path) = self.backup_mode.backup_path(&new_path)
+            && !new_backup_path.exists()
+        {
+            move_file(&backup_path, &new_backup_path)?;
+        }
+        if has_sidecar(epub_path) && !has_sidecar(&new_path) {
+            move_file(&sidecar_path(epub_path), &sidecar_path(&new_path))?;
+        }
+        info!(
+            "Moved \"{}\" to \"{}\"",
+            epub_path.display(),
+            new_path.display()
+        );
+
+        for book in self
+            .all_selected
+            .iter_mut()
+            .chain(
+                self.inputs
+                    .file_lists
+                    .iter_mut()
+                    .flat_map(|list| list.selected.iter_mut()),
+            )
+            .filter(|book| book.as_path() == epub_path)
+        {
+            *book = new_path.to_owned();
+        }
 
-        let file = File::open(epub_path)?;
-        let mut archive = ZipArchive::new(&file)?;
-        archive.extract(&temp_dir)?;
+        Ok(new_path)
+    }
 
-        Ok(temp_dir)
+    /// Get the current title of a book from its metadata, or from the Calibre library's
+    /// database for books of the library being edited
+    pub fn get_book_title(&self, epub_path: &Path) -> color_eyre::Result<String> {
+        if let Some(book) = self
+            .library
+            .as_ref()
+            .and_then(|library| library.book(epub_path))
+        {
+            return Ok(book.title.to_owned());
+        }
+        self.get_book_metadata(epub_path)?
+            .into_iter()
+            .find_map(|(_, metadata)| metadata.title())
+            .ok_or_else(|| eyre!("{} has no title", epub_path.display()))
     }
 
-    /// Get the epub's content.opf and metadata
-    fn get_metadata(&self, temp_dir: &Path) -> color_eyre::Result<(File, String)> {
-        let extracted_files = WalkDir::new(temp_dir)
+    /// Get the current creators of a book from its metadata
+    pub fn get_book_creators(&self, epub_path: &Path) -> color_eyre::Result<Vec<Creator>> {
+        Ok(self
+            .get_book_metadata(epub_path)?
             .into_iter()
-            .filter_map(|entry| entry.ok());
-        for extracted_file in extracted_files {
-            if extracted_file.path().file_name().unwrap_or_default() == "content.opf" {
-                let mut content = String::new();
-                File::open(extracted_file.path())
-                    .unwrap()
-                    .read_to_string(&mut content)?;
-                return Ok((File::create(extracted_file.into_path())?, content));
-            }
+            .next()
+            .map(|(_, metadata)| metadata.creators())
+            .unwrap_or_default())
+    }
+
+    /// Get the current cover image of a book, if it has one
+    ///
+    /// Comics have no cover of their own, so their first page is used. PDFs have no cover
+    /// image to show.
+    pub fn get_book_cover(&self, epub_path: &Path) -> color_eyre::Result<Option<CoverImage>> {
+        if is_pdf(epub_path) {
+            return Ok(None);
+        }
+        if is_comic_book(epub_path) {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(epub_path)?))?;
+            let mut pages: Vec<String> = archive
+                .file_names()
+                .filter(|name| image::ImageFormat::from_path(name).is_ok())
+                .map(String::from)
+                .collect();
+            pages.sort();
+            let Some(page) = pages.first() else {
+                return Ok(None);
+            };
+            let mut data = Vec::new();
+            archive.by_name(page)?.read_to_end(&mut data)?;
+            let format = image::guess_format(&data)
+                .map_err(|_| eyre!("Unsupported cover image {}", page))?;
+
+            return Ok(Some(CoverImage { data, format }));
+        }
+        if is_kindle_book(epub_path) {
+            let book = MobiBook::read(epub_path)?;
+            let Some(data) = book.cover() else {
+                return Ok(None);
+            };
+            let format = image::guess_format(data).map_err(|_| eyre!("Unsupported cover image"))?;
+
+            return Ok(Some(CoverImage {
+                data: data.to_vec(),
+                format,
+            }));
         }
+        if is_fictionbook(epub_path) {
+            let (_, book) = self.read_fictionbook(epub_path)?;
+            let Some(data) = book.cover()? else {
+                return Ok(None);
+            };
+            let format =
+                image::guess_format(&data).map_err(|_| eyre!("Unsupported cover image"))?;
 
-        Err(color_eyre::eyre::eyre!("content.opf not found"))
+            return Ok(Some(CoverImage { data, format }));
+        }
+
+        let mut archive = ZipArchive::new(BufReader::new(File::open(epub_path)?))?;
+        let Some((meta_path, metadata)) = self.get_metadata(&mut archive)?.into_iter().next()
+        else {
+            return Ok(None);
+        };
+        let Some(item) = metadata.cover_image() else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        archive
+            .by_name(&package_entry(&meta_path, &item.href))?
+            .read_to_end(&mut data)?;
+        let format = image::guess_format(&data)
+            .map_err(|_| eyre!("Unsupported cover image {}", item.href))?;
+
+        Ok(Some(CoverImage { data, format }))
     }
 
-    /// Edit the metadata based on the inputs given
-    fn edit_metadata(&self, mut metadata: String) -> color_eyre::Result<String> {
-        let current_book_inputs = &self.all_field_values[self.current_book];
-        if let Some(format_string) = current_book_inputs.get(&InputField::Format) {
-            let format_string = format_string.as_str();
-            let position = &format!(
-                "{:0>2}",
-                (&current_book_inputs[&InputField::BookOrder].parse::<u32>()? + 1).to_string()
-            );
-            let title = &current_book_inputs[&InputField::BookTitle];
-            let series = &current_book_inputs[&InputField::Series];
-            let title_re = Regex::new(r#"(<.*(title|meta).*>)(.+)(</.*(title|meta).*>)"#)?;
-            let sort_re = Regex::new(r#"(title_sort.*content=")(.*)("/>)"#)?;
-            let substitutions =
-                HashMap::from([("position", position), ("title", title), ("series", series)]);
-            let formatted_string = subst::substitute(format_string, &substitutions)?;
-
-            metadata = title_re
-                .replace_all(&metadata, |caps: &regex::Captures| {
-                    format!("{}{}{}", &caps[1], formatted_string, &caps[4])
+    /// Get the creators a manifest gives a book, or none if the book's creators are left
+    /// as they are
+    ///
+    /// The book's own creators are used first, then the series' creators, and when the
+    /// series normalises its creators the book's current creators are normalised instead.
+    pub fn manifest_creators(
+        &self,
+        series: &SeriesManifest,
+        book: &BookManifest,
+        epub_path: &Path,
+    ) -> color_eyre::Result<Option<Vec<Creator>>> {
+        let creators = match book.authors.as_ref().or(series.authors.as_ref()) {
+            Some(creators) => creators.to_owned(),
+            None if series.normalize_authors => self.get_book_creators(epub_path)?,
+            None => return Ok(None),
+        };
+        if !series.normalize_authors {
+            return Ok(Some(creators));
+        }
+
+        Ok(Some(creators.iter().map(Creator::normalized).collect()))
+    }
+
+    /// Get the title and series information of a book for series detection
+    pub fn get_book_info(&self, epub_path: &Path) -> color_eyre::Result<BookInfo> {
+        let metadata = self.get_book_metadata(epub_path)?;
+        let (series, position) = metadata
+            .iter()
+            .find_map(|(_, metadata)| metadata.series())
+            .map_or((None, None), |(series, position)| (Some(series), position));
+
+        Ok(BookInfo {
+            path: epub_path.to_owned(),
+            title: metadata.iter().find_map(|(_, metadata)| metadata.title()),
+            series,
+            position,
+            date: metadata
+                .iter()
+                .find_map(|(_, metadata)| metadata.dc_value("date")),
+        })
+    }
+
+    /// Write the current metadata of a book to its OPF sidecar, returning the path of the
+    /// sidecar
+    ///
+    /// Books with more than one package document have their first package written.
+    pub fn export_sidecar(&self, epub_path: &Path) -> color_eyre::Result<PathBuf> {
+        let (_, metadata) = self
+            .get_book_metadata(epub_path)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("{} has no metadata", epub_path.display()))?;
+
+        write_sidecar(epub_path, &metadata.metadata_only())
+    }
+
+    /// Propose the series of every book in the directory from their metadata, filenames
+    /// and titles
+    pub fn detect_series(&self, directory: PathBuf) -> Detection {
+        let books = self
+            .get_current_file_list(directory)
+            .into_iter()
+            .filter(|path| path.is_file())
+            .map(|path| {
+                self.get_book_info(&path).unwrap_or_else(|err| {
+                    warn!("Unable to read {}: {}", path.display(), err);
+                    BookInfo {
+                        path,
+                        ..BookInfo::default()
+                    }
                 })
-                .to_string();
+            })
+            .collect();
+
+        detect_series(books)
+    }
 
-            metadata = sort_re
-                .replace_all(&metadata, |caps: &regex::Captures| {
-                    format!("{}{}{}", &caps[1], formatted_string, &caps[3])
+    /// Sort the books of a series by their series index, the numbers in their filenames or
+    /// titles, or their publication date, marking the books whose position is ambiguous
+    pub fn auto_order(&mut self, series_index: usize) {
+        let books: Vec<BookInfo> = self.inputs.file_lists[series_index]
+            .selected
+            .iter()
+            .map(|path| {
+                self.get_book_info(path).unwrap_or_else(|err| {
+                    warn!("Unable to read {}: {}", path.display(), err);
+                    BookInfo {
+                        path: path.to_owned(),
+                        ..BookInfo::default()
+                    }
                 })
-                .to_string();
+            })
+            .collect();
+        let proposed = order_books(&books);
+
+        let selected = &mut self.inputs.file_lists[series_index].selected;
+        for path in selected.iter() {
+            self.ambiguous_books.remove(path);
+        }
+        for &i in &proposed.ambiguous {
+            self.ambiguous_books.insert(selected[i].to_owned());
+        }
+        *selected = proposed
+            .order
+            .iter()
+            .map(|&i| selected[i].to_owned())
+            .collect();
+        for field in InputField::book_fields() {
+            if let Some(values) = self.inputs.field_values[series_index].get_mut(&field)
+                && values.len() == proposed.order.len()
+            {
+                *values = proposed
+                    .order
+                    .iter()
+                    .map(|&i| values[i].to_owned())
+                    .collect();
+            }
         }
+    }
 
-        Ok(metadata)
+    /// Work out the changes editing a book would make to its metadata without writing
+    /// anything to disk
+    pub fn preview_epub(&self, book_index: usize) -> color_eyre::Result<Vec<FieldChange>> {
+        let epub_path = &self.all_selected[book_index];
+        self.check_library_book(epub_path)?;
+        let mut changes = Vec::new();
+        let packages = self.get_book_metadata(epub_path)?;
+        if let Some((_, metadata)) = packages.first()
+            && let Some(new_path) = self.new_book_path(book_index, metadata)?
+        {
+            let current_path = canonicalize(epub_path)?;
+            let new_path = unique_path(&new_path, &current_path);
+            if new_path != current_path {
+                changes.push(FieldChange {
+                    field: String::from("file"),
+                    before: Some(display_path(&current_path)),
+                    after: Some(display_path(&new_path)),
+                });
+            }
+        }
+        let cover = self.new_cover(book_index)?;
+        if let Some(source) = self.all_field_values[book_index].get(&InputField::BookCover) {
+            if !is_epub(epub_path) {
+                return Err(eyre!("Covers can only be replaced in epub books"));
+            }
+            changes.push(FieldChange {
+                field: String::from("cover image"),
+                before: packages
+                    .first()
+                    .and_then(|(_, metadata)| metadata.cover_image())
+                    .map(|item| item.href),
+                after: Some(source.trim().to_string()),
+            });
+        }
+        if is_kindle_book(epub_path) {
+            // Only the changes the EXTH records can hold are shown
+            let mut book = MobiBook::read(epub_path)?;
+            for (_, metadata) in packages {
+                book.set_metadata(&self.edit_metadata(metadata.clone(), book_index)?);
+                changes.append(&mut metadata.diff(&book.metadata()?));
+            }
+
+            return Ok(changes);
+        }
+        if is_pdf(epub_path) {
+            // Only the changes the information dictionary and XMP packet can hold are shown
+            let mut book = PdfBook::read(epub_path)?;
+            for (_, metadata) in packages {
+                book.set_metadata(&self.edit_metadata(metadata.clone(), book_index)?)?;
+                changes.append(&mut metadata.diff(&book.metadata(&file_stem(epub_path))?));
+            }
+
+            return Ok(changes);
+        }
+        if is_comic_book(epub_path) {
+            // Only the changes the ComicInfo file can hold are shown
+            let mut archive = ZipArchive::new(BufReader::new(File::open(epub_path)?))?;
+            let (_, mut comic_info) = self.get_comic_info(&mut archive)?;
+            for (_, metadata) in packages {
+                comic_info.set_metadata(&self.edit_comic_metadata(metadata.clone(), book_index)?);
+                changes.append(&mut metadata.diff(&comic_info.metadata(&file_stem(epub_path))?));
+            }
+
+            return Ok(changes);
+        }
+        if is_fictionbook(epub_path) {
+            // Only the changes the FictionBook's description can hold are shown
+            let (_, mut book) = self.read_fictionbook(epub_path)?;
+            for (_, metadata) in packages {
+                book.set_metadata(&self.edit_metadata(metadata.clone(), book_index)?);
+                changes.append(&mut metadata.diff(&book.metadata(&file_stem(epub_path))?));
+            }
+
+            return Ok(changes);
+        }
+        for (meta_path, metadata) in packages {
+            let mut edited = self.edit_metadata(metadata.clone(), book_index)?;
+            if let Some(cover) = &cover {
+                self.replace_cover(&mut edited, &meta_path, cover, &mut HashMap::new())?;
+            }
+            changes.append(&mut metadata.diff(&edited));
+        }
+
+        Ok(changes)
+    }
+
+    /// Generate the previews of the changes for every selected book
+    pub fn preview_all(&mut self) {
+        self.previews = (0..self.all_selected.len())
+            .map(|i| self.preview_epub(i).map_err(|err| err.to_string()))
+            .collect();
+        self.review_state = ListState::default();
     }
 
-    /// Repackage the epub's files into an epub with the new metadata
-    fn repackage_epub(&self, temp_dir: &Path, output_path: PathBuf) -> color_eyre::Result<()> {
-        let temp_file = File::create(output_path)?;
-        let mut zip = ZipWriter::new(temp_file);
+    /// Render the title the format string gives the book in the given row of a series,
+    /// reading the book's metadata once and keeping it for the following renders
+    pub fn preview_title(&mut self, series_index: usize, row: usize) -> color_eyre::Result<String> {
+        let (inputs, epub_path, total) = self.preview_inputs(series_index, row)?;
+        parse_position(&inputs[&InputField::BookPosition])?;
 
-        zip.start_file("mimetype", SimpleFileOptions::default())?;
-        zip.write_all("application/epub+zip".as_bytes())?;
+        format_title(&inputs, &epub_path, total, &self.title_metadata[&epub_path])
+    }
 
-        for file in WalkDir::new(temp_dir)
-            .into_iter()
-            .filter_map(|entry| entry.ok())
+    /// Render the path the file path format gives the book in the given row of a series, if
+    /// one is set
+    pub fn preview_path(
+        &mut self,
+        series_index: usize,
+        row: usize,
+    ) -> color_eyre::Result<Option<PathBuf>> {
+        let (inputs, epub_path, total) = self.preview_inputs(series_index, row)?;
+        if !inputs.contains_key(&InputField::PathFormat) {
+            return Ok(None);
+        }
+
+        Ok(Some(format_path(
+            &inputs,
+            &epub_path,
+            total,
+            &self.title_metadata[&epub_path],
+        )?))
+    }
+
+    /// Get the current cover of a book for the cover preview, decoding it once and keeping
+    /// it for the following renders
+    pub fn preview_cover(&mut self, epub_path: &Path) -> &Result<DynamicImage, String> {
+        let key = CoverKey::Book(epub_path.to_owned());
+        if !self.cover_previews.contains_key(&key) {
+            let cover = self
+                .get_book_cover(epub_path)
+                .and_then(|cover| cover.ok_or_else(|| eyre!("No cover")))
+                .and_then(|cover| cover.decode());
+            self.cache_cover_preview(key.clone(), cover);
+        }
+
+        &self.cover_previews[&key]
+    }
+
+    /// Get the cover the book in the given row of a series will have for the cover preview,
+    /// which is its new cover when one was given and its current cover otherwise
+    pub fn preview_book_cover(
+        &mut self,
+        series_index: usize,
+        row: usize,
+    ) -> &Result<DynamicImage, String> {
+        let inputs = self.book_inputs(series_index, row);
+        let series = inputs[&InputField::Series].to_owned();
+        let position = parse_position(&inputs[&InputField::BookPosition])
+            .map(|position| position.to_string())
+            .unwrap_or_else(|_| inputs[&InputField::BookPosition].to_owned());
+        let source = inputs
+            .get(&InputField::BookCover)
+            .and_then(|cover| CoverSource::parse(cover));
+        let key = match &source {
+            Some(CoverSource::Image(path)) => CoverKey::Image(path.to_owned()),
+            Some(CoverSource::Placeholder) => {
+                CoverKey::Placeholder(series.clone(), position.clone())
+            }
+            None => {
+                let epub_path = self.inputs.file_lists[series_index].selected[row].to_owned();
+                return self.preview_cover(&epub_path);
+            }
+        };
+        if !self.cover_previews.contains_key(&key) {
+            let cover = source
+                .unwrap()
+                .load(&series, &position)
+                .and_then(|cover| cover.decode());
+            self.cache_cover_preview(key.clone(), cover);
+        }
+
+        &self.cover_previews[&key]
+    }
+
+    /// Keep a decoded cover for the cover previews, shrunk down to save memory
+    fn cache_cover_preview(&mut self, key: CoverKey, cover: color_eyre::Result<DynamicImage>) {
+        let cover = cover
+            .map(|cover| cover.thumbnail(200, 300))
+            .map_err(|err| err.to_string());
+        self.cover_previews.insert(key, cover);
+    }
+
+    /// Get the inputs, path and number of books in the series of the book in the given row
+    /// of a series for previewing, making sure the book's metadata has been read
+    fn preview_inputs(
+        &mut self,
+        series_index: usize,
+        row: usize,
+    ) -> color_eyre::Result<(HashMap<InputField, String>, PathBuf, usize)> {
+        let epub_path = self.inputs.file_lists[series_index].selected[row].to_owned();
+        if !self.title_metadata.contains_key(&epub_path) {
+            let (_, metadata) = self
+                .get_book_metadata(&epub_path)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| eyre!("{} has no package document", epub_path.display()))?;
+            self.title_metadata.insert(epub_path.to_owned(), metadata);
+        }
+
+        let series = &self.inputs.field_values[series_index][&InputField::Series][0];
+        let total = self
+            .inputs
+            .field_values
+            .iter()
+            .zip(&self.inputs.file_lists)
+            .filter(|(field_values, _)| &field_values[&InputField::Series][0] == series)
+            .map(|(_, file_list)| file_list.selected.len())
+            .sum();
+
+        Ok((self.book_inputs(series_index, row), epub_path, total))
+    }
+
+    /// Read an entry of the epub's archive as a string
+    fn read_entry<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        name: &str,
+    ) -> color_eyre::Result<String> {
+        let mut content = String::new();
+        archive.by_name(name)?.read_to_string(&mut content)?;
+
+        Ok(content)
+    }
+
+    /// Get the entry name and parsed metadata of the package document of every rendition
+    /// listed in the epub's container
+    fn get_metadata<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+    ) -> color_eyre::Result<Vec<(String, OpfDocument)>> {
+        if archive.index_for_name(CONTAINER_PATH).is_none() {
+            return Err(eyre!("{} not found", CONTAINER_PATH));
+        }
+        let container = Container::parse(&self.read_entry(archive, CONTAINER_PATH)?)?;
+
+        let mut packages = Vec::new();
+        for package_path in container.package_paths()? {
+            let meta_path = package_path.to_string_lossy().replace('\\', "/");
+            if archive.index_for_name(&meta_path).is_none() {
+                return Err(eyre!(
+                    "Package document {} listed in {} not found",
+                    meta_path,
+                    CONTAINER_PATH
+                ));
+            }
+            let content = self.read_entry(archive, &meta_path)?;
+            let metadata = OpfDocument::parse(&content)
+                .map_err(|err| eyre!("Invalid {}: {}", meta_path, err))?;
+            packages.push((meta_path, metadata));
+        }
+
+        Ok(packages)
+    }
+
+    /// Get the package documents of a book
+    ///
+    /// Kindle books and PDFs give a single package document made from their EXTH records or
+    /// their information dictionary and XMP packet with no entry name. Comics give one made
+    /// from their `ComicInfo.xml` and FictionBooks one made from their description, along
+    /// with the name of the entry holding it when they are zipped.
+    fn get_book_metadata(
+        &self,
+        epub_path: &Path,
+    ) -> color_eyre::Result<Vec<(String, OpfDocument)>> {
+        if is_kindle_book(epub_path) {
+            return Ok(vec![(
+                String::new(),
+                MobiBook::read(epub_path)?.metadata()?,
+            )]);
+        }
+        if is_pdf(epub_path) {
+            return Ok(vec![(
+                String::new(),
+                PdfBook::read(epub_path)?.metadata(&file_stem(epub_path))?,
+            )]);
+        }
+        if is_fictionbook(epub_path) {
+            let (entry, book) = self.read_fictionbook(epub_path)?;
+            return Ok(vec![(entry, book.metadata(&file_stem(epub_path))?)]);
+        }
+
+        let mut archive = ZipArchive::new(BufReader::new(File::open(epub_path)?))?;
+        if is_comic_book(epub_path) {
+            let (entry, comic_info) = self.get_comic_info(&mut archive)?;
+            return Ok(vec![(entry, comic_info.metadata(&file_stem(epub_path))?)]);
+        }
+        self.get_metadata(&mut archive)
+    }
+
+    /// Get the entry name and parsed `ComicInfo.xml` of a comic's archive, or an empty one
+    /// when the comic doesn't have one
+    fn get_comic_info<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+    ) -> color_eyre::Result<(String, ComicInfo)> {
+        let entry = archive
+            .file_names()
+            .find(|name| name.eq_ignore_ascii_case(COMIC_INFO_PATH))
+            .map(String::from);
+        match entry {
+            Some(entry) => {
+                let comic_info = ComicInfo::parse(&self.read_entry(archive, &entry)?)?;
+                Ok((entry, comic_info))
+            }
+            None => Ok((COMIC_INFO_PATH.to_string(), ComicInfo::new())),
+        }
+    }
+
+    /// Get the entry name and parsed book of a FictionBook, the entry being the first FB2
+    /// file of the archive when the book is zipped and empty otherwise
+    fn read_fictionbook(&self, book_path: &Path) -> color_eyre::Result<(String, FictionBook)> {
+        if !is_zipped_fictionbook(book_path) {
+            return Ok((String::new(), FictionBook::parse(&fs::read(book_path)?)?));
+        }
+        let mut archive = ZipArchive::new(BufReader::new(File::open(book_path)?))?;
+        let entry = archive
+            .file_names()
+            .find(|name| is_fictionbook(Path::new(name)))
+            .map(String::from)
+            .ok_or_else(|| eyre!("No FB2 file found in {}", display_path(book_path)))?;
+        let mut data = Vec::new();
+        archive.by_name(&entry)?.read_to_end(&mut data)?;
+
+        Ok((entry, FictionBook::parse(&data)?))
+    }
+
+    /// Get the number of books in the series of the book at the given index
+    fn series_total(&self, book_index: usize) -> usize {
+        let series = &self.all_field_values[book_index][&InputField::Series];
+        self.all_field_values
+            .iter()
+            .filter(|inputs| &inputs[&InputField::Series] == series)
+            .count()
+    }
+
+    /// Get the path the file path format gives the book at the given index, if one is set
+    ///
+    /// Books of a Calibre library can't be given a file path format.
+    fn new_book_path(
+        &self,
+        book_index: usize,
+        metadata: &OpfDocument,
+    ) -> color_eyre::Result<Option<PathBuf>> {
+        let inputs = &self.all_field_values[book_index];
+        if !inputs.contains_key(&InputField::PathFormat) {
+            return Ok(None);
+        }
+        if self.library.is_some() {
+            return Err(eyre!(
+                "Books in a Calibre library can't be moved, Calibre keeps their paths"
+            ));
+        }
+
+        Ok(Some(format_path(
+            inputs,
+            &self.all_selected[book_index],
+            self.series_total(book_index),
+            metadata,
+        )?))
+    }
+
+    /// Load the new cover of the book at the given index, if one was given
+    fn new_cover(&self, book_index: usize) -> color_eyre::Result<Option<CoverImage>> {
+        let inputs = &self.all_field_values[book_index];
+        let Some(source) = inputs
+            .get(&InputField::BookCover)
+            .and_then(|cover| CoverSource::parse(cover))
+        else {
+            return Ok(None);
+        };
+        let position = parse_position(&input_position(inputs)?)?.to_string();
+
+        Ok(Some(source.load(&inputs[&InputField::Series], &position)?))
+    }
+
+    /// Replace the cover of a package, adding the new entries of the epub to the edited
+    /// entries
+    ///
+    /// A cover image that can be written is overwritten in place, re-encoding the new cover
+    /// in its format so the pages showing it keep working. Otherwise the new cover is added
+    /// as a new image along with a cover page when the book doesn't have one.
+    fn replace_cover(
+        &self,
+        metadata: &mut OpfDocument,
+        meta_path: &str,
+        cover: &CoverImage,
+        edited_entries: &mut HashMap<String, Vec<u8>>,
+    ) -> color_eyre::Result<()> {
+        if let Some(item) = metadata.cover_image()
+            && let Some(data) = cover.encoded_as(&item.media_type)?
         {
-            let file = file.path();
-            if let Some(file_name) = file.file_name() {
-                if file_name == OsStr::new("mimetype") {
+            edited_entries.insert(package_entry(meta_path, &item.href), data);
+            metadata.set_cover_image(&item.id);
+            return Ok(());
+        }
+
+        let image_href = metadata.unique_href(&format!("cover.{}", cover.extension()));
+        let image_id = metadata.add_manifest_item("cover-image", &image_href, cover.media_type());
+        metadata.set_cover_image(&image_id);
+        edited_entries.insert(package_entry(meta_path, &image_href), cover.data.to_owned());
+        if !metadata.has_cover_page() {
+            let page_href = metadata.unique_href("cover.xhtml");
+            let page_id = metadata.add_manifest_item("cover", &page_href, "application/xhtml+xml");
+            metadata.add_cover_page(&page_id, &page_href);
+            edited_entries.insert(
+                package_entry(meta_path, &page_href),
+                cover_page(&image_href, metadata.is_epub3()).into_bytes(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Edit the metadata based on the inputs given for the book at the given index
+    fn edit_metadata(
+        &self,
+        mut metadata: OpfDocument,
+        book_index: usize,
+    ) -> color_eyre::Result<OpfDocument> {
+        let current_book_inputs = &self.all_field_values[book_index];
+        let series_index = parse_position(&input_position(current_book_inputs)?)?.to_string();
+        let series = &current_book_inputs[&InputField::Series];
+        if current_book_inputs.contains_key(&InputField::Format) {
+            let formatted_string = format_title(
+                current_book_inputs,
+                &self.all_selected[book_index],
+                self.series_total(book_index),
+                &metadata,
+            )?;
+
+            debug!(
+                "Setting title \"{}\" to \"{}\"",
+                metadata.title().unwrap_or_default(),
+                formatted_string
+            );
+            metadata.set_title(&formatted_string);
+        }
+        if !series.is_empty() {
+            metadata.set_series(series, &series_index);
+        }
+        if let Some(authors) = current_book_inputs.get(&InputField::BookAuthors) {
+            let creators = parse_creators(authors)?;
+            if creators != metadata.creators() {
+                metadata.set_creators(&creators);
+            }
+        }
+        for field in DcField::VALUES {
+            if let Some(value) = current_book_inputs.get(&InputField::Metadata(field)) {
+                field.validate(value)?;
+                if !field.is_dublin_core() {
                     continue;
                 }
+                let values = field.values(value);
+                if values != metadata.dc_values(field.element()) {
+                    metadata.set_dc_values(field.element(), &values);
+                }
             }
+        }
+
+        Ok(metadata)
+    }
 
-            if file.is_file() {
-                let relative_path = file.strip_prefix(temp_dir)?.to_string_lossy();
-                let options =
-                    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    /// Edit the metadata of a comic based on the inputs given, along with the volume and the
+    /// number of books in its series that only comics have a place for
+    fn edit_comic_metadata(
+        &self,
+        metadata: OpfDocument,
+        book_index: usize,
+    ) -> color_eyre::Result<OpfDocument> {
+        let mut metadata = self.edit_metadata(metadata, book_index)?;
+        let inputs = &self.all_field_values[book_index];
+        if !inputs[&InputField::Series].is_empty() {
+            metadata.set_meta_content(COUNT_META, &self.series_total(book_index).to_string());
+        }
+        if let Some(volume) = inputs.get(&InputField::Metadata(DcField::Volume)) {
+            metadata.set_meta_content(VOLUME_META, volume.trim());
+        }
+
+        Ok(metadata)
+    }
+
+    /// Get the indices of the epub's archive entries in the order they should be written
+    ///
+    /// The OCF container rules require the `mimetype` entry to come first, so it is followed
+    /// by the `META-INF` entries and then every other entry in its original order.
+    fn get_entry_order<R: Read + Seek>(&self, archive: &ZipArchive<R>) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..archive.len()).collect();
+        indices.sort_by_key(|&i| match archive.name_for_index(i).unwrap_or_default() {
+            MIMETYPE_PATH => 0,
+            name if name.starts_with("META-INF/") => 1,
+            _ => 2,
+        });
+
+        indices
+    }
+
+    /// Repackage the epub or comic with the edited entries replacing the original ones
+    ///
+    /// An epub's `mimetype` entry is stored uncompressed with no extra field as the OCF
+    /// container rules require. Unedited entries are copied over without being recompressed
+    /// and edited entries keep their original compression method when it can be written,
+    /// falling back to deflate for methods such as LZMA and Deflate64.
+    fn repackage_book<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        mut edited_entries: HashMap<String, Vec<u8>>,
+        output_path: &Path,
+    ) -> color_eyre::Result<()> {
+        let temp_file = sibling_temp_file(output_path)?;
+        let mut zip = ZipWriter::new(BufWriter::new(temp_file));
+
+        if is_epub(output_path) {
+            zip.start_file(
+                MIMETYPE_PATH,
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+            )?;
+            zip.write_all(EPUB_MIMETYPE.as_bytes())?;
+        }
 
-                zip.start_file(relative_path, options)?;
-                let contents = read(file)?;
-                zip.write_all(&contents)?;
+        for i in self.get_entry_order(archive) {
+            let entry = archive.by_index_raw(i)?;
+            // Entries with unsafe names are copied over as they are too, as nothing is
+            // written to disk under their names
+            if entry.name() == MIMETYPE_PATH {
+                continue;
             }
+
+            match edited_entries.remove(entry.name()) {
+                Some(contents) => {
+                    let mut options = SimpleFileOptions::default().compression_method(match entry
+                        .compression()
+                    {
+                        method @ (CompressionMethod::Stored
+                        | CompressionMethod::Deflated
+                        | CompressionMethod::Bzip2
+                        | CompressionMethod::Zstd
+                        | CompressionMethod::Xz) => method,
+                        _ => CompressionMethod::Deflated,
+                    });
+                    if let Some(last_modified) = entry.last_modified() {
+                        options = options.last_modified_time(last_modified);
+                    }
+                    let name = entry.name().to_string();
+                    zip.start_file(name, options)?;
+                    zip.write_all(&contents)?;
+                }
+                None => zip.raw_copy_file(entry)?,
+            }
+        }
+
+        // Entries that were not in the original epub go at the end
+        let mut new_entries: Vec<(String, Vec<u8>)> = edited_entries.into_iter().collect();
+        new_entries.sort();
+        for (name, contents) in new_entries {
+            let options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+            zip.start_file(name, options)?;
+            zip.write_all(&contents)?;
         }
 
-        zip.finish()?;
+        let temp_file = zip.finish()?.into_inner()?;
+        replace_file(temp_file, output_path, &self.backup_mode)?;
 
         Ok(())
     }
 }
+
+/// Get the name of the archive entry an href of a package document points to
+///
+/// Hrefs are relative to the package document and may be percent-encoded.
+fn package_entry(meta_path: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut bytes = Vec::new();
+    let mut rest = href.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2)) {
+            (b'%', Some(hex))
+                if let Ok(value) = u8::from_str_radix(&String::from_utf8_lossy(hex), 16) =>
+            {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    let mut components: Vec<&str> = meta_path.split('/').collect();
+    components.pop();
+    let decoded = String::from_utf8_lossy(&bytes).to_string();
+    for component in decoded.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    components.join("/")
+}
+
+/// Get the position of a book from its inputs, which is its place in the list unless one was
+/// given
+fn input_position(inputs: &HashMap<InputField, String>) -> color_eyre::Result<String> {
+    match inputs.get(&InputField::BookPosition) {
+        Some(position) => Ok(position.to_owned()),
+        None => Ok((inputs[&InputField::BookOrder].parse::<u32>()? + 1).to_string()),
+    }
+}
+
+/// Get the values of the format string placeholders for a book from its inputs, the number
+/// of books in its series and its current metadata
+fn format_values(
+    inputs: &HashMap<InputField, String>,
+    epub_path: &Path,
+    total: usize,
+    metadata: &OpfDocument,
+) -> color_eyre::Result<HashMap<&'static str, String>> {
+    let year = metadata
+        .dc_value("date")
+        .map(|date| date.chars().take_while(char::is_ascii_digit).collect())
+        .unwrap_or_default();
+    let filename = file_stem(epub_path);
+    // The creators typed in are used over the book's current ones
+    let (author, author_sort) = match inputs.get(&InputField::BookAuthors) {
+        Some(authors) => {
+            let creators = parse_creators(authors)?;
+            let author = first_author(&creators).cloned().unwrap_or_default();
+            (author.name, author.file_as.unwrap_or_default())
+        }
+        None => (
+            metadata.author().unwrap_or_default(),
+            metadata.author_sort().unwrap_or_default(),
+        ),
+    };
+
+    Ok(HashMap::from([
+        ("title", inputs[&InputField::BookTitle].to_owned()),
+        ("series", inputs[&InputField::Series].to_owned()),
+        ("position", input_position(inputs)?),
+        ("author", author),
+        ("author_sort", author_sort),
+        ("year", year),
+        ("total", total.to_string()),
+        ("original_title", metadata.title().unwrap_or_default()),
+        ("filename", filename),
+    ]))
+}
+
+/// Render the title the format string gives a book
+fn format_title(
+    inputs: &HashMap<InputField, String>,
+    epub_path: &Path,
+    total: usize,
+    metadata: &OpfDocument,
+) -> color_eyre::Result<String> {
+    let template = Template::parse(&inputs[&InputField::Format])?;
+
+    Ok(template.render(&format_values(inputs, epub_path, total, metadata)?))
+}
+
+/// Render the path the file path format gives a book, relative paths being relative to the
+/// directory the app was started in
+fn format_path(
+    inputs: &HashMap<InputField, String>,
+    epub_path: &Path,
+    total: usize,
+    metadata: &OpfDocument,
+) -> color_eyre::Result<PathBuf> {
+    let template = Template::parse(&inputs[&InputField::PathFormat])?;
+    let path = template.render_path(
+        &format_values(inputs, epub_path, total, metadata)?,
+        &book_extension(epub_path),
+    )?;
+
+    Ok(current_dir()?.join(path))
+}
+
+/// Whether the path is a book the app can edit, an epub, a Kindle book, a comic, a PDF or
+/// a FictionBook
+pub fn is_book(path: &Path) -> bool {
+    is_epub(path)
+        || is_kindle_book(path)
+        || is_comic_book(path)
+        || is_pdf(path)
+        || is_fictionbook(path)
+}
+
+/// Get the file extension of a book in lowercase, which is `fb2.zip` for zipped
+/// FictionBooks
+pub fn book_extension(path: &Path) -> String {
+    if is_zipped_fictionbook(path) {
+        return ZIPPED_FB2_EXTENSION.to_string();
+    }
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| EPUB_EXTENSION.to_string())
+}
+
+/// Whether the path is an epub
+fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(EPUB_EXTENSION))
+}
+
+/// Get the file name of a book without its extension
+pub fn file_stem(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match is_zipped_fictionbook(path) {
+        true => Path::new(&stem)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        false => stem,
+    }
+}
+
+/// Get a path for showing to the user, relative to the directory the app was started in
+/// when it is inside it
+pub fn display_path(path: &Path) -> String {
+    current_dir()
+        .ok()
+        .and_then(|directory| path.strip_prefix(directory).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Parse a series position, which can be zero or have decimals but can't be negative
+pub fn parse_position(position: &str) -> color_eyre::Result<f64> {
+    match position.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(eyre!("Invalid series position \"{}\"", position)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::read, io::Cursor};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// Build an epub whose entries break the OCF rules, with its `mimetype` compressed and
+    /// after the content
+    fn unordered_epub() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, method, contents) in [
+            ("OEBPS/text.xhtml", CompressionMethod::Deflated, "<html/>"),
+            (MIMETYPE_PATH, CompressionMethod::Deflated, EPUB_MIMETYPE),
+            ("OEBPS/content.opf", CompressionMethod::Stored, "<package/>"),
+            (
+                "META-INF/container.xml",
+                CompressionMethod::Deflated,
+                "<container/>",
+            ),
+            ("OEBPS/notes.txt", CompressionMethod::Bzip2, "Notes"),
+        ] {
+            zip.start_file(
+                name,
+                SimpleFileOptions::default().compression_method(method),
+            )
+            .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Get the name, compression method and contents of every entry of an archive
+    fn entries(data: &[u8]) -> Vec<(String, CompressionMethod, String)> {
+        let mut archive = ZipArchive::new(Cursor::new(data)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut entry = archive.by_index(i).unwrap();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (entry.name().to_string(), entry.compression(), contents)
+            })
+            .collect()
+    }
+
+    /// Repackage the unordered epub with the given edited entries, returning the new epub
+    fn repackage(edited_entries: &[(&str, &str)]) -> Vec<u8> {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("book.epub");
+        let mut model = Model::new();
+        model.backup_mode = BackupMode::Disabled;
+        let mut archive = ZipArchive::new(Cursor::new(unordered_epub())).unwrap();
+        let edited_entries = edited_entries
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.as_bytes().to_vec()))
+            .collect();
+        model
+            .repackage_book(&mut archive, edited_entries, &path)
+            .unwrap();
+
+        read(path).unwrap()
+    }
+
+    #[test]
+    fn mimetype_comes_first_and_stored() {
+        let data = repackage(&[]);
+
+        // The local header of the first entry is followed by its name and contents, with no
+        // extra field in between
+        assert_eq!(&data[..4], b"PK\x03\x04");
+        assert_eq!(&data[8..10], &0u16.to_le_bytes());
+        assert_eq!(&data[28..30], &0u16.to_le_bytes());
+        assert_eq!(
+            &data[30..30 + MIMETYPE_PATH.len() + EPUB_MIMETYPE.len()],
+            format!("{}{}", MIMETYPE_PATH, EPUB_MIMETYPE).as_bytes()
+        );
+        let names: Vec<String> = entries(&data)
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                MIMETYPE_PATH,
+                "META-INF/container.xml",
+                "OEBPS/text.xhtml",
+                "OEBPS/content.opf",
+                "OEBPS/notes.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn edited_entries_keep_their_compression() {
+        let data = repackage(&[
+            ("OEBPS/content.opf", "<package version=\"3.0\"/>"),
+            ("OEBPS/notes.txt", "Edited notes"),
+            ("OEBPS/cover.png", "New cover"),
+        ]);
+
+        assert_eq!(
+            entries(&data),
+            [
+                (
+                    String::from(MIMETYPE_PATH),
+                    CompressionMethod::Stored,
+                    String::from(EPUB_MIMETYPE)
+                ),
+                (
+                    String::from("META-INF/container.xml"),
+                    CompressionMethod::Deflated,
+                    String::from("<container/>")
+                ),
+                (
+                    String::from("OEBPS/text.xhtml"),
+                    CompressionMethod::Deflated,
+                    String::from("<html/>")
+                ),
+                (
+                    String::from("OEBPS/content.opf"),
+                    CompressionMethod::Stored,
+                    String::from("<package version=\"3.0\"/>")
+                ),
+                (
+                    String::from("OEBPS/notes.txt"),
+                    CompressionMethod::Bzip2,
+                    String::from("Edited notes")
+                ),
+                // New entries are deflated at the end
+                (
+                    String::from("OEBPS/cover.png"),
+                    CompressionMethod::Deflated,
+                    String::from("New cover")
+                ),
+            ]
+        );
+    }
+}
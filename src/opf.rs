@@ -0,0 +1,1203 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+use color_eyre::eyre::eyre;
+
+use crate::{
+    creator::{Creator, first_author},
+    xml::{Element, XmlDocument, local_name},
+};
+
+/// The namespace used by Dublin Core elements
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+/// The namespace used by OPF elements and attributes
+const OPF_NAMESPACE: &str = "http://www.idpf.org/2007/opf";
+
+/// Struct holding a Dublin Core element from the OPF metadata
+#[derive(Clone, Debug, PartialEq)]
+pub struct DcEntry {
+    /// The name of the element without its prefix (e.g. `title`)
+    pub name: String,
+    /// The id of the element if it has one
+    pub id: Option<String>,
+    /// The text content of the element
+    pub value: String,
+    /// All attributes of the element
+    pub attributes: Vec<(String, String)>,
+}
+
+/// Struct holding a `<meta>` element from the OPF metadata
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetaEntry {
+    /// The `name` attribute used by EPUB2 style metas
+    pub name: Option<String>,
+    /// The `content` attribute used by EPUB2 style metas
+    pub content: Option<String>,
+    /// The `property` attribute used by EPUB3 style metas
+    pub property: Option<String>,
+    /// The `refines` attribute used by EPUB3 style metas
+    pub refines: Option<String>,
+    /// The id of the element if it has one
+    pub id: Option<String>,
+    /// The text content of the element
+    pub value: String,
+}
+
+/// Struct holding the change made to a metadata field between two documents
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    /// The label of the field (e.g. `dc:title` or `calibre:series`)
+    pub field: String,
+    /// The value of the field before the edit
+    pub before: Option<String>,
+    /// The value of the field after the edit
+    pub after: Option<String>,
+}
+
+/// Struct holding an `<item>` element from the OPF manifest
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestItem {
+    /// The id of the item
+    pub id: String,
+    /// The path of the item relative to the package document
+    pub href: String,
+    /// The media type of the item
+    pub media_type: String,
+}
+
+/// Struct holding a parsed OPF package document
+///
+/// Only the elements that are edited change when the document is serialized again,
+/// everything else is written back exactly as it was read.
+#[derive(Clone, Debug)]
+pub struct OpfDocument {
+    /// The underlying XML document
+    document: XmlDocument,
+    /// The namespace prefixes bound to the Dublin Core namespace
+    dc_prefixes: Vec<String>,
+}
+
+impl OpfDocument {
+    /// Parse an OPF package document from a string
+    pub fn parse(source: &str) -> color_eyre::Result<Self> {
+        let document = XmlDocument::parse(source)?;
+        let root = document.root();
+        if root.local_name() != "package" {
+            return Err(eyre!(
+                "Expected a <package> root element but found <{}>",
+                root.name
+            ));
+        }
+        if root.child("metadata").is_none() {
+            return Err(eyre!("OPF package has no <metadata> element"));
+        }
+
+        let mut dc_prefixes = vec![String::from("dc")];
+        for element in [root, root.child("metadata").unwrap()] {
+            for (key, value) in element.attributes() {
+                if let Some(prefix) = key.strip_prefix("xmlns:")
+                    && value == DC_NAMESPACE
+                    && !dc_prefixes.iter().any(|p| p == prefix)
+                {
+                    dc_prefixes.push(prefix.to_string());
+                }
+            }
+        }
+
+        Ok(OpfDocument {
+            document,
+            dc_prefixes,
+        })
+    }
+
+    /// Build an EPUB2 package document holding only the given metadata elements, for books
+    /// that keep their metadata somewhere else
+    ///
+    /// The elements can use the `dc` and `opf` prefixes.
+    pub fn from_metadata(elements: &[String]) -> color_eyre::Result<Self> {
+        OpfDocument::parse(&format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<package xmlns=\"{0}\" version=\"2.0\">
+  <metadata xmlns:dc=\"{1}\" xmlns:opf=\"{0}\">
+    {2}
+  </metadata>
+</package>
+",
+            OPF_NAMESPACE,
+            DC_NAMESPACE,
+            elements.join("\n    ")
+        ))
+    }
+
+    /// Get a copy of the package document holding only its metadata, leaving out the
+    /// manifest, spine and guide that point into the book
+    pub fn metadata_only(&self) -> Self {
+        let mut package = self.clone();
+        package
+            .document
+            .root_mut()
+            .remove_children(|element| element.local_name() != "metadata");
+
+        package
+    }
+
+    /// Get the version of the package (e.g. "2.0" or "3.0")
+    pub fn version(&self) -> &str {
+        self.document.root().attribute("version").unwrap_or("2.0")
+    }
+
+    /// Whether the package is an EPUB3 package
+    pub fn is_epub3(&self) -> bool {
+        self.version().starts_with('3')
+    }
+
+    /// Get every Dublin Core element in the metadata
+    pub fn dublin_core(&self) -> Vec<DcEntry> {
+        self.metadata_elements()
+            .filter(|element| self.is_dc(element))
+            .map(|element| DcEntry {
+                name: element.local_name().to_string(),
+                id: element.attribute("id").map(String::from),
+                value: element.text().trim().to_string(),
+                attributes: element.attributes().to_vec(),
+            })
+            .collect()
+    }
+
+    /// Get every `<meta>` element in the metadata
+    pub fn metas(&self) -> Vec<MetaEntry> {
+        self.metadata_elements()
+            .filter(|element| element.local_name() == "meta")
+            .map(|element| MetaEntry {
+                name: element.attribute("name").map(String::from),
+                content: element.attribute("content").map(String::from),
+                property: element.attribute("property").map(String::from),
+                refines: element.attribute("refines").map(String::from),
+                id: element.attribute("id").map(String::from),
+                value: element.text().trim().to_string(),
+            })
+            .collect()
+    }
+
+    /// Get every metadata field as a label and its values
+    ///
+    /// Dublin Core elements are labelled by their qualified name, EPUB2 metas by their name
+    /// and EPUB3 metas by their property along with the id of the element they refine. The
+    /// EPUB2 `opf:` attributes of Dublin Core elements are labelled by the attribute along
+    /// with the element's value.
+    pub fn fields(&self) -> Vec<(String, Vec<String>)> {
+        let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+        let mut push = |label: String, value: String| match fields
+            .iter_mut()
+            .find(|(field, _)| *field == label)
+        {
+            Some((_, values)) => values.push(value),
+            None => fields.push((label, vec![value])),
+        };
+
+        for entry in self.dublin_core() {
+            for (key, value) in &entry.attributes {
+                if key.contains(':') && !key.starts_with("xmlns:") && !key.starts_with("xml:") {
+                    push(format!("{} ({})", key, entry.value), value.to_owned());
+                }
+            }
+            push(format!("dc:{}", entry.name), entry.value);
+        }
+        for meta in self.metas() {
+            match (meta.name, meta.property) {
+                (Some(name), _) => push(name, meta.content.unwrap_or_default()),
+                (None, Some(property)) => match meta.refines {
+                    Some(refines) => push(format!("{} ({})", property, refines), meta.value),
+                    None => push(property, meta.value),
+                },
+                (None, None) => {}
+            }
+        }
+
+        fields
+    }
+
+    /// Compare the metadata fields of this document with an edited copy of it
+    pub fn diff(&self, edited: &OpfDocument) -> Vec<FieldChange> {
+        let before = self.fields();
+        let after = edited.fields();
+        let find = |fields: &[(String, Vec<String>)], label: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == label)
+                .map(|(_, values)| values.join("; "))
+        };
+
+        let mut labels: Vec<&String> = before.iter().map(|(label, _)| label).collect();
+        for (label, _) in &after {
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+
+        labels
+            .into_iter()
+            .filter_map(|label| {
+                let before_value = find(&before, label);
+                let after_value = find(&after, label);
+                (before_value != after_value).then(|| FieldChange {
+                    field: label.clone(),
+                    before: before_value,
+                    after: after_value,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the value of the first Dublin Core element with the given name
+    pub fn dc_value(&self, name: &str) -> Option<String> {
+        self.dublin_core()
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.value)
+    }
+
+    /// Get the values of every Dublin Core element with the given name
+    pub fn dc_values(&self, name: &str) -> Vec<String> {
+        self.dublin_core()
+            .into_iter()
+            .filter(|entry| entry.name == name)
+            .map(|entry| entry.value)
+            .collect()
+    }
+
+    /// Set the values of the Dublin Core elements with the given name
+    ///
+    /// When there are as many elements as values their text is replaced in place, keeping
+    /// their attributes, otherwise the elements are replaced with one for each value.
+    pub fn set_dc_values(&mut self, name: &str, values: &[String]) {
+        let dc_prefixes = self.dc_prefixes.clone();
+        let is_field =
+            |element: &Element| is_dc(element, &dc_prefixes) && element.local_name() == name;
+        if self.dc_values(name).len() == values.len() {
+            for (element, value) in self
+                .metadata_elements_mut()
+                .filter(|element| is_field(element))
+                .zip(values)
+            {
+                element.set_text(value);
+            }
+            return;
+        }
+
+        self.remove_metadata_elements(is_field);
+        for value in values {
+            let element = Element::new(&self.dc_name(name)).with_text(value);
+            self.insert_metadata_element(element);
+        }
+    }
+
+    /// Set the value of the first Dublin Core element with the given name, adding the
+    /// element if it does not exist
+    pub fn set_dc_value(&mut self, name: &str, value: &str) {
+        let dc_prefixes = self.dc_prefixes.clone();
+        if let Some(element) = self
+            .metadata_elements_mut()
+            .find(|element| is_dc(element, &dc_prefixes) && element.local_name() == name)
+        {
+            element.set_text(value);
+            return;
+        }
+
+        let element = Element::new(&self.dc_name(name)).with_text(value);
+        self.insert_metadata_element(element);
+    }
+
+    /// Get the title of the book
+    pub fn title(&self) -> Option<String> {
+        self.dc_value("title")
+    }
+
+    /// Get the first author of the book
+    pub fn author(&self) -> Option<String> {
+        first_author(&self.creators()).map(|creator| creator.name.clone())
+    }
+
+    /// Get the sort name of the first author of the book
+    pub fn author_sort(&self) -> Option<String> {
+        first_author(&self.creators()).and_then(|creator| creator.file_as.clone())
+    }
+
+    /// Get the creators of the book
+    ///
+    /// Roles and sort names are read from the EPUB2 `opf:role` and `opf:file-as` attributes,
+    /// or from EPUB3 `role` and `file-as` metas refining the creator.
+    pub fn creators(&self) -> Vec<Creator> {
+        let metas = self.metas();
+        let refinement = |id: &Option<String>, property: &str| {
+            let refines = format!("#{}", id.as_deref()?);
+            metas
+                .iter()
+                .find(|meta| {
+                    meta.property.as_deref() == Some(property)
+                        && meta.refines.as_deref() == Some(refines.as_str())
+                })
+                .map(|meta| meta.value.clone())
+        };
+
+        self.dublin_core()
+            .into_iter()
+            .filter(|entry| entry.name == "creator")
+            .map(|entry| {
+                let attribute = |name: &str| {
+                    entry
+                        .attributes
+                        .iter()
+                        .find(|(key, _)| key.contains(':') && local_name(key) == name)
+                        .map(|(_, value)| value.to_owned())
+                };
+                Creator {
+                    role: attribute("role").or_else(|| refinement(&entry.id, "role")),
+                    file_as: attribute("file-as").or_else(|| refinement(&entry.id, "file-as")),
+                    name: entry.value,
+                }
+            })
+            .collect()
+    }
+
+    /// Replace the creators of the book
+    ///
+    /// EPUB2 packages get `opf:role` and `opf:file-as` attributes while EPUB3 packages get
+    /// `role` and `file-as` metas refining each creator. The metas refining the old creators
+    /// are removed along with them.
+    pub fn set_creators(&mut self, creators: &[Creator]) {
+        let dc_prefixes = self.dc_prefixes.clone();
+        let old_ids: Vec<String> = self
+            .dublin_core()
+            .into_iter()
+            .filter(|entry| entry.name == "creator")
+            .filter_map(|entry| entry.id.map(|id| format!("#{}", id)))
+            .collect();
+        self.remove_metadata_elements(|element| {
+            (is_dc(element, &dc_prefixes) && element.local_name() == "creator")
+                || (element.local_name() == "meta"
+                    && element
+                        .attribute("refines")
+                        .is_some_and(|refines| old_ids.iter().any(|id| id == refines)))
+        });
+
+        let name = self.dc_name("creator");
+        if !self.is_epub3() {
+            let opf_prefix = self.opf_prefix();
+            for creator in creators {
+                let mut element = Element::new(&name);
+                if let Some(role) = &creator.role {
+                    element.set_attribute(&format!("{}:role", opf_prefix), role);
+                }
+                if let Some(file_as) = &creator.file_as {
+                    element.set_attribute(&format!("{}:file-as", opf_prefix), file_as);
+                }
+                self.insert_metadata_element(element.with_text(&creator.name));
+            }
+            return;
+        }
+
+        for creator in creators {
+            let id = self.unique_id("creator");
+            let element = Element::new(&name)
+                .with_attribute("id", &id)
+                .with_text(&creator.name);
+            self.insert_metadata_element(element);
+            let refines = format!("#{}", id);
+            if let Some(role) = &creator.role {
+                let element = Element::new(&self.meta_name())
+                    .with_attribute("refines", &refines)
+                    .with_attribute("property", "role")
+                    .with_attribute("scheme", "marc:relators")
+                    .with_text(role);
+                self.insert_metadata_element(element);
+            }
+            if let Some(file_as) = &creator.file_as {
+                self.set_property_meta("file-as", Some(&refines), file_as);
+            }
+        }
+    }
+
+    /// Set the title of the book, keeping calibre's title sort in line with it
+    pub fn set_title(&mut self, title: &str) {
+        self.set_dc_value("title", title);
+        if self.meta_content("calibre:title_sort").is_some() {
+            self.set_meta_content("calibre:title_sort", title);
+        }
+    }
+
+    /// Get the content of the first EPUB2 style `<meta name="" content="">` element
+    pub fn meta_content(&self, name: &str) -> Option<String> {
+        self.metas()
+            .into_iter()
+            .find(|meta| meta.name.as_deref() == Some(name))
+            .and_then(|meta| meta.content)
+    }
+
+    /// Set the content of an EPUB2 style `<meta name="" content="">` element, adding the
+    /// element if it does not exist
+    pub fn set_meta_content(&mut self, name: &str, content: &str) {
+        if let Some(element) = self.metadata_elements_mut().find(|element| {
+            element.local_name() == "meta" && element.attribute("name") == Some(name)
+        }) {
+            element.set_attribute("content", content);
+            return;
+        }
+
+        let element = Element::new(&self.meta_name())
+            .with_attribute("name", name)
+            .with_attribute("content", content);
+        self.insert_metadata_element(element);
+    }
+
+    /// Get the series of the book and the book's position in it, if it has one
+    ///
+    /// Calibre's metas are used first, then an EPUB3 `belongs-to-collection` meta along
+    /// with the `group-position` refining it. Collections refined as a `series` are used
+    /// over the ones without a type, and collections of any other type are left out.
+    pub fn series(&self) -> Option<(String, Option<String>)> {
+        if let Some(series) = self.meta_content("calibre:series")
+            && !series.trim().is_empty()
+        {
+            return Some((series, self.meta_content("calibre:series_index")));
+        }
+
+        let metas = self.metas();
+        let collections: Vec<&MetaEntry> = metas
+            .iter()
+            .filter(|meta| {
+                meta.property.as_deref() == Some("belongs-to-collection")
+                    && meta.refines.is_none()
+                    && !meta.value.is_empty()
+            })
+            .collect();
+        let collection = collections
+            .iter()
+            .find(|meta| collection_type(&metas, meta).as_deref() == Some("series"))
+            .or_else(|| {
+                collections
+                    .iter()
+                    .find(|meta| collection_type(&metas, meta).is_none())
+            })?;
+        let position = refinement(&metas, collection, "group-position");
+
+        Some((collection.value.clone(), position))
+    }
+
+    /// Set the series of the book and the book's position in it
+    ///
+    /// EPUB2 packages get calibre's `calibre:series` and `calibre:series_index` metas while
+    /// EPUB3 packages get a `belongs-to-collection` meta refined by `collection-type` and
+    /// `group-position`, reusing the collection already refined as a `series` and leaving
+    /// collections of any other type as they are. Calibre's metas are kept up to date in
+    /// EPUB3 packages that have them.
+    pub fn set_series(&mut self, series: &str, position: &str) {
+        if !self.is_epub3() || self.meta_content("calibre:series").is_some() {
+            self.set_meta_content("calibre:series", series);
+            self.set_meta_content("calibre:series_index", position);
+        }
+        if !self.is_epub3() {
+            return;
+        }
+
+        let metas = self.metas();
+        let series_id = metas
+            .iter()
+            .find(|meta| {
+                meta.property.as_deref() == Some("belongs-to-collection")
+                    && meta.refines.is_none()
+                    && collection_type(&metas, meta).as_deref() == Some("series")
+            })
+            .and_then(|meta| meta.id.clone());
+        let collection_id = match series_id {
+            Some(id) => {
+                if let Some(element) = self.metadata_elements_mut().find(|element| {
+                    element.local_name() == "meta" && element.attribute("id") == Some(&id)
+                }) {
+                    element.set_text(series);
+                }
+                id
+            }
+            None => {
+                let id = self.unique_id("series");
+                let element = Element::new(&self.meta_name())
+                    .with_attribute("property", "belongs-to-collection")
+                    .with_attribute("id", &id)
+                    .with_text(series);
+                self.insert_metadata_element(element);
+                id
+            }
+        };
+        let refines = format!("#{}", collection_id);
+        self.set_property_meta("collection-type", Some(&refines), "series");
+        self.set_property_meta("group-position", Some(&refines), position);
+    }
+
+    /// Get every item of the manifest
+    pub fn manifest_items(&self) -> Vec<ManifestItem> {
+        self.document
+            .root()
+            .child("manifest")
+            .into_iter()
+            .flat_map(|manifest| manifest.elements())
+            .filter(|element| element.local_name() == "item")
+            .filter_map(|element| {
+                Some(ManifestItem {
+                    id: element.attribute("id")?.to_string(),
+                    href: element.attribute("href")?.to_string(),
+                    media_type: element
+                        .attribute("media-type")
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Get the manifest item of the book's cover image, if it has one
+    ///
+    /// The item with the EPUB3 `cover-image` property is used first, then the image item
+    /// named by an EPUB2 `<meta name="cover">`.
+    pub fn cover_image(&self) -> Option<ManifestItem> {
+        let property_item = self
+            .document
+            .root()
+            .child("manifest")
+            .into_iter()
+            .flat_map(|manifest| manifest.elements())
+            .find(|element| {
+                element.local_name() == "item"
+                    && element.attribute("properties").is_some_and(|properties| {
+                        properties.split_whitespace().any(|p| p == "cover-image")
+                    })
+            })
+            .and_then(|element| element.attribute("id"));
+        let id = property_item
+            .map(String::from)
+            .or_else(|| self.meta_content("cover"))?;
+
+        self.manifest_items()
+            .into_iter()
+            .find(|item| item.id == id && item.media_type.starts_with("image/"))
+    }
+
+    /// Mark the manifest item with the given id as the book's cover image
+    ///
+    /// EPUB3 packages move the `cover-image` property to the item, and every package gets a
+    /// `<meta name="cover">` naming it for older reading systems.
+    pub fn set_cover_image(&mut self, id: &str) {
+        if self.is_epub3()
+            && let Some(manifest) = self.document.root_mut().child_mut("manifest")
+        {
+            for item in manifest
+                .elements_mut()
+                .filter(|element| element.local_name() == "item")
+            {
+                let mut properties: Vec<String> = item
+                    .attribute("properties")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter(|property| *property != "cover-image")
+                    .map(String::from)
+                    .collect();
+                if item.attribute("id") == Some(id) {
+                    properties.push(String::from("cover-image"));
+                }
+                if properties.is_empty() {
+                    item.remove_attribute("properties");
+                } else {
+                    item.set_attribute("properties", &properties.join(" "));
+                }
+            }
+        }
+        self.set_meta_content("cover", id);
+    }
+
+    /// Add an item to the manifest, giving the id it was added with
+    pub fn add_manifest_item(&mut self, id: &str, href: &str, media_type: &str) -> String {
+        let id = self.unique_id(id);
+        let root = self.document.root_mut();
+        if root.child("manifest").is_none() {
+            let name = prefixed_name(root, "manifest");
+            root.append_child(Element::new(&name));
+        }
+        let manifest = root.child_mut("manifest").unwrap();
+        let name = manifest
+            .child("item")
+            .map(|item| item.name.clone())
+            .unwrap_or_else(|| prefixed_name(manifest, "item"));
+        manifest.append_child(
+            Element::new(&name)
+                .with_attribute("id", &id)
+                .with_attribute("href", href)
+                .with_attribute("media-type", media_type),
+        );
+
+        id
+    }
+
+    /// Get an href based on the given one that no manifest item uses, adding a number to
+    /// the file name until it is free
+    pub fn unique_href(&self, href: &str) -> String {
+        let hrefs: HashSet<String> = self
+            .manifest_items()
+            .into_iter()
+            .map(|item| item.href.to_lowercase())
+            .collect();
+        let (stem, extension) = href.rsplit_once('.').unwrap_or((href, ""));
+
+        (1..)
+            .map(|i| match i {
+                1 => href.to_string(),
+                i => format!("{}-{}.{}", stem, i, extension),
+            })
+            .find(|candidate| !hrefs.contains(&candidate.to_lowercase()))
+            .unwrap()
+    }
+
+    /// Whether the guide has a reference to a cover page
+    pub fn has_cover_page(&self) -> bool {
+        self.document.root().child("guide").is_some_and(|guide| {
+            guide.elements().any(|reference| {
+                reference.local_name() == "reference"
+                    && reference.attribute("type") == Some("cover")
+            })
+        })
+    }
+
+    /// Make the manifest item with the given id and href the book's cover page, putting it
+    /// at the start of the spine and adding a cover reference to the guide
+    pub fn add_cover_page(&mut self, id: &str, href: &str) {
+        let root = self.document.root_mut();
+        if let Some(spine) = root.child_mut("spine") {
+            let name = spine
+                .child("itemref")
+                .map(|itemref| itemref.name.clone())
+                .unwrap_or_else(|| prefixed_name(spine, "itemref"));
+            spine.prepend_child(Element::new(&name).with_attribute("idref", id));
+        }
+
+        if root.child("guide").is_none() {
+            let name = prefixed_name(root, "guide");
+            root.append_child(Element::new(&name));
+        }
+        let guide = root.child_mut("guide").unwrap();
+        let name = guide
+            .child("reference")
+            .map(|reference| reference.name.clone())
+            .unwrap_or_else(|| prefixed_name(guide, "reference"));
+        guide.append_child(
+            Element::new(&name)
+                .with_attribute("type", "cover")
+                .with_attribute("title", "Cover")
+                .with_attribute("href", href),
+        );
+    }
+
+    /// Set the text of an EPUB3 style `<meta property="">` element with the given
+    /// `refines` target, adding the element if it does not exist
+    fn set_property_meta(&mut self, property: &str, refines: Option<&str>, value: &str) {
+        if let Some(element) = self.metadata_elements_mut().find(|element| {
+            element.local_name() == "meta"
+                && element.attribute("property") == Some(property)
+                && element.attribute("refines") == refines
+        }) {
+            element.set_text(value);
+            return;
+        }
+
+        let mut element = Element::new(&self.meta_name());
+        if let Some(refines) = refines {
+            element.set_attribute("refines", refines);
+        }
+        element.set_attribute("property", property);
+        element.set_text(value);
+        self.insert_metadata_element(element);
+    }
+
+    /// Generate an id based on the given name that is not used anywhere in the package
+    fn unique_id(&self, name: &str) -> String {
+        let mut ids = HashSet::new();
+        collect_ids(self.document.root(), &mut ids);
+        if !ids.contains(name) {
+            return name.to_string();
+        }
+
+        (1..)
+            .map(|i| format!("{}-{}", name, i))
+            .find(|id| !ids.contains(id))
+            .unwrap()
+    }
+
+    /// Whether the element is a Dublin Core element of this package
+    fn is_dc(&self, element: &Element) -> bool {
+        is_dc(element, &self.dc_prefixes)
+    }
+
+    /// Get the metadata element of the package
+    fn metadata(&self) -> &Element {
+        self.document.root().child("metadata").unwrap()
+    }
+
+    /// Get the metadata element of the package mutably
+    fn metadata_mut(&mut self) -> &mut Element {
+        self.document.root_mut().child_mut("metadata").unwrap()
+    }
+
+    /// Iterate over the metadata entries, including those inside the legacy OPF 1.x
+    /// `<dc-metadata>` and `<x-metadata>` wrappers
+    fn metadata_elements(&self) -> impl Iterator<Item = &Element> {
+        self.metadata().elements().flat_map(|element| {
+            if is_legacy_wrapper(element) {
+                element.elements().collect::<Vec<_>>()
+            } else {
+                vec![element]
+            }
+        })
+    }
+
+    /// Iterate mutably over the metadata entries
+    fn metadata_elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.metadata_mut().elements_mut().flat_map(|element| {
+            if is_legacy_wrapper(element) {
+                element.elements_mut().collect::<Vec<_>>()
+            } else {
+                vec![element]
+            }
+        })
+    }
+
+    /// Remove every metadata entry matching the predicate, including those inside the
+    /// legacy OPF 1.x wrappers
+    fn remove_metadata_elements<F: Fn(&Element) -> bool>(&mut self, predicate: F) {
+        let metadata = self.metadata_mut();
+        metadata.remove_children(&predicate);
+        for wrapper in metadata.elements_mut() {
+            if is_legacy_wrapper(wrapper) {
+                wrapper.remove_children(&predicate);
+            }
+        }
+    }
+
+    /// Add a new element to the end of the metadata
+    fn insert_metadata_element(&mut self, element: Element) {
+        let wrapper_name = if self.is_dc(&element) {
+            "dc-metadata"
+        } else {
+            "x-metadata"
+        };
+        let metadata = self.metadata_mut();
+        match metadata.child_mut(wrapper_name) {
+            Some(wrapper) => wrapper.append_child(element),
+            None => metadata.append_child(element),
+        }
+    }
+
+    /// Get the qualified name to use for a new Dublin Core element
+    fn dc_name(&mut self, name: &str) -> String {
+        let existing_prefix = self
+            .metadata_elements()
+            .find(|element| self.is_dc(element))
+            .and_then(|element| element.prefix().map(String::from));
+        let declared_prefix = [self.document.root(), self.metadata()]
+            .into_iter()
+            .flat_map(|element| element.attributes())
+            .find(|(key, value)| key.starts_with("xmlns:") && value == DC_NAMESPACE)
+            .map(|(key, _)| key["xmlns:".len()..].to_string());
+        let prefix = match existing_prefix.or(declared_prefix) {
+            Some(prefix) => prefix,
+            None => {
+                self.metadata_mut().set_attribute("xmlns:dc", DC_NAMESPACE);
+                String::from("dc")
+            }
+        };
+
+        format!("{}:{}", prefix, name)
+    }
+
+    /// Get the prefix bound to the OPF namespace, binding `opf` to it if there is none
+    fn opf_prefix(&mut self) -> String {
+        let declared_prefix = [self.document.root(), self.metadata()]
+            .into_iter()
+            .flat_map(|element| element.attributes())
+            .find(|(key, value)| key.starts_with("xmlns:") && value == OPF_NAMESPACE)
+            .map(|(key, _)| key["xmlns:".len()..].to_string());
+
+        declared_prefix.unwrap_or_else(|| {
+            self.metadata_mut()
+                .set_attribute("xmlns:opf", OPF_NAMESPACE);
+            String::from("opf")
+        })
+    }
+
+    /// Get the qualified name to use for a new `<meta>` element
+    fn meta_name(&self) -> String {
+        self.metadata_elements()
+            .find(|element| element.local_name() == "meta")
+            .map(|element| element.name.clone())
+            .unwrap_or_else(|| match self.metadata().prefix() {
+                Some(prefix) => format!("{}:meta", prefix),
+                None => String::from("meta"),
+            })
+    }
+}
+
+impl Display for OpfDocument {
+    /// Serialize the package document
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.document.fmt(f)
+    }
+}
+
+/// Whether the element is a Dublin Core element given the prefixes bound to the Dublin
+/// Core namespace
+fn is_dc(element: &Element, dc_prefixes: &[String]) -> bool {
+    match element.prefix() {
+        Some(prefix) => dc_prefixes.iter().any(|dc_prefix| dc_prefix == prefix),
+        None => element.attribute("xmlns") == Some(DC_NAMESPACE),
+    }
+}
+
+/// Get the qualified name for a new child of the element, using the element's own prefix
+fn prefixed_name(parent: &Element, name: &str) -> String {
+    match parent.prefix() {
+        Some(prefix) => format!("{}:{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Get the value of the EPUB3 meta with the given property refining a meta
+fn refinement(metas: &[MetaEntry], meta: &MetaEntry, property: &str) -> Option<String> {
+    let refines = format!("#{}", meta.id.as_deref()?);
+
+    metas
+        .iter()
+        .find(|refinement| {
+            refinement.property.as_deref() == Some(property)
+                && refinement.refines.as_deref() == Some(refines.as_str())
+        })
+        .map(|refinement| refinement.value.clone())
+}
+
+/// Get the `collection-type` refining a `belongs-to-collection` meta, if it has one
+fn collection_type(metas: &[MetaEntry], collection: &MetaEntry) -> Option<String> {
+    refinement(metas, collection, "collection-type")
+}
+
+/// Collect the ids of the element and all of its descendants
+fn collect_ids(element: &Element, ids: &mut HashSet<String>) {
+    if let Some(id) = element.attribute("id") {
+        ids.insert(id.to_string());
+    }
+    for child in element.elements() {
+        collect_ids(child, ids);
+    }
+}
+
+/// Whether the element is one of the OPF 1.x metadata wrappers
+fn is_legacy_wrapper(element: &Element) -> bool {
+    matches!(element.local_name(), "dc-metadata" | "x-metadata")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An EPUB2 package document with its metadata laid out as calibre writes it
+    const EPUB2: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="uuid_id" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Foundation</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Asimov, Isaac">Isaac Asimov</dc:creator>
+    <dc:identifier id="uuid_id" opf:scheme="uuid">1b1c2ed6-5a4e-4d5b-a2b2-1f1c8c7b9c11</dc:identifier>
+    <dc:subject>Science Fiction</dc:subject>
+    <dc:subject>Space</dc:subject>
+    <meta name="calibre:title_sort" content="Foundation"/>
+  </metadata>
+  <manifest>
+    <item id="text" href="text.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine>
+    <itemref idref="text"/>
+  </spine>
+</package>
+"#;
+
+    /// An EPUB3 package document whose creator is refined by metas
+    const EPUB3: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">urn:isbn:9780553293357</dc:identifier>
+    <dc:title>Foundation</dc:title>
+    <dc:creator id="creator">Isaac Asimov</dc:creator>
+    <meta refines="#creator" property="role" scheme="marc:relators">aut</meta>
+    <meta refines="#creator" property="file-as">Asimov, Isaac</meta>
+    <meta property="dcterms:modified">2024-01-01T00:00:00Z</meta>
+  </metadata>
+  <manifest/>
+</package>
+"##;
+
+    #[test]
+    fn untouched_documents_are_written_back_as_they_are() {
+        for source in [EPUB2, EPUB3] {
+            assert_eq!(OpfDocument::parse(source).unwrap().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn metadata_is_read_from_epub2_attributes_and_epub3_refinements() {
+        let expected = vec![Creator {
+            name: String::from("Isaac Asimov"),
+            role: Some(String::from("aut")),
+            file_as: Some(String::from("Asimov, Isaac")),
+        }];
+        for source in [EPUB2, EPUB3] {
+            let opf = OpfDocument::parse(source).unwrap();
+            assert_eq!(opf.title().as_deref(), Some("Foundation"));
+            assert_eq!(opf.creators(), expected);
+            assert_eq!(opf.author_sort().as_deref(), Some("Asimov, Isaac"));
+        }
+        let epub2 = OpfDocument::parse(EPUB2).unwrap();
+        assert!(!epub2.is_epub3());
+        assert_eq!(epub2.dc_values("subject"), ["Science Fiction", "Space"]);
+        assert!(OpfDocument::parse(EPUB3).unwrap().is_epub3());
+    }
+
+    #[test]
+    fn only_edited_fields_change() {
+        let mut opf = OpfDocument::parse(EPUB2).unwrap();
+        opf.set_title("Foundation and Empire");
+        opf.set_dc_values(
+            "subject",
+            &[String::from("Science Fiction"), String::from("Empire")],
+        );
+
+        assert_eq!(
+            opf.to_string(),
+            EPUB2
+                .replace(
+                    "<dc:title>Foundation</dc:title>",
+                    "<dc:title>Foundation and Empire</dc:title>"
+                )
+                .replace(
+                    "<dc:subject>Space</dc:subject>",
+                    "<dc:subject>Empire</dc:subject>"
+                )
+                .replace(
+                    "<meta name=\"calibre:title_sort\" content=\"Foundation\"/>",
+                    "<meta name=\"calibre:title_sort\" content=\"Foundation and Empire\"/>"
+                )
+        );
+    }
+
+    #[test]
+    fn new_elements_are_added_at_the_end_of_the_metadata() {
+        let mut opf = OpfDocument::parse(EPUB2).unwrap();
+        opf.set_dc_values("subject", &[String::from("Science Fiction")]);
+        opf.set_dc_value("publisher", "Gnome Press");
+
+        assert_eq!(
+            opf.to_string(),
+            EPUB2
+                .replace(
+                    "    <dc:subject>Science Fiction</dc:subject>\n    <dc:subject>Space</dc:subject>\n",
+                    ""
+                )
+                .replace(
+                    "content=\"Foundation\"/>\n",
+                    "content=\"Foundation\"/>\n    <dc:subject>Science Fiction</dc:subject>\n    <dc:publisher>Gnome Press</dc:publisher>\n"
+                )
+        );
+    }
+
+    #[test]
+    fn epub2_creators_are_written_with_opf_attributes() {
+        let mut opf = OpfDocument::parse(EPUB2).unwrap();
+        let creators = [
+            Creator {
+                name: String::from("Isaac Asimov"),
+                role: Some(String::from("aut")),
+                file_as: None,
+            },
+            Creator {
+                name: String::from("Jane Doe"),
+                role: Some(String::from("trl")),
+                file_as: Some(String::from("Doe, Jane")),
+            },
+        ];
+        opf.set_creators(&creators);
+
+        assert_eq!(opf.creators(), creators);
+        let output = opf.to_string();
+        assert!(output.contains(
+            "    <dc:creator opf:role=\"trl\" opf:file-as=\"Doe, Jane\">Jane Doe</dc:creator>\n"
+        ));
+        assert_eq!(OpfDocument::parse(&output).unwrap().creators(), creators);
+    }
+
+    #[test]
+    fn epub3_creators_are_refined_by_metas() {
+        let mut opf = OpfDocument::parse(EPUB3).unwrap();
+        let creators = [Creator {
+            name: String::from("Jane Doe"),
+            role: Some(String::from("trl")),
+            file_as: Some(String::from("Doe, Jane")),
+        }];
+        opf.set_creators(&creators);
+
+        // The metas refining the old creator go along with it
+        assert_eq!(
+            opf.to_string(),
+            EPUB3
+                .replace(
+                    "    <dc:creator id=\"creator\">Isaac Asimov</dc:creator>\n    <meta refines=\"#creator\" property=\"role\" scheme=\"marc:relators\">aut</meta>\n    <meta refines=\"#creator\" property=\"file-as\">Asimov, Isaac</meta>\n",
+                    ""
+                )
+                .replace(
+                    "</meta>\n  </metadata>",
+                    "</meta>\n    <dc:creator id=\"creator\">Jane Doe</dc:creator>\n    <meta refines=\"#creator\" property=\"role\" scheme=\"marc:relators\">trl</meta>\n    <meta refines=\"#creator\" property=\"file-as\">Doe, Jane</meta>\n  </metadata>"
+                )
+        );
+        assert_eq!(opf.creators(), creators);
+    }
+
+    #[test]
+    fn epub2_series_is_written_to_calibre_metas() {
+        let mut opf = OpfDocument::parse(EPUB2).unwrap();
+        opf.set_series("Foundation", "1");
+        assert_eq!(
+            opf.series(),
+            Some((String::from("Foundation"), Some(String::from("1"))))
+        );
+
+        opf.set_series("Foundation", "2.5");
+        assert_eq!(
+            opf.to_string(),
+            EPUB2.replace(
+                "content=\"Foundation\"/>\n",
+                "content=\"Foundation\"/>\n    <meta name=\"calibre:series\" content=\"Foundation\"/>\n    <meta name=\"calibre:series_index\" content=\"2.5\"/>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn epub3_series_collections_are_reused() {
+        let source = EPUB3.replace(
+            "  </metadata>",
+            "    <meta property=\"belongs-to-collection\" id=\"c01\">Foundation</meta>
+    <meta refines=\"#c01\" property=\"collection-type\">series</meta>
+    <meta refines=\"#c01\" property=\"group-position\">1</meta>
+  </metadata>",
+        );
+        let mut opf = OpfDocument::parse(&source).unwrap();
+        assert_eq!(
+            opf.series(),
+            Some((String::from("Foundation"), Some(String::from("1"))))
+        );
+        opf.set_series("The Foundation Series", "2");
+
+        assert_eq!(
+            opf.to_string(),
+            source
+                .replace(
+                    "id=\"c01\">Foundation</meta>",
+                    "id=\"c01\">The Foundation Series</meta>"
+                )
+                .replace(
+                    "property=\"group-position\">1</meta>",
+                    "property=\"group-position\">2</meta>"
+                )
+        );
+    }
+
+    #[test]
+    fn epub3_collections_of_other_types_are_left_alone() {
+        let source = EPUB3.replace(
+            "  </metadata>",
+            "    <meta property=\"belongs-to-collection\" id=\"c01\">Great Books</meta>
+    <meta refines=\"#c01\" property=\"collection-type\">set</meta>
+    <meta property=\"belongs-to-collection\">Reading List</meta>
+  </metadata>",
+        );
+        let mut opf = OpfDocument::parse(&source).unwrap();
+        // A collection without a type may be a series, unlike a set
+        assert_eq!(opf.series(), Some((String::from("Reading List"), None)));
+        opf.set_series("Foundation", "1");
+
+        assert_eq!(
+            opf.to_string(),
+            source.replace(
+                "Reading List</meta>\n",
+                "Reading List</meta>
+    <meta property=\"belongs-to-collection\" id=\"series\">Foundation</meta>
+    <meta refines=\"#series\" property=\"collection-type\">series</meta>
+    <meta refines=\"#series\" property=\"group-position\">1</meta>\n"
+            )
+        );
+        assert_eq!(
+            opf.series(),
+            Some((String::from("Foundation"), Some(String::from("1"))))
+        );
+    }
+
+    #[test]
+    fn elements_use_the_prefixes_the_package_declares() {
+        let source = r#"<opf:package xmlns:opf="http://www.idpf.org/2007/opf" version="2.0">
+  <opf:metadata xmlns:purl="http://purl.org/dc/elements/1.1/">
+    <purl:title>Foundation</purl:title>
+    <opf:meta name="cover" content="cover"/>
+  </opf:metadata>
+</opf:package>"#;
+        let mut opf = OpfDocument::parse(source).unwrap();
+        opf.set_dc_value("language", "en");
+        opf.set_meta_content("calibre:series", "Foundation");
+
+        assert_eq!(
+            opf.to_string(),
+            source.replace(
+                "content=\"cover\"/>\n",
+                "content=\"cover\"/>\n    <purl:language>en</purl:language>\n    <opf:meta name=\"calibre:series\" content=\"Foundation\"/>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn legacy_metadata_wrappers_are_edited_in_place() {
+        let source = r#"<package version="2.0">
+  <metadata>
+    <dc-metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+      <dc:title>Foundation</dc:title>
+    </dc-metadata>
+    <x-metadata>
+      <meta name="cover" content="cover"/>
+    </x-metadata>
+  </metadata>
+</package>"#;
+        let mut opf = OpfDocument::parse(source).unwrap();
+        opf.set_dc_value("creator", "Isaac Asimov");
+        opf.set_meta_content("calibre:series", "Foundation");
+
+        assert_eq!(
+            opf.to_string(),
+            source
+                .replace(
+                    "</dc:title>\n",
+                    "</dc:title>\n      <dc:creator>Isaac Asimov</dc:creator>\n"
+                )
+                .replace(
+                    "content=\"cover\"/>\n",
+                    "content=\"cover\"/>\n      <meta name=\"calibre:series\" content=\"Foundation\"/>\n"
+                )
+        );
+        assert_eq!(opf.author().as_deref(), Some("Isaac Asimov"));
+    }
+
+    #[test]
+    fn documents_without_a_package_are_rejected() {
+        assert!(OpfDocument::parse("<container/>").is_err());
+        assert!(OpfDocument::parse("<package version=\"2.0\"><manifest/></package>").is_err());
+    }
+}
@@ -0,0 +1,699 @@
+use std::{fs::read, path::Path};
+
+use color_eyre::eyre::eyre;
+use lopdf::{
+    Dictionary, IncrementalDocument, Object, ObjectId, Stream, decode_text_string, text_string,
+};
+use quick_xml::escape::escape;
+
+use crate::{
+    opf::OpfDocument,
+    xml::{Element, XmlDocument},
+};
+
+/// The file extension of a PDF
+const PDF_EXTENSION: &str = "pdf";
+
+/// The namespace of RDF, which XMP packets are written in
+const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+/// The namespaces of the XMP properties the app writes, keyed by their usual prefix
+const XMP_NAMESPACES: [(&str, &str); 4] = [
+    ("dc", "http://purl.org/dc/elements/1.1/"),
+    ("pdf", "http://ns.adobe.com/pdf/1.3/"),
+    ("calibre", "http://calibre-ebook.com/xmp-namespace"),
+    (
+        "calibreSI",
+        "http://calibre-ebook.com/xmp-namespace-series-index",
+    ),
+];
+/// The XMP packet given to PDFs that don't have one
+const EMPTY_XMP: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+  <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+    <rdf:Description rdf:about=\"\">
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>";
+/// The Dublin Core fields written to the XMP packet as they are, along with the kind of
+/// RDF array holding them
+const XMP_FIELDS: [(&str, &str); 4] = [
+    ("publisher", "Bag"),
+    ("language", "Bag"),
+    ("rights", "Alt"),
+    ("date", "Seq"),
+];
+
+/// Whether the path is a PDF
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(PDF_EXTENSION))
+}
+
+/// Struct holding a PDF, its metadata kept in both the document information dictionary and
+/// the XMP packet of the catalog
+///
+/// Edits are written as an incremental update appended to the original file, so the pages
+/// of the document are never rewritten.
+pub struct PdfBook {
+    /// The document, with the edited objects held in its new revision
+    document: IncrementalDocument,
+}
+
+impl PdfBook {
+    /// Read a PDF from a file
+    pub fn read(path: &Path) -> color_eyre::Result<Self> {
+        let data = read(path).map_err(|err| eyre!("Invalid PDF {}: {}", path.display(), err))?;
+
+        PdfBook::parse(&data).map_err(|err| eyre!("Invalid PDF {}: {}", path.display(), err))
+    }
+
+    /// Parse a PDF held in memory, refusing encrypted PDFs as their objects can't be
+    /// rewritten without their key
+    pub fn parse(data: &[u8]) -> color_eyre::Result<Self> {
+        let mut document = IncrementalDocument::load_from(data)?;
+        let previous = document.get_prev_documents();
+        if previous.trailer.has(b"Encrypt") || previous.encryption_state.is_some() {
+            return Err(eyre!("encrypted PDFs can't be edited"));
+        }
+        let version = previous.version.to_owned();
+        document.new_document.version = version;
+        // The new revision's cross-reference stream is written without a filter
+        document.new_document.trailer.remove(b"DecodeParms");
+
+        Ok(PdfBook { document })
+    }
+
+    /// Get the metadata of the PDF as an OPF package document, so that it can be edited in
+    /// the same way as an epub's
+    ///
+    /// Values from the XMP packet are used over the ones in the information dictionary. The
+    /// subject of the PDF becomes the description and its keywords the subjects, and PDFs
+    /// without a title are given the fallback title.
+    pub fn metadata(&self, fallback_title: &str) -> color_eyre::Result<OpfDocument> {
+        let info = self.info();
+        let xmp = self.xmp()?;
+        let info_value = |key: &[u8]| {
+            info.and_then(|info| info.get(key).ok())
+                .and_then(|value| decode_text_string(value).ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let mut elements = Vec::new();
+        let title = xmp_values(&xmp, "dc:title")
+            .into_iter()
+            .next()
+            .or_else(|| info_value(b"Title"))
+            .unwrap_or_else(|| fallback_title.to_string());
+        elements.push(format!("<dc:title>{}</dc:title>", escape(&title)));
+        let mut authors = xmp_values(&xmp, "dc:creator");
+        if authors.is_empty() {
+            authors = split_list(&info_value(b"Author").unwrap_or_default(), ';');
+        }
+        for author in authors {
+            elements.push(format!("<dc:creator>{}</dc:creator>", escape(&author)));
+        }
+        if let Some(description) = xmp_values(&xmp, "dc:description")
+            .into_iter()
+            .next()
+            .or_else(|| info_value(b"Subject"))
+        {
+            elements.push(format!(
+                "<dc:description>{}</dc:description>",
+                escape(&description)
+            ));
+        }
+        let mut keywords = xmp_values(&xmp, "dc:subject");
+        if keywords.is_empty() {
+            let pdf_keywords = xmp_values(&xmp, "pdf:Keywords").into_iter().next();
+            keywords = split_list(
+                &pdf_keywords
+                    .or_else(|| info_value(b"Keywords"))
+                    .unwrap_or_default(),
+                ',',
+            );
+        }
+        for keyword in keywords {
+            elements.push(format!("<dc:subject>{}</dc:subject>", escape(&keyword)));
+        }
+        for (name, _) in XMP_FIELDS {
+            for value in xmp_values(&xmp, &format!("dc:{}", name)) {
+                elements.push(format!("<dc:{0}>{1}</dc:{0}>", name, escape(&value)));
+            }
+        }
+        if let Some(series) = xmp_property(&xmp, "calibre:series") {
+            let value = |name: &str| {
+                series
+                    .elements()
+                    .find(|element| element.name == name)
+                    .map(|element| element.text().trim().to_string())
+                    .or_else(|| series.attribute(name).map(String::from))
+            };
+            for (meta, name) in [
+                ("calibre:series", "rdf:value"),
+                ("calibre:series_index", "calibreSI:series_index"),
+            ] {
+                if let Some(value) = value(name) {
+                    elements.push(format!(
+                        "<meta name=\"{}\" content=\"{}\"/>",
+                        meta,
+                        escape(&value)
+                    ));
+                }
+            }
+        }
+
+        OpfDocument::from_metadata(&elements)
+    }
+
+    /// Write the metadata of an OPF package document to the information dictionary and the
+    /// XMP packet, giving the PDF an XMP packet when it doesn't have one
+    ///
+    /// Only authors are kept, as PDFs have no place for other creators. The series is written
+    /// with calibre's XMP schema.
+    pub fn set_metadata(&mut self, metadata: &OpfDocument) -> color_eyre::Result<()> {
+        let title = metadata.title();
+        let authors: Vec<String> = metadata
+            .creators()
+            .into_iter()
+            .filter(|creator| creator.is_author())
+            .map(|creator| creator.name)
+            .collect();
+        let description = metadata.dc_value("description");
+        let keywords = metadata.dc_values("subject");
+
+        let mut info = self.info().cloned().unwrap_or_default();
+        for (key, value) in [
+            ("Title", title.clone()),
+            ("Author", Some(authors.join("; "))),
+            ("Subject", description.clone()),
+            ("Keywords", Some(keywords.join(", "))),
+        ] {
+            match value.filter(|value| !value.is_empty()) {
+                Some(value) => info.set(key, text_string(&value)),
+                None => {
+                    info.remove(key.as_bytes());
+                }
+            }
+        }
+        let new_document = &mut self.document.new_document;
+        match new_document
+            .trailer
+            .get(b"Info")
+            .and_then(Object::as_reference)
+        {
+            Ok(id) => new_document.set_object(id, info),
+            Err(_) => {
+                let id = new_document.add_object(info);
+                new_document.trailer.set("Info", id);
+            }
+        }
+
+        let mut xmp = self.xmp()?;
+        set_xmp_array(&mut xmp, "dc:title", "Alt", title.as_slice());
+        set_xmp_array(&mut xmp, "dc:creator", "Seq", &authors);
+        set_xmp_array(&mut xmp, "dc:description", "Alt", description.as_slice());
+        set_xmp_array(&mut xmp, "dc:subject", "Bag", &keywords);
+        set_xmp_text(&mut xmp, "pdf:Keywords", &keywords.join(", "));
+        for (name, kind) in XMP_FIELDS {
+            set_xmp_array(
+                &mut xmp,
+                &format!("dc:{}", name),
+                kind,
+                &metadata.dc_values(name),
+            );
+        }
+        remove_xmp_property(&mut xmp, "calibre:series");
+        if let Some((series, position)) = metadata.series() {
+            let mut element =
+                Element::new("calibre:series").with_attribute("rdf:parseType", "Resource");
+            element.append_child(Element::new("rdf:value").with_text(&series));
+            if let Some(position) = position {
+                element.append_child(Element::new("calibreSI:series_index").with_text(&position));
+            }
+            add_xmp_property(&mut xmp, element);
+        }
+        self.set_xmp(&xmp)?;
+
+        Ok(())
+    }
+
+    /// Write the PDF out with the incremental update holding the edited objects appended
+    pub fn save(&mut self) -> color_eyre::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.document.save_to(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// Get the document information dictionary
+    fn info(&self) -> Option<&Dictionary> {
+        self.trailer_object(b"Info")?.as_dict().ok()
+    }
+
+    /// Get an object the trailer refers to
+    fn trailer_object(&self, key: &[u8]) -> Option<&Object> {
+        let id = self
+            .document
+            .new_document
+            .trailer
+            .get(key)
+            .and_then(Object::as_reference)
+            .ok()?;
+
+        self.object(id).ok()
+    }
+
+    /// Get an object, from the new revision once it has been edited
+    fn object(&self, id: ObjectId) -> color_eyre::Result<&Object> {
+        Ok(match self.document.new_document.get_object(id) {
+            Ok(object) => object,
+            Err(_) => self.document.get_prev_documents().get_object(id)?,
+        })
+    }
+
+    /// Get the XMP packet of the catalog, or an empty one when the PDF doesn't have one
+    fn xmp(&self) -> color_eyre::Result<XmlDocument> {
+        let Some(id) = self
+            .trailer_object(b"Root")
+            .and_then(|catalog| catalog.as_dict().ok())
+            .and_then(|catalog| catalog.get(b"Metadata").ok())
+            .and_then(|metadata| metadata.as_reference().ok())
+        else {
+            return XmlDocument::parse(EMPTY_XMP);
+        };
+        let stream = self.object(id)?.as_stream()?;
+        let content = stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone());
+        let source = String::from_utf8_lossy(&content);
+
+        XmlDocument::parse(source.trim_start_matches('\u{feff}').trim_end_matches('\0'))
+            .map_err(|err| eyre!("Invalid XMP packet: {}", err))
+    }
+
+    /// Replace the XMP packet of the catalog, adding it to the catalog when the PDF doesn't
+    /// have one
+    ///
+    /// The packet is left uncompressed so that it can be found without reading the PDF.
+    fn set_xmp(&mut self, xmp: &XmlDocument) -> color_eyre::Result<()> {
+        let stream = Stream::new(
+            Dictionary::from_iter([
+                ("Type", Object::Name(b"Metadata".to_vec())),
+                ("Subtype", Object::Name(b"XML".to_vec())),
+            ]),
+            xmp.to_string().into_bytes(),
+        );
+        let root_id = self
+            .document
+            .new_document
+            .trailer
+            .get(b"Root")
+            .and_then(Object::as_reference)?;
+        self.document.opt_clone_object_to_new_document(root_id)?;
+        let new_document = &mut self.document.new_document;
+        match new_document
+            .get_dictionary(root_id)?
+            .get(b"Metadata")
+            .and_then(Object::as_reference)
+        {
+            Ok(id) => new_document.set_object(id, stream),
+            Err(_) => {
+                let id = new_document.add_object(stream);
+                new_document
+                    .get_dictionary_mut(root_id)?
+                    .set("Metadata", id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Split a list written in a single value, such as the authors or keywords of the
+/// information dictionary
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Get every `rdf:Description` of an XMP packet
+fn descriptions(xmp: &XmlDocument) -> Vec<&Element> {
+    let Some(rdf) = rdf_element(xmp.root()) else {
+        return Vec::new();
+    };
+
+    rdf.elements()
+        .filter(|element| element.name == "rdf:Description")
+        .collect()
+}
+
+/// Find the `rdf:RDF` element of an XMP packet, which is the root or a child of the root
+fn rdf_element(root: &Element) -> Option<&Element> {
+    if root.name == "rdf:RDF" {
+        return Some(root);
+    }
+
+    root.elements().find(|element| element.name == "rdf:RDF")
+}
+
+/// Find the element of an XMP property
+fn xmp_property<'a>(xmp: &'a XmlDocument, name: &str) -> Option<&'a Element> {
+    descriptions(xmp)
+        .into_iter()
+        .find_map(|description| description.elements().find(|element| element.name == name))
+}
+
+/// Get the values of an XMP property, which can be an RDF array, text or an attribute of its
+/// `rdf:Description`
+///
+/// The `x-default` item of a language alternative comes first.
+fn xmp_values(xmp: &XmlDocument, name: &str) -> Vec<String> {
+    if let Some(property) = xmp_property(xmp, name) {
+        let values: Vec<String> = match property.elements().next() {
+            Some(array) => {
+                let mut items: Vec<&Element> = array
+                    .elements()
+                    .filter(|element| element.name == "rdf:li")
+                    .collect();
+                items.sort_by_key(|item| item.attribute("xml:lang") != Some("x-default"));
+                items.into_iter().map(|item| item.text()).collect()
+            }
+            None => vec![property.text()],
+        };
+
+        return values
+            .into_iter()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+    }
+
+    descriptions(xmp)
+        .into_iter()
+        .find_map(|description| description.attribute(name))
+        .map(|value| vec![value.trim().to_string()])
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Remove an XMP property from every `rdf:Description`, whether it is an element or an
+/// attribute
+fn remove_xmp_property(xmp: &mut XmlDocument, name: &str) {
+    let root = xmp.root_mut();
+    let rdf = if root.name == "rdf:RDF" {
+        root
+    } else {
+        match root
+            .elements_mut()
+            .find(|element| element.name == "rdf:RDF")
+        {
+            Some(rdf) => rdf,
+            None => return,
+        }
+    };
+    for description in rdf
+        .elements_mut()
+        .filter(|element| element.name == "rdf:Description")
+    {
+        description.remove_children(|element| element.name == name);
+        if description.attribute(name).is_some() {
+            description.remove_attribute(name);
+        }
+    }
+}
+
+/// Add an XMP property to the first `rdf:Description`, declaring its namespace there when it
+/// isn't declared already
+fn add_xmp_property(xmp: &mut XmlDocument, property: Element) {
+    let prefixes: Vec<String> = std::iter::once(property.name.to_owned())
+        .chain(property.elements().map(|element| element.name.to_owned()))
+        .filter_map(|name| name.split_once(':').map(|(prefix, _)| prefix.to_string()))
+        .filter(|prefix| prefix != "rdf")
+        .collect();
+    let root = xmp.root_mut();
+    let mut declared: Vec<String> = root
+        .attributes()
+        .iter()
+        .map(|(key, _)| key.to_owned())
+        .collect();
+    let rdf = if root.name == "rdf:RDF" {
+        root
+    } else {
+        if root.elements().all(|element| element.name != "rdf:RDF") {
+            root.append_child(Element::new("rdf:RDF").with_attribute("xmlns:rdf", RDF_NAMESPACE));
+        }
+        root.elements_mut()
+            .find(|element| element.name == "rdf:RDF")
+            .unwrap()
+    };
+    declared.extend(rdf.attributes().iter().map(|(key, _)| key.to_owned()));
+    if rdf
+        .elements()
+        .all(|element| element.name != "rdf:Description")
+    {
+        rdf.append_child(Element::new("rdf:Description").with_attribute("rdf:about", ""));
+    }
+    let description = rdf
+        .elements_mut()
+        .find(|element| element.name == "rdf:Description")
+        .unwrap();
+    for prefix in prefixes {
+        let key = format!("xmlns:{}", prefix);
+        if !declared.contains(&key)
+            && description.attribute(&key).is_none()
+            && let Some((_, namespace)) = XMP_NAMESPACES.iter().find(|(name, _)| *name == prefix)
+        {
+            description.set_attribute(&key, namespace);
+        }
+    }
+    description.append_child(property);
+}
+
+/// Set an XMP property holding an RDF array, removing it when there are no values
+fn set_xmp_array(xmp: &mut XmlDocument, name: &str, kind: &str, values: &[String]) {
+    if xmp_values(xmp, name) == values {
+        return;
+    }
+    remove_xmp_property(xmp, name);
+    if values.is_empty() {
+        return;
+    }
+
+    let mut array = Element::new(&format!("rdf:{}", kind));
+    for value in values {
+        let mut item = Element::new("rdf:li").with_text(value);
+        if kind == "Alt" {
+            item.set_attribute("xml:lang", "x-default");
+        }
+        array.append_child(item);
+    }
+    let mut property = Element::new(name);
+    property.append_child(array);
+    add_xmp_property(xmp, property);
+}
+
+/// Set an XMP property holding text, removing it when the text is empty
+fn set_xmp_text(xmp: &mut XmlDocument, name: &str, value: &str) {
+    if xmp_values(xmp, name)
+        .first()
+        .map(String::as_str)
+        .unwrap_or_default()
+        == value
+    {
+        return;
+    }
+    remove_xmp_property(xmp, name);
+    if !value.is_empty() {
+        add_xmp_property(xmp, Element::new(name).with_text(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{Document, dictionary};
+
+    use super::*;
+    use crate::creator::Creator;
+
+    /// Build a PDF with a single empty page, the given information dictionary and, when
+    /// given, an XMP packet
+    fn pdf(info: Dictionary, xmp: Option<&str>) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        };
+        if let Some(xmp) = xmp {
+            let metadata_id = document.add_object(Stream::new(
+                dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+                xmp.as_bytes().to_vec(),
+            ));
+            catalog.set("Metadata", metadata_id);
+        }
+        let catalog_id = document.add_object(catalog);
+        document.trailer.set("Root", catalog_id);
+        if !info.is_empty() {
+            let info_id = document.add_object(info);
+            document.trailer.set("Info", info_id);
+        }
+
+        let mut data = Vec::new();
+        document.save_to(&mut data).unwrap();
+
+        data
+    }
+
+    /// Build a PDF whose metadata is only in its information dictionary
+    fn info_pdf() -> Vec<u8> {
+        pdf(
+            dictionary! {
+                "Title" => text_string("Foundation"),
+                "Author" => text_string("Isaac Asimov"),
+                "Keywords" => text_string("Science Fiction, Space"),
+                "Producer" => text_string("A word processor"),
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn metadata_round_trips_through_an_incremental_update() {
+        let original = info_pdf();
+        let mut book = PdfBook::parse(&original).unwrap();
+        let mut metadata = book.metadata("fallback").unwrap();
+        metadata.set_title("Foundation and Empire");
+        metadata.set_creators(&[
+            Creator {
+                name: String::from("Isaac Asimov"),
+                ..Creator::default()
+            },
+            Creator {
+                name: String::from("Jane Doe"),
+                role: Some(String::from("trl")),
+                file_as: None,
+            },
+        ]);
+        metadata.set_dc_value("description", "The second book");
+        metadata.set_dc_values(
+            "subject",
+            &[String::from("Science Fiction"), String::from("Empire")],
+        );
+        metadata.set_dc_values("publisher", &[String::from("Gnome Press")]);
+        metadata.set_series("Foundation", "2");
+        book.set_metadata(&metadata).unwrap();
+        let output = book.save().unwrap();
+
+        assert!(output.starts_with(&original));
+        let edited = PdfBook::parse(&output).unwrap();
+        let reread = edited.metadata("fallback").unwrap();
+        assert_eq!(reread.title().as_deref(), Some("Foundation and Empire"));
+        let names: Vec<String> = reread
+            .creators()
+            .into_iter()
+            .map(|creator| creator.name)
+            .collect();
+        assert_eq!(names, ["Isaac Asimov"]);
+        assert_eq!(
+            reread.dc_value("description").as_deref(),
+            Some("The second book")
+        );
+        assert_eq!(reread.dc_values("subject"), ["Science Fiction", "Empire"]);
+        assert_eq!(reread.dc_values("publisher"), ["Gnome Press"]);
+        assert_eq!(
+            reread.series(),
+            Some((String::from("Foundation"), Some(String::from("2"))))
+        );
+
+        let info = edited.info().unwrap();
+        let info_value = |key: &[u8]| decode_text_string(info.get(key).unwrap()).unwrap();
+        assert_eq!(info_value(b"Title"), "Foundation and Empire");
+        assert_eq!(info_value(b"Author"), "Isaac Asimov");
+        assert_eq!(info_value(b"Keywords"), "Science Fiction, Empire");
+        assert_eq!(info_value(b"Producer"), "A word processor");
+    }
+
+    #[test]
+    fn pdfs_without_metadata_are_given_an_xmp_packet() {
+        let original = pdf(Dictionary::new(), None);
+        let mut book = PdfBook::parse(&original).unwrap();
+        let mut metadata = book.metadata("Untitled Book").unwrap();
+        assert_eq!(metadata.title().as_deref(), Some("Untitled Book"));
+        assert!(metadata.creators().is_empty());
+
+        metadata.set_title("Foundation");
+        book.set_metadata(&metadata).unwrap();
+        let output = book.save().unwrap();
+
+        assert!(output.starts_with(&original));
+        let edited = PdfBook::parse(&output).unwrap();
+        assert_eq!(
+            xmp_values(&edited.xmp().unwrap(), "dc:title"),
+            ["Foundation"]
+        );
+        assert_eq!(
+            edited.metadata("Untitled Book").unwrap().title().as_deref(),
+            Some("Foundation")
+        );
+    }
+
+    #[test]
+    fn xmp_values_are_used_over_the_information_dictionary() {
+        let xmp = EMPTY_XMP.replace(
+            "<rdf:Description rdf:about=\"\">",
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+      <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Foundation</rdf:li></rdf:Alt></dc:title>
+      <dc:creator><rdf:Seq><rdf:li>Isaac Asimov</rdf:li></rdf:Seq></dc:creator>",
+        );
+        let book = PdfBook::parse(&pdf(
+            dictionary! {
+                "Title" => text_string("foundation.doc"),
+                "Author" => text_string("Typist"),
+            },
+            Some(&xmp),
+        ))
+        .unwrap();
+
+        let metadata = book.metadata("fallback").unwrap();
+        assert_eq!(metadata.title().as_deref(), Some("Foundation"));
+        assert_eq!(metadata.author().as_deref(), Some("Isaac Asimov"));
+    }
+
+    #[test]
+    fn unchanged_metadata_keeps_the_original_bytes() {
+        let original = info_pdf();
+        let mut book = PdfBook::parse(&original).unwrap();
+        book.set_metadata(&book.metadata("fallback").unwrap())
+            .unwrap();
+        let output = book.save().unwrap();
+
+        assert!(output.starts_with(&original));
+        let reread = PdfBook::parse(&output)
+            .unwrap()
+            .metadata("fallback")
+            .unwrap();
+        assert_eq!(reread.title().as_deref(), Some("Foundation"));
+        assert_eq!(reread.dc_values("subject"), ["Science Fiction", "Space"]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs::{read_to_string, remove_file},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    manifest::SeriesManifest,
+    storage::{BackupMode, replace_file, sibling_temp_file},
+};
+
+/// The file the session is autosaved to, inside the directory the app was started in
+pub const SESSION_PATH: &str = ".ebook_meta_editor_session.json";
+
+/// Struct holding an in-progress editing session so it can be resumed later
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Session {
+    /// The index of the page the session was on
+    pub current_page: usize,
+    /// The number of series being edited
+    pub series_num: i8,
+    /// The index of the series being edited
+    pub current_series_num: usize,
+    /// The series entered so far, with every book's title
+    pub series: Vec<SeriesManifest>,
+    /// The books that were already edited on the loading page
+    #[serde(default)]
+    pub finished_books: Vec<PathBuf>,
+}
+
+impl Session {
+    /// Load the session from a file
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        Ok(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    /// Save the session to a file, replacing it atomically so that a crash can't leave a
+    /// session that fails to load
+    pub fn save(&self, path: &Path) -> color_eyre::Result<()> {
+        let mut temp_file = sibling_temp_file(path)?;
+        temp_file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        replace_file(temp_file, path, &BackupMode::Disabled)?;
+
+        Ok(())
+    }
+
+    /// Delete the session file if it exists
+    pub fn clear(path: &Path) -> color_eyre::Result<()> {
+        if path.exists() {
+            remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn sessions_round_trip_through_their_file() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join(SESSION_PATH);
+        let session = Session {
+            current_page: 2,
+            series_num: 1,
+            current_series_num: 0,
+            series: Vec::new(),
+            finished_books: vec![PathBuf::from("Foundation.epub")],
+        };
+
+        session.save(&path).unwrap();
+        Session {
+            current_page: 3,
+            ..session.clone()
+        }
+        .save(&path)
+        .unwrap();
+        assert_eq!(Session::load(&path).unwrap().current_page, 3);
+        // The session is replaced without leaving a temporary file or a backup behind
+        assert_eq!(directory.path().read_dir().unwrap().count(), 1);
+
+        Session::clear(&path).unwrap();
+        assert!(!path.exists());
+        assert!(Session::load(&path).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+
+use crate::{
+    comic::VOLUME_META,
+    manifest::{BookManifest, FieldValue, Manifest, SeriesManifest},
+    model::{DcField, parse_position},
+    opf::OpfDocument,
+    storage::{BackupMode, replace_file, sibling_temp_file},
+};
+
+/// The extension of the OPF sidecars kept next to books
+pub const SIDECAR_EXTENSION: &str = "opf";
+/// The format string of the series imported from sidecars, keeping the sidecars' titles
+const SIDECAR_FORMAT: &str = "${title}";
+
+/// Get the path of a book's sidecar, the book's path with `.opf` added
+///
+/// The book's own extension is kept so that the formats of a book sharing a directory and
+/// name, as in a Calibre library, each have their own sidecar.
+pub fn sidecar_path(book_path: &Path) -> PathBuf {
+    let mut file_name = book_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}", SIDECAR_EXTENSION));
+
+    book_path.with_file_name(file_name)
+}
+
+/// Whether a book has a sidecar next to it
+pub fn has_sidecar(book_path: &Path) -> bool {
+    sidecar_path(book_path).is_file()
+}
+
+/// Read the package document of a book's sidecar
+pub fn read_sidecar(book_path: &Path) -> color_eyre::Result<OpfDocument> {
+    let path = sidecar_path(book_path);
+    let content = read_to_string(&path)
+        .map_err(|err| eyre!("Unable to read sidecar {}: {}", path.display(), err))?;
+
+    OpfDocument::parse(&content).map_err(|err| eyre!("Invalid sidecar {}: {}", path.display(), err))
+}
+
+/// Write a package document to a book's sidecar, replacing the sidecar already there,
+/// returning the path of the sidecar
+pub fn write_sidecar(book_path: &Path, metadata: &OpfDocument) -> color_eyre::Result<PathBuf> {
+    let path = sidecar_path(book_path);
+    let mut temp_file = sibling_temp_file(&path)?;
+    temp_file.write_all(metadata.to_string().as_bytes())?;
+    replace_file(temp_file, &path, &BackupMode::Disabled)?;
+
+    Ok(path)
+}
+
+/// Build a series manifest from the sidecars of the given books, so that importing them
+/// goes through the same edit as any other manifest
+///
+/// Books are grouped by the series in their sidecar and ordered by their position, with
+/// the books outside of any series in a series without a name, which leaves their series
+/// as it is. Every book keeps the title, creators and fields of its sidecar, and fields
+/// missing from the sidecar aren't changed.
+pub fn sidecar_manifest(books: &[PathBuf]) -> color_eyre::Result<Manifest> {
+    let mut manifest = Manifest::default();
+    for book in books {
+        let sidecar = read_sidecar(book)?;
+        let (name, position) = sidecar
+            .series()
+            .filter(|(series, _)| !series.trim().is_empty())
+            .map_or((String::new(), None), |(series, position)| {
+                (series.trim().to_string(), position)
+            });
+        let mut fields = BTreeMap::new();
+        for field in DcField::VALUES {
+            // Subjects are kept as a list so that a `;` within one stays part of it
+            let value = match field {
+                DcField::Subjects => Some(sidecar.dc_values(field.element()))
+                    .filter(|subjects| !subjects.is_empty())
+                    .map(FieldValue::List),
+                DcField::Volume => sidecar.meta_content(VOLUME_META).map(FieldValue::Text),
+                _ => sidecar.dc_value(field.element()).map(FieldValue::Text),
+            };
+            if let Some(value) = value.filter(|value| !value.to_input(field).trim().is_empty()) {
+                fields.insert(field, value);
+            }
+        }
+        let creators = sidecar.creators();
+        let book = BookManifest {
+            path: book.to_owned(),
+            title: sidecar.title(),
+            position,
+            authors: (!creators.is_empty()).then_some(creators),
+            fields,
+            cover: None,
+        };
+
+        match manifest
+            .series
+            .iter_mut()
+            .find(|series| series.name == name)
+        {
+            Some(series) => series.books.push(book),
+            None => manifest.series.push(SeriesManifest {
+                name,
+                format: SIDECAR_FORMAT.to_string(),
+                path_format: None,
+                authors: None,
+                normalize_authors: false,
+                fields: BTreeMap::new(),
+                cover: None,
+                books: vec![book],
+            }),
+        }
+    }
+    // Books without a position keep their place after the books that have one
+    let position = |book: &BookManifest| {
+        book.position
+            .as_deref()
+            .and_then(|position| parse_position(position).ok())
+            .unwrap_or(f64::INFINITY)
+    };
+    for series in &mut manifest.series {
+        series
+            .books
+            .sort_by(|a, b| position(a).total_cmp(&position(b)));
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::creator::Creator;
+
+    /// Build the package document of a book with the given title, series metas and subjects
+    fn metadata(title: &str, series: Option<(&str, &str)>, subjects: &[&str]) -> OpfDocument {
+        let mut elements = vec![
+            format!("<dc:title>{}</dc:title>", title),
+            String::from("<dc:creator opf:role=\"aut\">Isaac Asimov</dc:creator>"),
+        ];
+        if let Some((series, position)) = series {
+            elements.push(format!(
+                "<meta name=\"calibre:series\" content=\"{}\"/>",
+                series
+            ));
+            elements.push(format!(
+                "<meta name=\"calibre:series_index\" content=\"{}\"/>",
+                position
+            ));
+        }
+        for subject in subjects {
+            elements.push(format!("<dc:subject>{}</dc:subject>", subject));
+        }
+
+        OpfDocument::from_metadata(&elements).unwrap()
+    }
+
+    #[test]
+    fn sidecars_are_named_after_the_whole_file_name() {
+        assert_eq!(
+            sidecar_path(Path::new("books/Foundation.epub")),
+            Path::new("books/Foundation.epub.opf")
+        );
+        assert_eq!(
+            sidecar_path(Path::new("books/Foundation.pdf")),
+            Path::new("books/Foundation.pdf.opf")
+        );
+        assert_eq!(
+            sidecar_path(Path::new("books/Foundation.fb2.zip")),
+            Path::new("books/Foundation.fb2.zip.opf")
+        );
+    }
+
+    #[test]
+    fn sidecars_round_trip_the_package_document() {
+        let directory = tempdir().unwrap();
+        let book = directory.path().join("Foundation.epub");
+        assert!(!has_sidecar(&book));
+
+        let foundation = metadata("Foundation", Some(("Foundation", "1")), &["Space"]);
+        let path = write_sidecar(&book, &foundation).unwrap();
+        assert_eq!(path, directory.path().join("Foundation.epub.opf"));
+        assert!(has_sidecar(&book));
+        let sidecar = read_sidecar(&book).unwrap();
+        assert_eq!(sidecar.title().as_deref(), Some("Foundation"));
+        assert_eq!(sidecar.creators(), foundation.creators());
+        assert_eq!(sidecar.series(), foundation.series());
+        assert_eq!(sidecar.dc_values("subject"), ["Space"]);
+
+        // Writing it again replaces the sidecar already there
+        write_sidecar(&book, &metadata("Foundation and Empire", None, &[])).unwrap();
+        assert_eq!(
+            read_sidecar(&book).unwrap().title().as_deref(),
+            Some("Foundation and Empire")
+        );
+    }
+
+    #[test]
+    fn manifests_group_books_by_series_in_order() {
+        let directory = tempdir().unwrap();
+        let books: Vec<PathBuf> = [
+            ("Second Foundation", Some(("Foundation", "3")), &[][..]),
+            ("I, Robot", None, &["Robots; Short Stories"][..]),
+            (
+                "Foundation",
+                Some(("Foundation", "1")),
+                &["Space", "Empire"][..],
+            ),
+            ("Prelude", Some(("Foundation", "")), &[][..]),
+        ]
+        .into_iter()
+        .map(|(title, series, subjects)| {
+            let book = directory.path().join(format!("{}.epub", title));
+            write_sidecar(&book, &metadata(title, series, subjects)).unwrap();
+            book
+        })
+        .collect();
+
+        let manifest = sidecar_manifest(&books).unwrap();
+        let names: Vec<&str> = manifest
+            .series
+            .iter()
+            .map(|series| series.name.as_str())
+            .collect();
+        assert_eq!(names, ["Foundation", ""]);
+        let foundation = &manifest.series[0];
+        assert_eq!(foundation.format, SIDECAR_FORMAT);
+        let titles: Vec<Option<&str>> = foundation
+            .books
+            .iter()
+            .map(|book| book.title.as_deref())
+            .collect();
+        // Books without a position come after the ones that have one
+        assert_eq!(
+            titles,
+            [
+                Some("Foundation"),
+                Some("Second Foundation"),
+                Some("Prelude")
+            ]
+        );
+        let first = &foundation.books[0];
+        assert_eq!(first.path, books[2]);
+        assert_eq!(first.position.as_deref(), Some("1"));
+        assert_eq!(
+            first.authors,
+            Some(vec![Creator {
+                name: String::from("Isaac Asimov"),
+                role: Some(String::from("aut")),
+                file_as: None,
+            }])
+        );
+        assert_eq!(
+            first.fields,
+            BTreeMap::from([(
+                DcField::Subjects,
+                FieldValue::List(vec![String::from("Space"), String::from("Empire")])
+            )])
+        );
+
+        // A `;` within a subject stays part of it
+        let robot = &manifest.series[1].books[0];
+        assert_eq!(robot.position, None);
+        assert_eq!(
+            robot.fields.get(&DcField::Subjects),
+            Some(&FieldValue::List(vec![String::from(
+                "Robots; Short Stories"
+            )]))
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    fs::{File, copy, create_dir_all, metadata, remove_file, rename, set_permissions},
+    io::{BufReader, ErrorKind, copy as copy_stream},
+    path::{Component, Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+use tempfile::NamedTempFile;
+
+use crate::model::file_stem;
+
+/// The directory backups are kept in when using a backup directory
+pub const BACKUP_DIRECTORY: &str = ".ebook_meta_editor_backups";
+
+/// Enum of the ways a book can be backed up before it is overwritten
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackupMode {
+    /// Don't keep a backup
+    Disabled,
+    /// Keep a `.bak` copy next to the book
+    Sibling,
+    /// Keep a copy inside a backup directory, mirroring the book's absolute path
+    Directory(PathBuf),
+}
+
+impl BackupMode {
+    /// Get the next backup mode for cycling through the modes
+    pub fn next(&self) -> Self {
+        match self {
+            BackupMode::Disabled => BackupMode::Sibling,
+            BackupMode::Sibling => BackupMode::Directory(PathBuf::from(BACKUP_DIRECTORY)),
+            BackupMode::Directory(_) => BackupMode::Disabled,
+        }
+    }
+
+    /// Get the path the backup of a book is kept at
+    pub fn backup_path(&self, book_path: &Path) -> Option<PathBuf> {
+        match self {
+            BackupMode::Disabled => None,
+            BackupMode::Sibling => {
+                let mut file_name = book_path.file_name()?.to_os_string();
+                file_name.push(".bak");
+                Some(book_path.with_file_name(file_name))
+            }
+            BackupMode::Directory(directory) => {
+                let relative_path: PathBuf = book_path
+                    .components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect();
+                Some(directory.join(relative_path))
+            }
+        }
+    }
+
+    /// Whether a backup of the book exists
+    pub fn has_backup(&self, book_path: &Path) -> bool {
+        self.backup_path(book_path)
+            .is_some_and(|backup_path| backup_path.is_file())
+    }
+}
+
+/// Create a temporary file in the same directory as the given path, so that it can later
+/// be renamed over that path
+pub fn sibling_temp_file(path: &Path) -> color_eyre::Result<NamedTempFile> {
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    Ok(NamedTempFile::new_in(directory)?)
+}
+
+/// Atomically replace the file at the given path with the temporary file
+///
+/// The temporary file is flushed to disk before being renamed over the original, so the
+/// original is either left untouched or fully replaced. If a backup mode is given the
+/// original is copied to its backup path first, an existing backup is never overwritten so
+/// that it always holds the book as it was before it was first edited.
+pub fn replace_file(
+    temp_file: NamedTempFile,
+    path: &Path,
+    backup_mode: &BackupMode,
+) -> color_eyre::Result<()> {
+    if let Ok(original_metadata) = metadata(path) {
+        set_permissions(temp_file.path(), original_metadata.permissions())?;
+        if let Some(backup_path) = backup_mode.backup_path(path)
+            && !backup_path.exists()
+        {
+            if let Some(parent) = backup_path.parent() {
+                create_dir_all(parent)?;
+            }
+            copy(path, &backup_path)?;
+        }
+    }
+
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path)?;
+    sync_parent_directory(path);
+
+    Ok(())
+}
+
+/// Restore a book from its backup, replacing the current file atomically
+pub fn restore_backup(path: &Path, backup_mode: &BackupMode) -> color_eyre::Result<()> {
+    let backup_path = backup_mode
+        .backup_path(path)
+        .filter(|backup_path| backup_path.is_file())
+        .ok_or_else(|| eyre!("No backup found for {}", path.display()))?;
+
+    let mut temp_file = sibling_temp_file(path)?;
+    copy_stream(
+        &mut BufReader::new(File::open(backup_path)?),
+        temp_file.as_file_mut(),
+    )?;
+
+    replace_file(temp_file, path, &BackupMode::Disabled)
+}
+
+/// Get a path for a book to be moved to that isn't taken by another file, adding a number
+/// to the file name until it is free
+///
+/// The book's own path is never taken, so a book that is already in the right place stays
+/// where it is.
+pub fn unique_path(path: &Path, book_path: &Path) -> PathBuf {
+    let stem = file_stem(path);
+    let extension = path
+        .file_name()
+        .map(|name| name.to_string_lossy()[stem.len()..].to_string())
+        .unwrap_or_default();
+
+    let mut candidate = path.to_path_buf();
+    let mut number = 2;
+    while candidate != book_path && candidate.exists() {
+        candidate = path.with_file_name(format!("{} ({}){}", stem, number, extension));
+        number += 1;
+    }
+
+    candidate
+}
+
+/// Move a file, creating the directories it is moved into
+///
+/// Files moved to another filesystem, which can't be renamed, are copied over atomically
+/// and then removed. Any other failure to rename the file is returned.
+pub fn move_file(from: &Path, to: &Path) -> color_eyre::Result<()> {
+    if let Some(parent) = to.parent() {
+        create_dir_all(parent)?;
+    }
+    match rename(from, to) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => copy_and_remove(from, to)?,
+        Err(err) => {
+            return Err(eyre!(
+                "Unable to move {} to {}: {}",
+                from.display(),
+                to.display(),
+                err
+            ));
+        }
+    }
+    sync_parent_directory(to);
+
+    Ok(())
+}
+
+/// Move a file by copying it over atomically and then removing it, for files that can't
+/// be renamed as they are moved to another filesystem
+fn copy_and_remove(from: &Path, to: &Path) -> color_eyre::Result<()> {
+    let mut temp_file = sibling_temp_file(to)?;
+    copy_stream(
+        &mut BufReader::new(File::open(from)?),
+        temp_file.as_file_mut(),
+    )?;
+    replace_file(temp_file, to, &BackupMode::Disabled)?;
+    remove_file(from)?;
+
+    Ok(())
+}
+
+/// Flush the rename of a file to disk by syncing its parent directory
+///
+/// Directories can't be opened for syncing on every platform so failures are ignored.
+fn sync_parent_directory(path: &Path) {
+    if let Some(parent) = path.parent()
+        && let Ok(directory) = File::open(if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        })
+    {
+        let _ = directory.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{read_to_string, write};
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// Write the contents to a temporary file next to the path
+    fn temp_file(path: &Path, contents: &str) -> NamedTempFile {
+        let temp_file = sibling_temp_file(path).unwrap();
+        write(temp_file.path(), contents).unwrap();
+
+        temp_file
+    }
+
+    #[test]
+    fn backups_are_kept_next_to_the_book_or_in_a_directory() {
+        let book = Path::new("/books/Foundation.epub");
+        assert_eq!(BackupMode::Disabled.backup_path(book), None);
+        assert_eq!(
+            BackupMode::Sibling.backup_path(book),
+            Some(PathBuf::from("/books/Foundation.epub.bak"))
+        );
+        assert_eq!(
+            BackupMode::Directory(PathBuf::from("/backups")).backup_path(book),
+            Some(PathBuf::from("/backups/books/Foundation.epub"))
+        );
+    }
+
+    #[test]
+    fn replaced_files_keep_their_first_backup() {
+        let directory = tempdir().unwrap();
+        let book = directory.path().join("Foundation.epub");
+        write(&book, "original").unwrap();
+        let backup_mode = BackupMode::Sibling;
+
+        replace_file(temp_file(&book, "first edit"), &book, &backup_mode).unwrap();
+        assert_eq!(read_to_string(&book).unwrap(), "first edit");
+        assert!(backup_mode.has_backup(&book));
+        replace_file(temp_file(&book, "second edit"), &book, &backup_mode).unwrap();
+        assert_eq!(read_to_string(&book).unwrap(), "second edit");
+        assert_eq!(
+            read_to_string(directory.path().join("Foundation.epub.bak")).unwrap(),
+            "original"
+        );
+
+        restore_backup(&book, &backup_mode).unwrap();
+        assert_eq!(read_to_string(&book).unwrap(), "original");
+    }
+
+    #[test]
+    fn backup_directories_are_created_as_needed() {
+        let directory = tempdir().unwrap();
+        let book = directory.path().join("Foundation.epub");
+        write(&book, "original").unwrap();
+        let backup_mode = BackupMode::Directory(directory.path().join("backups"));
+
+        replace_file(temp_file(&book, "edited"), &book, &backup_mode).unwrap();
+        let backup_path = backup_mode.backup_path(&book).unwrap();
+        assert_eq!(read_to_string(backup_path).unwrap(), "original");
+    }
+
+    #[test]
+    fn new_files_are_written_without_a_backup() {
+        let directory = tempdir().unwrap();
+        let book = directory.path().join("Foundation.epub");
+
+        replace_file(temp_file(&book, "new"), &book, &BackupMode::Sibling).unwrap();
+        assert_eq!(read_to_string(&book).unwrap(), "new");
+        assert!(!BackupMode::Sibling.has_backup(&book));
+        assert!(restore_backup(&book, &BackupMode::Sibling).is_err());
+    }
+
+    #[test]
+    fn unique_paths_are_numbered_until_free() {
+        let directory = tempdir().unwrap();
+        let taken = directory.path().join("Foundation.epub");
+        write(&taken, "").unwrap();
+        write(directory.path().join("Foundation (2).epub"), "").unwrap();
+        let book = directory.path().join("book.epub");
+
+        assert_eq!(
+            unique_path(&taken, &book),
+            directory.path().join("Foundation (3).epub")
+        );
+        // A book already at the path keeps it
+        assert_eq!(unique_path(&taken, &taken), taken);
+        let free = directory.path().join("Foundation and Empire.epub");
+        assert_eq!(unique_path(&free, &book), free);
+        // Zipped FictionBooks keep their whole extension
+        let zipped = directory.path().join("Foundation.fb2.zip");
+        write(&zipped, "").unwrap();
+        assert_eq!(
+            unique_path(&zipped, &book),
+            directory.path().join("Foundation (2).fb2.zip")
+        );
+    }
+
+    #[test]
+    fn moved_files_are_renamed_into_new_directories() {
+        let directory = tempdir().unwrap();
+        let from = directory.path().join("book.epub");
+        write(&from, "book").unwrap();
+        let to = directory.path().join("Isaac Asimov/Foundation.epub");
+
+        move_file(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(read_to_string(&to).unwrap(), "book");
+    }
+
+    #[test]
+    fn failed_moves_leave_the_files_alone() {
+        let directory = tempdir().unwrap();
+        let from = directory.path().join("missing.epub");
+        let to = directory.path().join("Foundation.epub");
+
+        let err = move_file(&from, &to).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("Unable to move {}", from.display())));
+        assert!(!to.exists());
+    }
+
+    #[test]
+    fn files_are_copied_when_they_cant_be_renamed() {
+        let directory = tempdir().unwrap();
+        let from = directory.path().join("book.epub");
+        write(&from, "book").unwrap();
+        let to = directory.path().join("Foundation.epub");
+        write(&to, "old book").unwrap();
+
+        copy_and_remove(&from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(read_to_string(&to).unwrap(), "book");
+        // Nothing but the moved file is left behind
+        assert_eq!(directory.path().read_dir().unwrap().count(), 1);
+    }
+}
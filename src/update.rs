@@ -1,10 +1,17 @@
-use std::{cmp::Ordering, collections::HashMap, fs::canonicalize, path::PathBuf, time::Duration};
+use std::{cmp::Ordering, fs::canonicalize, path::PathBuf, time::Duration};
 
+use cli_log::*;
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
-use epub::doc::EpubDoc;
 use ratatui::widgets::TableState;
 
-use crate::model::{FileList, InputField, Model, Page};
+use crate::{
+    cover::{CoverImage, CoverSource, PLACEHOLDER_COVER},
+    creator::{Creator, format_creators, parse_creators},
+    format::Template,
+    model::{DEFAULT_FORMAT, DcField, FileList, InputField, Model, Page, parse_position},
+    sidecar::{has_sidecar, sidecar_manifest},
+    storage::restore_backup,
+};
 
 /// Enum for holding direction for page changing event
 pub(crate) enum Direction {
@@ -42,6 +49,8 @@ pub enum EventMessage {
     ChangeField,
     /// Change the input field within the file table
     ChangeTableField(TableDirection),
+    /// Change the cell being edited in the table of Dublin Core fields
+    ChangeMetadataField(TableDirection),
     /// Input text into the input field
     InputText(char),
     /// Remove text from the input field
@@ -52,6 +61,33 @@ pub enum EventMessage {
     ChangeBookPosition(usize),
     /// Show or hide the help page
     ChangeHelpPageStatus(HelpPageState),
+    /// Cycle through the ways books are backed up
+    ChangeBackupMode,
+    /// Scroll the list of changes on the review page
+    ScrollReview(Direction),
+    /// Save the inputs to a series manifest
+    SaveManifest,
+    /// Restore the highlighted book in the selection page from its backup
+    RestoreBackup,
+    /// Resume the session saved by a previous run of the app
+    ResumeSession,
+    /// Propose the series and their books from the books in the current directory
+    DetectSeries,
+    /// Fill in every page from the sidecars of the books in the current directory
+    ImportSidecars,
+    /// Write the sidecars of the selected books in the selection page, or of the
+    /// highlighted book when none are selected
+    ExportSidecars,
+    /// Sort the books of the current series by the positions inferred from them
+    AutoOrder,
+    /// Give every book of the current series the creators of the highlighted book
+    CopyAuthors,
+    /// Normalise the creators of every book of the current series
+    NormalizeAuthors,
+    /// Give every book of the current series without a cover a placeholder cover
+    PlaceholderCovers,
+    /// Show or hide the cover previews
+    ToggleCovers,
 }
 
 /// Function for processing events
@@ -67,16 +103,15 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                         for _ in 0..(model.inputs.series_num - model.inputs.file_lists.len() as i8)
                         {
                             model.inputs.file_lists.push(FileList::from_iter(
-                                model
-                                    .get_current_file_list(PathBuf::from("./"))
-                                    .clone()
-                                    .into_iter(),
+                                model.get_current_file_list(PathBuf::from("./")).clone(),
                             ));
                             if model.inputs.series_num > model.inputs.field_values.len() as i8 {
-                                model
-                                    .inputs
-                                    .field_values
-                                    .push(HashMap::from([(InputField::BookTitle, Vec::new())]));
+                                model.inputs.field_values.push(
+                                    InputField::book_fields()
+                                        .into_iter()
+                                        .map(|field| (field, Vec::new()))
+                                        .collect(),
+                                );
                             }
                         }
                     }
@@ -98,16 +133,21 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                     }
                     Direction::Next => {
                         if !model.inputs.file_lists[current_series].selected.is_empty() {
-                            if model.inputs.field_values[current_series].len() == 1 {
+                            if !model.inputs.field_values[current_series]
+                                .contains_key(&InputField::Series)
+                            {
                                 model.inputs.field_values[current_series].insert(
                                     InputField::Series,
                                     vec![String::from("Placeholder title")],
                                 );
-                                model.inputs.field_values[current_series].insert(
-                                    InputField::Format,
-                                    // vec![String::from("${series_name} (${position}) - ${title}")],
-                                    vec![String::from("${series} (${position}) - ${title}")],
-                                );
+                                model.inputs.field_values[current_series]
+                                    .insert(InputField::Format, vec![String::from(DEFAULT_FORMAT)]);
+                                model.inputs.field_values[current_series]
+                                    .insert(InputField::PathFormat, vec![String::new()]);
+                                for field in DcField::VALUES {
+                                    model.inputs.field_values[current_series]
+                                        .insert(InputField::Metadata(field), vec![String::new()]);
+                                }
                                 model.inputs.file_table_states.push(TableState::new());
                             }
                             model.current_page.saturating_add(1)
@@ -118,39 +158,29 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                 },
                 Page::BookData => match direction {
                     Direction::Previous => model.current_page.saturating_sub(1),
+                    // Don't move on until every input of the series is valid
+                    Direction::Next if !book_data_is_valid(model, current_series) => {
+                        model.current_page
+                    }
                     Direction::Next => {
                         if model.inputs.current_series_num < model.inputs.series_num as usize - 1 {
                             model.inputs.current_series_num += 1;
                             model.current_page.saturating_sub(1)
                         } else {
-                            for i in 0..model.inputs.file_lists.len() {
-                                let file_list = &mut model.inputs.file_lists[i];
-                                for book in &file_list.selected {
-                                    let position =
-                                        file_list.selected.iter().position(|b| b == book).unwrap();
-                                    let title = &model.inputs.field_values[i]
-                                        .get(&InputField::BookTitle)
-                                        .unwrap()[position];
-                                    let series_name = &model.inputs.field_values[i]
-                                        .get(&InputField::Series)
-                                        .unwrap()[0];
-                                    let format_string = &model.inputs.field_values[i]
-                                        .get(&InputField::Format)
-                                        .unwrap()[0];
-                                    model.all_field_values.push(HashMap::from([
-                                        (InputField::Format, format_string.to_string()),
-                                        (InputField::BookOrder, position.to_string()),
-                                        (InputField::BookTitle, title.to_owned()),
-                                        (InputField::Series, series_name.to_owned()),
-                                    ]))
-                                }
-
-                                model.all_selected.append(&mut file_list.selected);
-                            }
+                            model.collect_books();
+                            model.preview_all();
                             model.current_page.saturating_add(1) % Page::VALUES.len()
                         }
                     }
                 },
+                Page::Review => match direction {
+                    Direction::Previous => model.current_page.saturating_sub(1),
+                    // Don't edit any book until every book can be edited
+                    Direction::Next if model.previews.iter().any(Result::is_err) => {
+                        model.current_page
+                    }
+                    Direction::Next => model.current_page.saturating_add(1) % Page::VALUES.len(),
+                },
                 _ => match direction {
                     Direction::Previous => model.current_page.saturating_sub(1),
                     Direction::Next => model.current_page.saturating_add(1) % Page::VALUES.len(),
@@ -169,6 +199,21 @@ pub fn update(model: &mut Model, msg: EventMessage) {
             model.inputs.file_lists[current_series].state.previous();
         }
         EventMessage::SelectFile => {
+            let (title, creators) = model.inputs.file_lists[current_series]
+                .state
+                .selected
+                .map(|selected_idx| &model.inputs.file_lists[current_series].items[selected_idx])
+                .filter(|file_name| file_name.is_file())
+                .map(|file_name| {
+                    (
+                        model.get_book_title(file_name).unwrap_or_default(),
+                        model
+                            .get_book_creators(file_name)
+                            .map(|creators| format_creators(&creators))
+                            .unwrap_or_default(),
+                    )
+                })
+                .unwrap_or_default();
             let file_list = &mut model.inputs.file_lists[current_series];
             let state = &mut file_list.state;
             if let Some(selected_idx) = state.selected {
@@ -178,10 +223,13 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                         for i in 0..file_list.selected.len() {
                             if &file_list.selected[i] == file_name {
                                 file_list.selected.remove(i);
-                                if let Some(book_titles) = model.inputs.field_values[current_series]
-                                    .get_mut(&InputField::BookTitle)
-                                {
-                                    book_titles.remove(i);
+                                for field in InputField::book_fields() {
+                                    if let Some(values) =
+                                        model.inputs.field_values[current_series].get_mut(&field)
+                                        && i < values.len()
+                                    {
+                                        values.remove(i);
+                                    }
                                 }
                                 break;
                             }
@@ -191,14 +239,29 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                         if let Some(book_titles) = model.inputs.field_values[current_series]
                             .get_mut(&InputField::BookTitle)
                         {
-                            book_titles.push(
-                                EpubDoc::new(file_name)
-                                    .unwrap()
-                                    .metadata
-                                    .get("title")
-                                    .unwrap()[0]
-                                    .to_owned(),
-                            )
+                            book_titles.push(title)
+                        }
+                        if let Some(book_positions) = model.inputs.field_values[current_series]
+                            .get_mut(&InputField::BookPosition)
+                        {
+                            book_positions.push(String::new());
+                        }
+                        if let Some(book_authors) = model.inputs.field_values[current_series]
+                            .get_mut(&InputField::BookAuthors)
+                        {
+                            book_authors.push(creators);
+                        }
+                        if let Some(book_covers) = model.inputs.field_values[current_series]
+                            .get_mut(&InputField::BookCover)
+                        {
+                            book_covers.push(String::new());
+                        }
+                        for field in DcField::VALUES {
+                            if let Some(values) = model.inputs.field_values[current_series]
+                                .get_mut(&InputField::BookMetadata(field))
+                            {
+                                values.push(String::new());
+                            }
                         }
                     }
                 }
@@ -206,7 +269,8 @@ pub fn update(model: &mut Model, msg: EventMessage) {
             state.next();
         }
         EventMessage::ChangeDirectory(directory) => {
-            if directory.is_dir() {
+            // A Calibre library's books are listed without their directories
+            if directory.is_dir() && model.library.is_none() {
                 let items = model.get_current_file_list(directory.clone());
                 let file_list = &mut model.inputs.file_lists[current_series];
                 file_list.items = items;
@@ -217,23 +281,43 @@ pub fn update(model: &mut Model, msg: EventMessage) {
         EventMessage::ChangeField => {
             model.inputs.currently_editing = match model.inputs.currently_editing {
                 InputField::Series => InputField::Format,
-                InputField::Format => InputField::BookOrder,
+                InputField::Format => InputField::PathFormat,
+                InputField::PathFormat => InputField::MetadataFields,
+                InputField::MetadataFields => InputField::BookOrder,
                 InputField::BookOrder => InputField::Series,
                 InputField::BookTitle => InputField::BookTitle,
+                InputField::BookPosition => InputField::BookPosition,
+                InputField::BookAuthors => InputField::BookAuthors,
+                InputField::BookCover => InputField::BookCover,
+                InputField::Metadata(field) => InputField::Metadata(field),
+                InputField::BookMetadata(field) => InputField::BookMetadata(field),
+            };
+            if model.inputs.currently_editing == InputField::MetadataFields {
+                let table_state = &mut model.inputs.metadata_table_state;
+                if table_state.selected_cell().is_none() {
+                    table_state.select_cell(Some((0, 1)));
+                }
+                let book_state = &mut model.inputs.file_table_states[current_series];
+                if book_state.selected().is_none()
+                    && !model.inputs.file_lists[current_series].selected.is_empty()
+                {
+                    book_state.select(Some(0));
+                }
             }
         }
         EventMessage::InputText(char) => {
+            if let Some(value) = metadata_value_mut(model, current_series) {
+                value.push(char);
+            }
             if model.inputs.currently_editing == InputField::BookOrder {
                 let (current_row, current_cell) = model.inputs.file_table_states[current_series]
                     .selected_cell()
                     .unwrap_or_default();
-                if current_cell == 1 {
-                    if let Some(book_titles) =
-                        model.inputs.field_values[current_series].get_mut(&InputField::BookTitle)
-                    {
-                        let book_title = &mut book_titles[current_row];
-                        book_title.push(char);
-                    }
+                if let Some(field) = table_field(current_cell)
+                    && let Some(values) = model.inputs.field_values[current_series].get_mut(&field)
+                    && let Some(value) = values.get_mut(current_row)
+                {
+                    value.push(char);
                 }
             }
             if let Some(value) =
@@ -244,17 +328,18 @@ pub fn update(model: &mut Model, msg: EventMessage) {
             }
         }
         EventMessage::RemoveText => {
+            if let Some(value) = metadata_value_mut(model, current_series) {
+                value.pop();
+            }
             if model.inputs.currently_editing == InputField::BookOrder {
                 let (current_row, current_cell) = model.inputs.file_table_states[current_series]
                     .selected_cell()
                     .unwrap_or_default();
-                if current_cell == 1 {
-                    if let Some(book_titles) =
-                        model.inputs.field_values[current_series].get_mut(&InputField::BookTitle)
-                    {
-                        let book_title = &mut book_titles[current_row];
-                        book_title.pop();
-                    }
+                if let Some(field) = table_field(current_cell)
+                    && let Some(values) = model.inputs.field_values[current_series].get_mut(&field)
+                    && let Some(value) = values.get_mut(current_row)
+                {
+                    value.pop();
                 }
             }
             if let Some(value) =
@@ -282,26 +367,36 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                 _ => {}
             }
         }
+        EventMessage::ChangeMetadataField(direction) => {
+            let table_state = &mut model.inputs.metadata_table_state;
+            let row = table_state.selected().unwrap_or_default();
+            match direction {
+                TableDirection::PreviousRow => table_state.select(Some(row.saturating_sub(1))),
+                TableDirection::NextRow => {
+                    table_state.select(Some((row + 1).min(DcField::VALUES.len() - 1)))
+                }
+                TableDirection::PreviousCol => table_state.select_column(Some(1)),
+                TableDirection::NextCol => table_state.select_column(Some(2)),
+            }
+        }
         EventMessage::SwapBook(direction) => {
             let table_state = &mut model.inputs.file_table_states[current_series];
             if let Some(selected_row) = table_state.selected() {
-                let book_list = &mut model.inputs.file_lists[current_series].selected;
-                let book_titles = model.inputs.field_values[current_series]
-                    .get_mut(&InputField::BookTitle)
-                    .unwrap();
+                let num_books = model.inputs.file_lists[current_series].selected.len();
+                model
+                    .ambiguous_books
+                    .remove(&model.inputs.file_lists[current_series].selected[selected_row]);
                 match direction {
                     Direction::Next => {
-                        if selected_row < book_list.len() - 1 {
-                            book_list.swap(selected_row, selected_row + 1);
-                            book_titles.swap(selected_row, selected_row + 1);
+                        if selected_row < num_books - 1 {
                             table_state.select_next();
+                            swap_books(model, current_series, selected_row, selected_row + 1);
                         }
                     }
                     Direction::Previous => {
                         if selected_row > 0 {
-                            book_list.swap(selected_row, selected_row - 1);
-                            book_titles.swap(selected_row, selected_row - 1);
                             table_state.select_previous();
+                            swap_books(model, current_series, selected_row, selected_row - 1);
                         }
                     }
                 }
@@ -309,24 +404,20 @@ pub fn update(model: &mut Model, msg: EventMessage) {
         }
         EventMessage::ChangeBookPosition(new_index) => {
             if let Some(current_index) = model.inputs.file_table_states[current_series].selected() {
-                let book_list = &mut model.inputs.file_lists[current_series].selected;
-                let book_titles = model.inputs.field_values[current_series]
-                    .get_mut(&InputField::BookTitle)
-                    .unwrap();
+                let book_list = &model.inputs.file_lists[current_series].selected;
                 let num_books = book_list.len();
                 if (0..num_books).contains(&new_index) {
+                    model.ambiguous_books.remove(&book_list[current_index]);
                     match new_index.cmp(&current_index) {
                         Ordering::Less => {
                             for i in ((new_index + 1)..=current_index).rev() {
-                                book_list.swap(i, i - 1);
-                                book_titles.swap(i, i - 1);
+                                swap_books(model, current_series, i, i - 1);
                             }
                         }
                         Ordering::Equal => {}
                         Ordering::Greater => {
                             for i in current_index..new_index {
-                                book_list.swap(i, i + 1);
-                                book_titles.swap(i, i + 1);
+                                swap_books(model, current_series, i, i + 1);
                             }
                         }
                     }
@@ -334,6 +425,129 @@ pub fn update(model: &mut Model, msg: EventMessage) {
                 }
             };
         }
+        EventMessage::ScrollReview(direction) => match direction {
+            Direction::Next => model.review_state.next(),
+            Direction::Previous => model.review_state.previous(),
+        },
+        EventMessage::SaveManifest => {
+            if let Err(err) = model.save_manifest() {
+                warn!("Unable to save the series manifest: {}", err);
+            }
+        }
+        EventMessage::ChangeBackupMode => {
+            model.backup_mode = model.backup_mode.next();
+        }
+        EventMessage::AutoOrder => model.auto_order(current_series),
+        EventMessage::CopyAuthors => {
+            if let Some(selected_row) = model.inputs.file_table_states[current_series].selected()
+                && let Some(authors) =
+                    model.inputs.field_values[current_series].get_mut(&InputField::BookAuthors)
+                && let Some(selected_authors) = authors.get(selected_row).cloned()
+            {
+                authors.fill(selected_authors);
+            }
+        }
+        EventMessage::NormalizeAuthors => {
+            if let Some(authors) =
+                model.inputs.field_values[current_series].get_mut(&InputField::BookAuthors)
+            {
+                for book_authors in authors.iter_mut() {
+                    // Creators that can't be read are left for the user to fix
+                    if let Ok(creators) = parse_creators(book_authors) {
+                        let creators: Vec<Creator> =
+                            creators.iter().map(Creator::normalized).collect();
+                        *book_authors = format_creators(&creators);
+                    }
+                }
+            }
+        }
+        EventMessage::PlaceholderCovers => {
+            for row in 0..model.inputs.file_lists[current_series].selected.len() {
+                let epub_path = model.inputs.file_lists[current_series].selected[row].to_owned();
+                // Books that have a cover keep it
+                let has_cover = !matches!(model.get_book_cover(&epub_path), Ok(None));
+                if let Some(cover) = model.inputs.field_values[current_series]
+                    .get_mut(&InputField::BookCover)
+                    .and_then(|covers| covers.get_mut(row))
+                    && cover.is_empty()
+                    && !has_cover
+                {
+                    *cover = PLACEHOLDER_COVER.to_string();
+                }
+            }
+        }
+        EventMessage::ToggleCovers => model.show_covers = !model.show_covers,
+        EventMessage::DetectSeries => {
+            let detection = model.detect_series(PathBuf::from("./"));
+            let mut summary = detection.summary();
+            if !detection.manifest.series.is_empty()
+                && let Err(err) = model.load_manifest(&detection.manifest)
+            {
+                warn!("Unable to load the detected series: {}", err);
+                summary = format!("Unable to load the detected series: {}", err);
+            }
+            model.detection_summary = Some(summary);
+        }
+        EventMessage::ImportSidecars => {
+            let books: Vec<PathBuf> = model
+                .get_current_file_list(PathBuf::from("./"))
+                .into_iter()
+                .filter(|path| path.is_file() && has_sidecar(path))
+                .collect();
+            let summary = if books.is_empty() {
+                String::from("No sidecars found")
+            } else {
+                match sidecar_manifest(&books)
+                    .and_then(|manifest| model.load_manifest(&manifest).map(|()| manifest))
+                {
+                    Ok(manifest) => format!(
+                        "Imported {} sidecars into {} series",
+                        books.len(),
+                        manifest.series.len()
+                    ),
+                    Err(err) => {
+                        warn!("Unable to import the sidecars: {}", err);
+                        format!("Unable to import the sidecars: {}", err)
+                    }
+                }
+            };
+            model.detection_summary = Some(summary);
+        }
+        EventMessage::ExportSidecars => {
+            let file_list = &model.inputs.file_lists[current_series];
+            let books = if file_list.selected.is_empty() {
+                file_list
+                    .state
+                    .selected
+                    .map(|selected_idx| file_list.items[selected_idx].to_owned())
+                    .filter(|path| path.is_file())
+                    .into_iter()
+                    .collect()
+            } else {
+                file_list.selected.clone()
+            };
+            for book in books {
+                if let Err(err) = model.export_sidecar(&book) {
+                    warn!("Unable to export {}: {}", book.display(), err);
+                }
+            }
+        }
+        EventMessage::RestoreBackup => {
+            let file_list = &model.inputs.file_lists[current_series];
+            if let Some(selected_idx) = file_list.state.selected {
+                let file_name = &file_list.items[selected_idx];
+                if let Err(err) = restore_backup(file_name, &model.backup_mode) {
+                    warn!("Unable to restore {}: {}", file_name.display(), err);
+                }
+            }
+        }
+        EventMessage::ResumeSession => {
+            if let Some(session) = model.saved_session.take()
+                && let Err(err) = model.resume_session(session)
+            {
+                warn!("Unable to resume the previous session: {}", err);
+            }
+        }
         EventMessage::ChangeHelpPageStatus(status) => match status {
             HelpPageState::Show => {
                 model.help = true;
@@ -345,15 +559,99 @@ pub fn update(model: &mut Model, msg: EventMessage) {
     }
 }
 
+/// Whether the format strings, creators, fields, positions and covers of every book of a
+/// series can be used
+fn book_data_is_valid(model: &Model, series_index: usize) -> bool {
+    let field_values = &model.inputs.field_values[series_index];
+    let formats_valid = [InputField::Format, InputField::PathFormat]
+        .iter()
+        .filter_map(|field| field_values.get(field).and_then(|values| values.first()))
+        .all(|value| Template::parse(value).is_ok());
+    let authors_valid = field_values
+        .get(&InputField::BookAuthors)
+        .is_none_or(|authors| {
+            authors
+                .iter()
+                .all(|authors| parse_creators(authors).is_ok())
+        });
+
+    let fields_valid = DcField::VALUES.iter().all(|&field| {
+        [InputField::Metadata(field), InputField::BookMetadata(field)]
+            .iter()
+            .filter_map(|input_field| field_values.get(input_field))
+            .flatten()
+            .all(|value| field.validate(value).is_ok())
+    });
+
+    let positions_valid = (0..model.inputs.file_lists[series_index].selected.len())
+        .all(|row| parse_position(&model.book_position(series_index, row)).is_ok());
+
+    let covers_valid = field_values
+        .get(&InputField::BookCover)
+        .is_none_or(|covers| {
+            covers.iter().all(|cover| match CoverSource::parse(cover) {
+                Some(CoverSource::Image(path)) => CoverImage::read(&path).is_ok(),
+                _ => true,
+            })
+        });
+
+    formats_valid && authors_valid && fields_valid && positions_valid && covers_valid
+}
+
+/// Get the value in the highlighted cell of the table of Dublin Core fields when it is being
+/// edited, which is either the series' value or the value for the book highlighted in the
+/// book order table
+fn metadata_value_mut(model: &mut Model, series_index: usize) -> Option<&mut String> {
+    if model.inputs.currently_editing != InputField::MetadataFields {
+        return None;
+    }
+    let table_state = &model.inputs.metadata_table_state;
+    let field = DcField::VALUES[table_state.selected().unwrap_or_default()];
+    let input_field = match table_state.selected_column() {
+        Some(2) => InputField::BookMetadata(field),
+        _ => InputField::Metadata(field),
+    };
+    let index = match input_field {
+        InputField::BookMetadata(_) => model.inputs.file_table_states[series_index].selected()?,
+        _ => 0,
+    };
+
+    model.inputs.field_values[series_index]
+        .get_mut(&input_field)?
+        .get_mut(index)
+}
+
+/// Get the input field edited in the given column of the book order table
+fn table_field(column: usize) -> Option<InputField> {
+    match column {
+        1 => Some(InputField::BookTitle),
+        2 => Some(InputField::BookPosition),
+        4 => Some(InputField::BookAuthors),
+        6 => Some(InputField::BookCover),
+        _ => None,
+    }
+}
+
+/// Swap two books of a series along with their titles, positions, creators and fields
+fn swap_books(model: &mut Model, series_index: usize, a: usize, b: usize) {
+    model.inputs.file_lists[series_index].selected.swap(a, b);
+    for field in InputField::book_fields() {
+        if let Some(values) = model.inputs.field_values[series_index].get_mut(&field)
+            && a.max(b) < values.len()
+        {
+            values.swap(a, b);
+        }
+    }
+}
+
 /// Function for polling events and keybinds and returning related event
 pub fn handle_event(model: &Model) -> color_eyre::Result<Option<EventMessage>> {
     // Wait up to 250ms for an event
-    if event::poll(Duration::from_millis(250))? {
-        if let Event::Key(key) = event::read()? {
-            if key.kind == event::KeyEventKind::Press {
-                return Ok(handle_key(model, key));
-            }
-        }
+    if event::poll(Duration::from_millis(250))?
+        && let Event::Key(key) = event::read()?
+        && key.kind == event::KeyEventKind::Press
+    {
+        return Ok(handle_key(model, key));
     }
 
     Ok(None)
@@ -371,6 +669,15 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
             Some(EventMessage::Quit)
         }
         _ if model.help => None,
+        KeyCode::Char('c')
+            if key.modifiers.contains(KeyModifiers::ALT)
+                && matches!(
+                    Page::VALUES[model.current_page],
+                    Page::FileSelection | Page::BookData
+                ) =>
+        {
+            Some(EventMessage::ToggleCovers)
+        }
         KeyCode::Right if key.modifiers.contains(KeyModifiers::CONTROL) => {
             Some(EventMessage::ChangePage(Direction::Next))
         }
@@ -379,15 +686,30 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
         }
         // Set page specific keybinds
         _ => match Page::VALUES[model.current_page] {
+            Page::Home => match key.code {
+                KeyCode::Char('r') if model.saved_session.is_some() => {
+                    Some(EventMessage::ResumeSession)
+                }
+                _ => None,
+            },
             Page::SeriesData => match key.code {
                 KeyCode::Left => Some(EventMessage::SetSeriesCounter(-1)),
                 KeyCode::Right => Some(EventMessage::SetSeriesCounter(1)),
+                KeyCode::Char('b') => Some(EventMessage::ChangeBackupMode),
+                KeyCode::Char('d') => Some(EventMessage::DetectSeries),
+                KeyCode::Char('i') => Some(EventMessage::ImportSidecars),
                 _ => None,
             },
             Page::FileSelection => match key.code {
                 KeyCode::Down => Some(EventMessage::NextFile),
                 KeyCode::Up => Some(EventMessage::PreviousFile),
                 KeyCode::Tab => Some(EventMessage::SelectFile),
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Some(EventMessage::RestoreBackup)
+                }
+                KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Some(EventMessage::ExportSidecars)
+                }
                 KeyCode::Right | KeyCode::Left => match key.code {
                     KeyCode::Right => {
                         if let Some(new_directory_index) = model.inputs.file_lists
@@ -418,8 +740,29 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
             },
             Page::BookData => match key.code {
                 KeyCode::Tab => Some(EventMessage::ChangeField),
+                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Some(EventMessage::SaveManifest)
+                }
                 _ => {
-                    if model.inputs.currently_editing == InputField::BookOrder {
+                    if model.inputs.currently_editing == InputField::MetadataFields {
+                        match key.code {
+                            KeyCode::Up => Some(EventMessage::ChangeMetadataField(
+                                TableDirection::PreviousRow,
+                            )),
+                            KeyCode::Down => {
+                                Some(EventMessage::ChangeMetadataField(TableDirection::NextRow))
+                            }
+                            KeyCode::Left => Some(EventMessage::ChangeMetadataField(
+                                TableDirection::PreviousCol,
+                            )),
+                            KeyCode::Right => {
+                                Some(EventMessage::ChangeMetadataField(TableDirection::NextCol))
+                            }
+                            KeyCode::Backspace => Some(EventMessage::RemoveText),
+                            KeyCode::Char(value) => Some(EventMessage::InputText(value)),
+                            _ => None,
+                        }
+                    } else if model.inputs.currently_editing == InputField::BookOrder {
                         let current_col = model.inputs.file_table_states
                             [model.inputs.current_series_num]
                             .selected_column()
@@ -432,6 +775,18 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
                             KeyCode::Down if key.modifiers.contains(KeyModifiers::CONTROL) => {
                                 Some(EventMessage::SwapBook(Direction::Next))
                             }
+                            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                Some(EventMessage::AutoOrder)
+                            }
+                            KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                Some(EventMessage::CopyAuthors)
+                            }
+                            KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                Some(EventMessage::NormalizeAuthors)
+                            }
+                            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                Some(EventMessage::PlaceholderCovers)
+                            }
                             KeyCode::Right => {
                                 Some(EventMessage::ChangeTableField(TableDirection::NextCol))
                             }
@@ -444,18 +799,23 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
                             KeyCode::Down => {
                                 Some(EventMessage::ChangeTableField(TableDirection::NextRow))
                             }
-                            KeyCode::Char(value) => {
-                                if value.is_ascii_digit() && current_col != 1 {
+                            KeyCode::Char(value) => match table_field(current_col) {
+                                Some(
+                                    InputField::BookTitle
+                                    | InputField::BookAuthors
+                                    | InputField::BookCover,
+                                ) => Some(EventMessage::InputText(value)),
+                                Some(_) if value.is_ascii_digit() || value == '.' => {
+                                    Some(EventMessage::InputText(value))
+                                }
+                                None if value.is_ascii_digit() => {
                                     Some(EventMessage::ChangeBookPosition(
                                         (value.to_digit(10).unwrap() as usize).saturating_sub(1),
                                     ))
-                                } else if current_col == 1 {
-                                    Some(EventMessage::InputText(value))
-                                } else {
-                                    None
                                 }
-                            }
-                            KeyCode::Backspace if current_col == 1 => {
+                                _ => None,
+                            },
+                            KeyCode::Backspace if table_field(current_col).is_some() => {
                                 Some(EventMessage::RemoveText)
                             }
                             _ => None,
@@ -469,6 +829,11 @@ fn handle_key(model: &Model, key: event::KeyEvent) -> Option<EventMessage> {
                     }
                 }
             },
+            Page::Review => match key.code {
+                KeyCode::Down => Some(EventMessage::ScrollReview(Direction::Next)),
+                KeyCode::Up => Some(EventMessage::ScrollReview(Direction::Previous)),
+                _ => None,
+            },
             _ => None,
         },
     }
@@ -1,14 +1,22 @@
 use std::collections::BTreeMap;
 
-use crate::model::{InputField, Model, Page};
+use cli_log::*;
+use image::{DynamicImage, imageops::FilterType};
+
+use crate::{
+    creator::parse_creators,
+    format::Template,
+    model::{DEFAULT_MANIFEST, DcField, InputField, Model, Page, display_path, is_book},
+    sidecar::has_sidecar,
+    storage::BackupMode,
+};
 
-use epub::doc::EpubDoc;
 use ratatui::{
     Frame,
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Text},
-    widgets::{Block, BorderType, Borders, Paragraph, Row, Table, TableState},
+    text::{Line, Span, Text},
+    widgets::{Block, BorderType, Borders, Cell, Paragraph, Row, Table, TableState, Wrap},
 };
 use tui_widget_list::{ListBuilder, ListState, ListView};
 
@@ -22,7 +30,7 @@ impl View {
             Block::new().style(Style::default().bg(Color::Rgb(20, 20, 20))),
             frame.area(),
         );
-        
+
         let bar_length = 2;
         // Split the TUI into three rows
         // (title bar, main content, and status bar)
@@ -40,12 +48,12 @@ impl View {
             View::draw_help(model, frame, chunks[1]);
         } else {
             match Page::VALUES[model.current_page] {
-                Page::Home => View::draw_home(frame, chunks[1]),
+                Page::Home => View::draw_home(model, frame, chunks[1]),
                 Page::SeriesData => View::draw_series_page(model, frame, chunks[1]),
                 Page::FileSelection => View::draw_file_selection(model, frame, chunks[1])?,
                 Page::BookData => View::draw_book_data_input(model, frame, chunks[1]),
-                Page::Loading => View::draw_loading(model, frame, chunks[1])?,
-                _ => {}
+                Page::Review => View::draw_review(model, frame, chunks[1]),
+                Page::Loading => View::draw_loading(model, frame, chunks[1]),
             };
         }
 
@@ -78,8 +86,8 @@ impl View {
             Page::SeriesData => "Num Series Selection",
             Page::FileSelection => "File Selection",
             Page::BookData => "Book Data Input",
+            Page::Review => "Review Changes",
             Page::Loading => "Metadata Edit Loading",
-            _ => "",
         };
         let current_page_text = Paragraph::new(Text::styled(
             current_page_string,
@@ -115,7 +123,7 @@ impl View {
     }
 
     /// Draw the app's home page.
-    fn draw_home(frame: &mut Frame, area: Rect) {
+    fn draw_home(model: &Model, frame: &mut Frame, area: Rect) {
         let center_chunk = View::centered_rect(60, 30, area);
 
         let title_block = Block::default().borders(Borders::ALL);
@@ -123,20 +131,22 @@ impl View {
         let nav_line = Line::from("Press <Ctrl + Left | Right> to navigate pages.");
         let help_line = Line::from("Press <Alt + H> from any page to show the Help screen.");
         let quit_line = Line::from("Press <Q | Esc> to quit the app.");
-        let title = Paragraph::new(
-            Text::from(vec![
-                Line::default(),
-                intro_line,
-                Line::default(),
-                Line::default(),
-                nav_line,
-                help_line,
-                quit_line,
-            ])
-            .style(Style::default().fg(Color::Green)),
-        )
-        .block(title_block)
-        .centered();
+        let resume_line = match model.saved_session {
+            Some(_) => Line::from("Press <R> to resume the previous session."),
+            None => Line::default(),
+        };
+        let lines = vec![
+            Line::default(),
+            intro_line,
+            Line::default(),
+            resume_line,
+            nav_line,
+            help_line,
+            quit_line,
+        ];
+        let title = Paragraph::new(Text::from(lines).style(Style::default().fg(Color::Green)))
+            .block(title_block)
+            .centered();
 
         frame.render_widget(title, center_chunk);
     }
@@ -145,11 +155,39 @@ impl View {
     fn draw_series_page(model: &Model, frame: &mut Frame, area: Rect) {
         let question = Paragraph::new(Text::raw("How many series are you editing?")).centered();
         let num_input = Paragraph::new(Text::raw(model.inputs.series_num.to_string())).centered();
+        let backup_text = match &model.backup_mode {
+            BackupMode::Disabled => String::from("Backups: disabled"),
+            BackupMode::Sibling => String::from("Backups: .bak file next to each book"),
+            BackupMode::Directory(directory) => {
+                format!("Backups: kept in {}", directory.display())
+            }
+        };
+        let mut status_lines = vec![Line::from(backup_text)];
+        if let Some(library) = &model.library {
+            status_lines.push(Line::from(format!(
+                "Calibre library: {} ({} books)",
+                library.directory.display(),
+                library.books.len()
+            )));
+        }
+        if let Some(summary) = &model.detection_summary {
+            status_lines.push(Line::default());
+            status_lines.push(Line::from(summary.as_str()));
+        }
+        let backup_line =
+            Paragraph::new(Text::from(status_lines).style(Style::default().fg(Color::DarkGray)))
+                .wrap(Wrap { trim: true })
+                .centered();
 
-        let chunks = Layout::vertical([Constraint::Length(2), Constraint::Min(3)])
-            .split(View::centered_rect(40, 50, area));
+        let chunks = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length(3),
+            Constraint::Min(1),
+        ])
+        .split(View::centered_rect(40, 50, area));
         frame.render_widget(question, chunks[0]);
         frame.render_widget(num_input, chunks[1]);
+        frame.render_widget(backup_line, chunks[2]);
     }
 
     /// Draw the app's file selection page
@@ -159,6 +197,8 @@ impl View {
         area: Rect,
     ) -> color_eyre::Result<()> {
         let current_idx = model.inputs.current_series_num;
+        let backup_mode = &model.backup_mode;
+        let library = &model.library;
         let file_list = &mut model.inputs.file_lists[current_idx];
         let file_builder = ListBuilder::new(|context| {
             let file_name = &file_list.items[context.index];
@@ -175,11 +215,27 @@ impl View {
             }
 
             let text: String;
-            if let Some(filename) = file_name.file_name() {
+            // Books of a Calibre library are listed by their series, title and authors
+            if let Some(book) = library.as_ref().and_then(|library| library.book(file_name)) {
+                text = book.label();
+            } else if let Some(filename) = file_name.file_name() {
                 text = filename.to_string_lossy().to_string();
             } else {
                 text = "Unable to read file".to_string();
             }
+            let mut line = Line::from(Span::styled(text, style));
+            if file_name.is_file() && backup_mode.has_backup(file_name) {
+                line.push_span(Span::styled(
+                    " [backup]",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if file_name.is_file() && has_sidecar(file_name) {
+                line.push_span(Span::styled(
+                    " [sidecar]",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
 
             let mut block = Block::new();
             if file_list.selected.contains(file_name) {
@@ -188,7 +244,7 @@ impl View {
                     .border_type(BorderType::Thick)
                     .style(Style::default().fg(Color::Red));
             }
-            let item = Paragraph::new(Text::styled(text, style)).block(block);
+            let item = Paragraph::new(line).block(block);
 
             (item, 1)
         });
@@ -202,7 +258,21 @@ impl View {
                 .split(area);
 
         frame.render_stateful_widget(file_list_widget, file_chunks[0], state);
-        View::draw_selected_files(model, frame, file_chunks[1])?;
+        let highlighted = state
+            .selected
+            .map(|i| file_list.items[i].to_owned())
+            .filter(|path| path.is_file() && is_book(path));
+        match highlighted {
+            Some(epub_path) if model.show_covers => {
+                let side_chunks =
+                    Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)])
+                        .split(file_chunks[1]);
+                View::draw_selected_files(model, frame, side_chunks[0])?;
+                let cover = model.preview_cover(&epub_path);
+                View::draw_cover(cover, frame, side_chunks[1]);
+            }
+            _ => View::draw_selected_files(model, frame, file_chunks[1])?,
+        }
 
         Ok(())
     }
@@ -249,17 +319,21 @@ impl View {
     fn draw_book_data_input(model: &mut Model, frame: &mut Frame, area: Rect) {
         let chunks = Layout::vertical([
             Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 12),
-            Constraint::Ratio(9, 12),
+            Constraint::Length(DcField::VALUES.len() as u16 + 4),
+            Constraint::Min(0),
         ])
         .split(area);
-        let top_chunks =
-            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .split(chunks[0]);
+        let top_chunks = Layout::horizontal([
+            Constraint::Percentage(30),
+            Constraint::Percentage(35),
+            Constraint::Percentage(35),
+        ])
+        .split(chunks[0]);
 
-        let input_chunks: [Rect; 2] = [
-            View::centered_rect(70, 80, top_chunks[0]),
-            View::centered_rect(70, 80, top_chunks[1]),
+        let input_chunks: [Rect; 3] = [
+            View::centered_rect(80, 80, top_chunks[0]),
+            View::centered_rect(90, 80, top_chunks[1]),
+            View::centered_rect(90, 80, top_chunks[2]),
         ];
         let border_color = |field: InputField| {
             if model.inputs.currently_editing == field {
@@ -274,60 +348,228 @@ impl View {
                 .title("Series Name"),
             input_chunks[0],
         );
-        frame.render_widget(
-            Block::bordered()
-                .border_style(border_color(InputField::Format))
-                .title("Format String"),
-            input_chunks[1],
-        );
+        let field_value = |field: &InputField| {
+            model.inputs.field_values[model.inputs.current_series_num]
+                .get(field)
+                .and_then(|values| values.first())
+                .map(String::as_str)
+                .unwrap_or_default()
+        };
+        for (i, (field, title)) in [
+            (InputField::Format, "Format String"),
+            (InputField::PathFormat, "File Path Format (optional)"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let parsed = Template::parse(field_value(&field));
+            let mut format_block = Block::bordered()
+                .border_style(border_color(field))
+                .title(title);
+            if let Err(err) = parsed {
+                format_block = format_block
+                    .border_style(Style::default().fg(Color::Red))
+                    .title_bottom(
+                        Line::from(err.to_string()).style(Style::default().fg(Color::Red)),
+                    );
+            }
+            frame.render_widget(format_block, input_chunks[i + 1]);
+        }
 
         let inputs: Vec<Paragraph> = Vec::from([
-            Paragraph::new(Line::from(
-                model.inputs.field_values[model.inputs.current_series_num][&InputField::Series][0]
-                    .as_str(),
-            )),
-            Paragraph::new(Line::from(
-                model.inputs.field_values[model.inputs.current_series_num][&InputField::Format][0]
-                    .as_str(),
-            )),
+            Paragraph::new(Line::from(field_value(&InputField::Series))),
+            Paragraph::new(Line::from(field_value(&InputField::Format))),
+            Paragraph::new(Line::from(field_value(&InputField::PathFormat))),
         ]);
 
         for i in 0..inputs.len() {
             frame.render_widget(&inputs[i], View::centered_rect(85, 50, input_chunks[i]));
         }
+        View::draw_metadata_fields(model, frame, chunks[1]);
         View::draw_book_order(model, frame, chunks[2]);
     }
 
+    /// Draw the table of Dublin Core fields, holding the values for the series and the
+    /// overrides for the book highlighted in the book order table
+    fn draw_metadata_fields(model: &mut Model, frame: &mut Frame, area: Rect) {
+        let chunk = Layout::default()
+            .constraints([Constraint::Min(0)])
+            .horizontal_margin(5)
+            .split(area)[0];
+
+        let current_series = model.inputs.current_series_num;
+        let field_values = &model.inputs.field_values[current_series];
+        let book_index = model.inputs.file_table_states[current_series].selected();
+        let book_title = book_index
+            .and_then(|i| {
+                field_values
+                    .get(&InputField::BookTitle)
+                    .and_then(|titles| titles.get(i))
+            })
+            .map(|title| format!("Value for \"{}\"", title))
+            .unwrap_or_else(|| "Value for the book".to_string());
+        let value_cell = |field: DcField, input_field: InputField, index: Option<usize>| {
+            let value = index
+                .and_then(|i| {
+                    field_values
+                        .get(&input_field)
+                        .and_then(|values| values.get(i))
+                })
+                .map(String::as_str)
+                .unwrap_or_default();
+            match field.validate(value) {
+                Ok(()) => Cell::from(value.to_string()),
+                Err(_) => Cell::from(value.to_string()).style(Style::default().fg(Color::Red)),
+            }
+        };
+        let rows: Vec<Row> = DcField::VALUES
+            .into_iter()
+            .map(|field| {
+                Row::new(vec![
+                    Cell::from(field.to_string()),
+                    value_cell(field, InputField::Metadata(field), Some(0)),
+                    value_cell(field, InputField::BookMetadata(field), book_index),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Percentage(16),
+            Constraint::Percentage(42),
+            Constraint::Percentage(42),
+        ];
+
+        let editing = model.inputs.currently_editing == InputField::MetadataFields;
+        let mut fields_table = Table::new(rows, widths)
+            .header(Row::new(vec![
+                Cell::from("Field"),
+                Cell::from("Series Value"),
+                Cell::from(book_title),
+            ]))
+            .column_spacing(2);
+        let mut block = Block::bordered().title("Metadata Fields");
+        if editing {
+            fields_table = fields_table.cell_highlight_style(Style::default().fg(Color::Green));
+            block = block.border_style(Style::default().fg(Color::Green));
+        }
+        frame.render_widget(block, chunk);
+        frame.render_stateful_widget(
+            fields_table,
+            View::centered_rect(95, 80, chunk),
+            &mut model.inputs.metadata_table_state,
+        );
+    }
+
     /// Draw the box for showing and giving the order of the books in the series
     fn draw_book_order(model: &mut Model, frame: &mut Frame, area: Rect) {
-        let chunk = Layout::default()
+        let mut chunk = Layout::default()
             .constraints([Constraint::Min(0)])
             .horizontal_margin(5)
             .split(area)[0];
 
         let current_series = model.inputs.current_series_num;
+        if model.show_covers && !model.inputs.file_lists[current_series].selected.is_empty() {
+            let chunks =
+                Layout::horizontal([Constraint::Min(0), Constraint::Length(28)]).split(chunk);
+            chunk = chunks[0];
+            let row = model.inputs.file_table_states[current_series]
+                .selected()
+                .unwrap_or_default()
+                .min(model.inputs.file_lists[current_series].selected.len() - 1);
+            let cover = model.preview_book_cover(current_series, row);
+            View::draw_cover(cover, frame, chunks[1]);
+        }
+        // The format string's own mistake is shown under its input, so each row just flags it
+        let valid_format =
+            Template::parse(&model.inputs.field_values[current_series][&InputField::Format][0])
+                .is_ok();
+        let new_titles: Vec<Cell> = (0..model.inputs.file_lists[current_series].selected.len())
+            .map(|i| match model.preview_title(current_series, i) {
+                Ok(title) => Cell::from(title).style(Style::default().fg(Color::Cyan)),
+                Err(_) if !valid_format => {
+                    Cell::from("Invalid format string").style(Style::default().fg(Color::Red))
+                }
+                Err(err) => Cell::from(err.to_string()).style(Style::default().fg(Color::Red)),
+            })
+            .collect();
+        let new_paths: Vec<Cell> = (0..model.inputs.file_lists[current_series].selected.len())
+            .map(|i| {
+                let file_name = model.inputs.file_lists[current_series].selected[i]
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                match model.preview_path(current_series, i) {
+                    Ok(Some(path)) => {
+                        Cell::from(display_path(&path)).style(Style::default().fg(Color::Cyan))
+                    }
+                    Ok(None) => Cell::from(file_name),
+                    Err(err) => Cell::from(err.to_string()).style(Style::default().fg(Color::Red)),
+                }
+            })
+            .collect();
+        let covers: Vec<Cell> = (0..model.inputs.file_lists[current_series].selected.len())
+            .map(|i| {
+                let cover = model.inputs.field_values[current_series]
+                    .get(&InputField::BookCover)
+                    .and_then(|covers| covers.get(i))
+                    .cloned()
+                    .unwrap_or_default();
+                if cover.trim().is_empty() {
+                    return Cell::from("");
+                }
+                match model.preview_book_cover(current_series, i) {
+                    Ok(_) => Cell::from(cover).style(Style::default().fg(Color::Cyan)),
+                    Err(_) => Cell::from(cover).style(Style::default().fg(Color::Red)),
+                }
+            })
+            .collect();
         let files = &model.inputs.file_lists[current_series].selected;
 
         let file_rows: Vec<Row> = (0..files.len())
             .map(|i| {
-                let epub = EpubDoc::new(&files[i]).unwrap();
+                let ambiguous = model.ambiguous_books.contains(&files[i]);
+                let position = if ambiguous {
+                    format!("{} ?", i + 1)
+                } else {
+                    (i + 1).to_string()
+                };
+                let style = if ambiguous {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default()
+                };
                 Row::new(vec![
-                    (i + 1).to_string(),
-                    model.inputs.field_values[current_series]
-                        .get(&InputField::BookTitle)
-                        .unwrap()[i]
-                        .to_owned(),
-                    String::from(&epub.metadata.get("creator").unwrap()[0]),
-                    files[i].file_name().unwrap().to_string_lossy().to_string(),
+                    Cell::from(position),
+                    Cell::from(
+                        model.inputs.field_values[current_series]
+                            .get(&InputField::BookTitle)
+                            .unwrap()[i]
+                            .to_owned(),
+                    ),
+                    Cell::from(model.book_position(current_series, i)),
+                    new_titles[i].clone(),
+                    View::authors_cell(
+                        model.inputs.field_values[current_series]
+                            .get(&InputField::BookAuthors)
+                            .and_then(|authors| authors.get(i))
+                            .map(String::as_str)
+                            .unwrap_or_default(),
+                    ),
+                    new_paths[i].clone(),
+                    covers[i].clone(),
                 ])
+                .style(style)
             })
             .collect();
         // Columns widths are constrained in the same way as Layout...
         let widths = [
             Constraint::Percentage(5),
-            Constraint::Percentage(35),
-            Constraint::Percentage(10),
-            Constraint::Percentage(50),
+            Constraint::Percentage(19),
+            Constraint::Percentage(7),
+            Constraint::Percentage(22),
+            Constraint::Percentage(17),
+            Constraint::Percentage(17),
+            Constraint::Percentage(13),
         ];
 
         let border_color = |field: InputField| {
@@ -349,7 +591,15 @@ impl View {
         );
         frame.render_stateful_widget(
             Table::new(
-                vec![Row::new(vec!["Position", "Title", "Author", "File Path"])],
+                vec![Row::new(vec![
+                    "Order",
+                    "Title",
+                    "Position",
+                    "New Title",
+                    "Authors",
+                    "File Path",
+                    "Cover",
+                ])],
                 widths,
             ),
             View::centered_rect(90, 90, chunk),
@@ -359,16 +609,144 @@ impl View {
         frame.render_stateful_widget(files_table, View::centered_rect(90, 80, chunk), table_state);
     }
 
-    fn draw_loading(model: &mut Model, frame: &mut Frame, area: Rect) -> color_eyre::Result<()> {
+    /// Draw a cover preview, drawing the image with half blocks so every cell shows two
+    /// pixels
+    fn draw_cover(cover: &Result<DynamicImage, String>, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Cover");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let image = match cover {
+            Ok(image) => image,
+            Err(err) => {
+                frame.render_widget(
+                    Paragraph::new(err.as_str())
+                        .style(Style::default().fg(Color::DarkGray))
+                        .wrap(Wrap { trim: true })
+                        .centered(),
+                    inner,
+                );
+                return;
+            }
+        };
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+
+        let image = image
+            .resize(
+                inner.width as u32,
+                inner.height as u32 * 2,
+                FilterType::Triangle,
+            )
+            .to_rgb8();
+        let padding = " ".repeat((inner.width as usize).saturating_sub(image.width() as usize) / 2);
+        let color = |x: u32, y: u32| {
+            let [red, green, blue] = image.get_pixel(x, y).0;
+            Color::Rgb(red, green, blue)
+        };
+        let lines: Vec<Line> = (0..image.height().div_ceil(2))
+            .map(|row| {
+                let mut spans = vec![Span::raw(padding.clone())];
+                spans.extend((0..image.width()).map(|x| {
+                    let mut style = Style::default().fg(color(x, row * 2));
+                    if row * 2 + 1 < image.height() {
+                        style = style.bg(color(x, row * 2 + 1));
+                    }
+                    Span::styled("▀", style)
+                }));
+                Line::from(spans)
+            })
+            .collect();
+
+        frame.render_widget(Paragraph::new(Text::from(lines)), inner);
+    }
+
+    /// Draw the page showing the metadata changes for every book before they are made
+    fn draw_review(model: &mut Model, frame: &mut Frame, area: Rect) {
+        let chunk = Layout::default()
+            .constraints([Constraint::Min(0)])
+            .horizontal_margin(5)
+            .split(area)[0];
+
+        let selected = &model.all_selected;
+        let previews = &model.previews;
+        let review_builder = ListBuilder::new(|context| {
+            let file_name = selected[context.index]
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "Unable to read file".to_string());
+            let mut style = Style::default().fg(Color::Green);
+            if context.is_selected {
+                style = style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+            }
+
+            let mut lines = vec![Line::from(Span::styled(file_name, style))];
+            match &previews[context.index] {
+                Ok(changes) if changes.is_empty() => {
+                    lines.push(
+                        Line::from("  No changes").style(Style::default().fg(Color::DarkGray)),
+                    );
+                }
+                Ok(changes) => {
+                    for change in changes {
+                        lines.push(Line::from(vec![
+                            Span::raw(format!("  {}: ", change.field)),
+                            Span::styled(
+                                change
+                                    .before
+                                    .clone()
+                                    .unwrap_or_else(|| "(none)".to_string()),
+                                Style::default().fg(Color::Red),
+                            ),
+                            Span::raw(" -> "),
+                            Span::styled(
+                                change.after.clone().unwrap_or_else(|| "(none)".to_string()),
+                                Style::default().fg(Color::Green),
+                            ),
+                        ]));
+                    }
+                }
+                Err(err) => {
+                    lines.push(
+                        Line::from(format!("  Error: {}", err))
+                            .style(Style::default().fg(Color::Red)),
+                    );
+                }
+            }
+            lines.push(Line::default());
+            let height = lines.len() as u16;
+
+            (Paragraph::new(Text::from(lines)), height)
+        });
+
+        let review_widget = ListView::new(review_builder, selected.len());
+        let title = if previews.iter().any(Result::is_err) {
+            "Changes (fix the errors before continuing)"
+        } else {
+            "Changes (nothing is written until you continue)"
+        };
+        frame.render_widget(Block::bordered().title(title), chunk);
+        frame.render_stateful_widget(
+            review_widget,
+            View::centered_rect(95, 90, chunk),
+            &mut model.review_state,
+        );
+    }
+
+    fn draw_loading(model: &mut Model, frame: &mut Frame, area: Rect) {
         let selected = &model.all_selected;
         let file_builder = ListBuilder::new(|context| {
             let file_name = &selected[context.index];
+            let error = model.failed_books.get(file_name);
 
             let mut style = Style::default().fg(Color::default());
             style = if model.finished_books.contains(file_name) {
                 style.fg(Color::Green)
-            } else if context.index == model.current_book {
+            } else if error.is_some() {
                 style.fg(Color::Red)
+            } else if context.index == model.current_book {
+                style.fg(Color::Yellow)
             } else {
                 style
             };
@@ -379,21 +757,53 @@ impl View {
             } else {
                 text = "Unable to read file".to_string();
             }
+            let mut lines = vec![Line::styled(text, style)];
+            // Books that couldn't be edited show why under their name
+            if let Some(error) = error {
+                lines.push(Line::styled(
+                    format!("  {}", error),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            let height = lines.len() as u16;
 
-            let item = Paragraph::new(Text::styled(text, style));
-
-            (item, 1)
+            (Paragraph::new(Text::from(lines)), height)
         });
 
+        let [list_area, status_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
         let file_list_widget = ListView::new(file_builder, selected.len()).infinite_scrolling(true);
-        frame.render_stateful_widget(file_list_widget, area, &mut ListState::default());
-
-        if model.current_book < model.all_selected.len() {
-            let book = model.all_selected[model.current_book].to_owned();
-            model.edit_epub(&book)?;
+        frame.render_stateful_widget(file_list_widget, list_area, &mut ListState::default());
+        let mut status = format!(
+            "Edited {} of {} books",
+            model.finished_books.len(),
+            model.all_selected.len()
+        );
+        if !model.failed_books.is_empty() {
+            status.push_str(&format!(", {} failed", model.failed_books.len()));
         }
+        frame.render_widget(
+            Paragraph::new(status)
+                .style(Style::default().fg(Color::DarkGray))
+                .centered(),
+            status_area,
+        );
 
-        Ok(())
+        // Edit the first book not edited yet, skipping the ones finished before a resume and
+        // the ones that failed
+        if let Some(next_book) = model.all_selected.iter().position(|book| {
+            !model.finished_books.contains(book) && !model.failed_books.contains_key(book)
+        }) {
+            model.current_book = next_book;
+            let book = model.all_selected[next_book].to_owned();
+            if let Err(err) = model.edit_epub(&book) {
+                warn!("Unable to edit {}: {}", book.display(), err);
+                model.failed_books.insert(book, err.to_string());
+            }
+            if let Err(err) = model.save_session() {
+                warn!("Unable to save the session: {}", err);
+            }
+        }
     }
 
     /// Draw the help page based on the current page
@@ -411,11 +821,23 @@ impl View {
                 let increase_decrease_line = Line::from(
                     "Press <Left | Right> to increase and decrease the number of series.",
                 );
+                let backup_line = Line::from(
+                    "Press <B> to change how books are backed up before they are edited.",
+                );
+                let detect_line = Line::from(
+                    "Press <D> to detect the series of the books in the current directory.",
+                );
+                let import_line = Line::from(
+                    "Press <I> to fill in every page from the sidecars of the books in the current directory.",
+                );
                 Paragraph::new(Text::from(vec![
                     heading_line,
                     Line::default(),
                     Line::from("-- Interact -- ").style(heading_style),
                     increase_decrease_line,
+                    backup_line,
+                    detect_line,
+                    import_line,
                 ]))
             }
             Page::FileSelection => {
@@ -433,6 +855,14 @@ impl View {
                 let nav_deeper_line = Line::from(">> Press <Right> to go enter a directory.");
                 let toggle_file_line =
                     Line::from(">> Press <Tab> to toggle the selection of a file.");
+                let restore_file_line = Line::from(
+                    ">> Press <Ctrl + R> to restore a file marked [backup] from its backup.",
+                );
+                let export_line = Line::from(
+                    ">> Press <Ctrl + E> to export the selected books, or the highlighted book, to sidecars.",
+                );
+                let toggle_covers_line =
+                    Line::from(">> Press <Alt + C> to show or hide the highlighted book's cover.");
 
                 Paragraph::new(Text::from(vec![
                     Line::from("-- Description --").style(heading_style),
@@ -448,6 +878,9 @@ impl View {
                     Line::default(),
                     Line::from("-- Interact -- ").style(heading_style),
                     toggle_file_line,
+                    restore_file_line,
+                    export_line,
+                    toggle_covers_line,
                 ]))
             }
             Page::BookData => {
@@ -458,6 +891,9 @@ impl View {
 
                 let series_name_info =
                     Line::from("Use the 'Series Name' block to input the name of the series.");
+                let series_name_info_cont = Line::from(
+                    "The series name and each book's position are saved as series metadata.",
+                );
 
                 let format_string_info = Line::from(
                     "Use the 'Format String' block to input the format you want the final",
@@ -465,7 +901,39 @@ impl View {
                 let format_string_info_cont = Line::from("book title to follow:");
                 let format_string_title = Line::from(" - Book title: ${title}");
                 let format_string_series = Line::from(" - Series name: ${series}");
-                let format_string_position = Line::from(" - Position in series: ${position}");
+                let format_string_position =
+                    Line::from(" - Position in series, padded to 2 digits: ${position}");
+                let format_string_more = Line::from(
+                    " - Also ${author}, ${author_sort}, ${year}, ${total}, ${original_title} and ${filename}",
+                );
+                let format_string_width = Line::from(
+                    " - Widths: ${position:03} pads with zeros, ${title:>10} aligns, ${title:.20} cuts",
+                );
+                let format_string_case =
+                    Line::from(" - Case: ${title|upper}, ${title|lower} or ${title|title}");
+                let format_string_optional = Line::from(
+                    " - Optional sections such as $[ (${year})] are left out when a value is empty",
+                );
+                let format_string_dollar = Line::from(" - $$ is a literal $");
+                let path_format_info = Line::from(
+                    "Use the 'File Path Format' block to move the edited books to new paths,",
+                );
+                let path_format_info_cont =
+                    Line::from("e.g. ${author}/${series}/${position} - ${title}.epub");
+                let position_info = Line::from(
+                    "A book's position is its order unless one is typed in the Position column,",
+                );
+                let position_info_cont =
+                    Line::from("positions can be zero, have decimals (2.5) or leave gaps.");
+
+                let metadata_fields_info = Line::from(
+                    "The Metadata Fields table sets Dublin Core fields for every book of the series,",
+                );
+                let metadata_fields_info_cont = Line::from(
+                    "the last column overrides them for the book highlighted in the book order table.",
+                );
+                let metadata_fields_info_subjects =
+                    Line::from("Subjects are separated by ;, empty fields are left as they are.");
 
                 let book_order_info = Line::from(
                     "Use the table to change the order of the books and correct book titles.",
@@ -476,30 +944,80 @@ impl View {
                 let nav_table_line =
                     Line::from(">> Press <Up | Down | Left | Right> to navigate the file table.");
 
-                let change_text_line =
-                    Line::from("While highlighting either text box or the book title:");
+                let change_text_line = Line::from(
+                    "While highlighting a text box, a metadata field or a book's title, position, authors or cover:",
+                );
                 let change_text_line_cont =
                     Line::from(" >> Press <any character> to edit the text.");
                 let change_order_line =
-                    Line::from("While highlighting any book but not the title:");
+                    Line::from("While highlighting any book but not the title or position:");
                 let change_order_digit_line =
                     Line::from(" >> Press <any digit> to move that book into that position.");
                 let change_order_arrows_line =
                     Line::from(" >> Press <Ctrl + Up | Down> to move the book one position.");
+                let authors_info =
+                    Line::from("Authors are written as Name [role] {file-as}, separated by ;");
+                let authors_info_cont =
+                    Line::from("e.g. Isaac Asimov {Asimov, Isaac}; Jane Doe [trl]");
+                let copy_authors_line = Line::from(
+                    " >> Press <Ctrl + A> to give every book the highlighted book's authors.",
+                );
+                let normalize_authors_line = Line::from(
+                    " >> Press <Ctrl + N> to swap 'Last, First' names and fill in sort names.",
+                );
+                let covers_info = Line::from(
+                    "The Cover column replaces a book's cover with an image file (JPEG, PNG or GIF),",
+                );
+                let covers_info_cont =
+                    Line::from("or with a generated cover when it holds 'placeholder'.");
+                let placeholder_covers_line = Line::from(
+                    " >> Press <Ctrl + P> to give every book without a cover a placeholder.",
+                );
+                let toggle_covers_line = Line::from(
+                    ">> Press <Alt + C> to show or hide the highlighted book's new cover.",
+                );
+                let auto_order_line = Line::from(
+                    " >> Press <Ctrl + O> to order the books by series index, filename, title or date.",
+                );
+                let ambiguous_line =
+                    Line::from("    Books marked ? need checking, moving one clears its mark.");
+                let save_manifest_line = Line::from(format!(
+                    ">> Press <Ctrl + S> to save every series to a manifest ({} by default).",
+                    DEFAULT_MANIFEST
+                ));
 
                 Paragraph::new(Text::from(vec![
                     Line::from("-- Description --").style(heading_style),
                     description,
                     Line::default(),
                     series_name_info,
+                    series_name_info_cont,
                     Line::default(),
                     format_string_info,
                     format_string_info_cont,
                     format_string_title,
                     format_string_series,
                     format_string_position,
+                    format_string_more,
+                    format_string_width,
+                    format_string_case,
+                    format_string_optional,
+                    format_string_dollar,
+                    Line::default(),
+                    path_format_info,
+                    path_format_info_cont,
+                    Line::default(),
+                    metadata_fields_info,
+                    metadata_fields_info_cont,
+                    metadata_fields_info_subjects,
                     Line::default(),
                     book_order_info,
+                    position_info,
+                    position_info_cont,
+                    authors_info,
+                    authors_info_cont,
+                    covers_info,
+                    covers_info_cont,
                     Line::default(),
                     Line::from("-- Nav --").style(heading_style),
                     swap_fields_line,
@@ -511,6 +1029,42 @@ impl View {
                     change_order_line,
                     change_order_digit_line,
                     change_order_arrows_line,
+                    auto_order_line,
+                    ambiguous_line,
+                    copy_authors_line,
+                    normalize_authors_line,
+                    placeholder_covers_line,
+                    toggle_covers_line,
+                    save_manifest_line,
+                ]))
+            }
+            Page::Review => {
+                let description = Line::from(
+                    "This page shows the metadata changes that will be made to every book.",
+                );
+                let description_cont =
+                    Line::from("No book is written to until you continue to the next page.");
+                let error_line = Line::from(
+                    "Books that can't be edited show the error in red instead, and must be fixed before continuing.",
+                );
+
+                let nav_line = Line::from(">> Press <Up | Down> to move through the books.");
+                let confirm_line =
+                    Line::from(">> Press <Ctrl + Right> to confirm and edit the books.");
+                let back_line = Line::from(">> Press <Ctrl + Left> to go back and make changes.");
+
+                Paragraph::new(Text::from(vec![
+                    Line::from("-- Description --").style(heading_style),
+                    description,
+                    description_cont,
+                    error_line,
+                    Line::default(),
+                    Line::from("-- Nav --").style(heading_style),
+                    nav_line,
+                    Line::default(),
+                    Line::from("-- Interact --").style(heading_style),
+                    confirm_line,
+                    back_line,
                 ]))
             }
             _ => Paragraph::new(Text::from("No help for this page.")),
@@ -540,4 +1094,13 @@ impl View {
         ])
         .split(popup_layout[1])[1] // Return the middle chunk
     }
+
+    /// Get the cell showing a book's creators, marked in red when they can't be read
+    fn authors_cell(authors: &str) -> Cell<'static> {
+        let cell = Cell::from(authors.to_string());
+        match parse_creators(authors) {
+            Ok(_) => cell,
+            Err(_) => cell.style(Style::default().fg(Color::Red)),
+        }
+    }
 }
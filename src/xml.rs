@@ -0,0 +1,575 @@
+use std::fmt::{self, Display};
+
+use color_eyre::eyre::eyre;
+use quick_xml::{
+    Reader,
+    escape::{escape, unescape},
+    events::{BytesStart, Event},
+};
+
+/// Enum of the nodes that make up an XML document
+#[derive(Clone, Debug)]
+pub enum Node {
+    /// An element along with its attributes and children
+    Element(Element),
+    /// Character data, kept exactly as it appeared in the source (still escaped)
+    Text(String),
+    /// Anything else (declarations, comments, CDATA, doctypes), kept verbatim
+    Other(String),
+}
+
+/// Struct holding a single XML element
+///
+/// The element remembers the exact markup it was parsed from so that serializing an
+/// untouched element gives back the original bytes.
+#[derive(Clone, Debug)]
+pub struct Element {
+    /// The qualified name of the element (e.g. `dc:title`)
+    pub name: String,
+    /// The attributes of the element in source order, with unescaped values
+    attributes: Vec<(String, String)>,
+    /// The child nodes of the element
+    pub children: Vec<Node>,
+    /// Whether the element was written as `<tag/>`
+    self_closing: bool,
+    /// The original start tag, cleared once the name or attributes change
+    raw_start: Option<String>,
+    /// The original end tag, written back as it was whatever the element's children become
+    raw_end: Option<String>,
+}
+
+impl Element {
+    /// Create a new, empty element with the given qualified name
+    pub fn new(name: &str) -> Self {
+        Element {
+            name: name.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            self_closing: true,
+            raw_start: None,
+            raw_end: None,
+        }
+    }
+
+    /// Builder method for adding an attribute to a new element
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    /// Builder method for setting the text of a new element
+    pub fn with_text(mut self, value: &str) -> Self {
+        self.set_text(value);
+        self
+    }
+
+    /// Get the name of the element without its namespace prefix
+    pub fn local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+
+    /// Get the namespace prefix of the element if it has one
+    pub fn prefix(&self) -> Option<&str> {
+        self.name.split_once(':').map(|(prefix, _)| prefix)
+    }
+
+    /// Get all attributes of the element in source order
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// Get the value of an attribute by its qualified name
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set the value of an attribute, adding it if it does not exist
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        if self.attribute(name) == Some(value) {
+            return;
+        }
+        match self.attributes.iter_mut().find(|(key, _)| key == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.attributes.push((name.to_string(), value.to_string())),
+        }
+        self.raw_start = None;
+    }
+
+    /// Remove an attribute if the element has it
+    pub fn remove_attribute(&mut self, name: &str) {
+        if self.attribute(name).is_none() {
+            return;
+        }
+        self.attributes.retain(|(key, _)| key != name);
+        self.raw_start = None;
+    }
+
+    /// Get the unescaped text content of the element
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Text(raw) => text.push_str(&unescape(raw).unwrap_or(raw.into())),
+                Node::Other(raw) if raw.starts_with("<![CDATA[") => {
+                    text.push_str(&raw[9..raw.len() - 3]);
+                }
+                Node::Element(element) => text.push_str(&element.text()),
+                Node::Other(_) => {}
+            }
+        }
+
+        text
+    }
+
+    /// Replace the content of the element with the given text
+    pub fn set_text(&mut self, value: &str) {
+        if !self.self_closing && self.text() == value && self.elements().next().is_none() {
+            return;
+        }
+        self.children = vec![Node::Text(escape(value).to_string())];
+        if self.self_closing {
+            self.self_closing = false;
+            self.raw_start = None;
+        }
+    }
+
+    /// Iterate over the child elements
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Iterate mutably over the child elements
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Find the first child element with the given local name
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.local_name() == name)
+    }
+
+    /// Find the first child element with the given local name mutably
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.elements_mut()
+            .find(|element| element.local_name() == name)
+    }
+
+    /// Append a child element, copying the indentation used by the existing children
+    ///
+    /// The first child of an element gets one more level of indentation than the element's
+    /// closing tag when the closing tag is on a line of its own.
+    pub fn append_child(&mut self, element: Element) {
+        let closing_indent = match self.children.last() {
+            Some(Node::Text(text)) if text.trim().is_empty() => Some(self.children.pop().unwrap()),
+            _ => None,
+        };
+        let indent = self.child_indent().or_else(|| match &closing_indent {
+            Some(Node::Text(text)) if text.contains('\n') => Some(format!("{}  ", text)),
+            _ => None,
+        });
+        if let Some(indent) = indent {
+            self.children.push(Node::Text(indent));
+        }
+        self.children.push(Node::Element(element));
+        if let Some(closing_indent) = closing_indent {
+            self.children.push(closing_indent);
+        }
+        if self.self_closing {
+            self.self_closing = false;
+            self.raw_start = None;
+        }
+    }
+
+    /// Insert a child element before the existing child elements, copying their indentation
+    pub fn prepend_child(&mut self, element: Element) {
+        self.insert_child_before(element, |_| true);
+    }
+
+    /// Insert a child element before the first child element matching the predicate,
+    /// copying the indentation of the existing children, or append it when none match
+    pub fn insert_child_before<F: Fn(&Element) -> bool>(&mut self, element: Element, predicate: F) {
+        let indent = self.child_indent();
+        let Some(index) = self
+            .children
+            .iter()
+            .position(|child| matches!(child, Node::Element(child) if predicate(child)))
+        else {
+            self.append_child(element);
+            return;
+        };
+        self.children.insert(index, Node::Element(element));
+        if let Some(indent) = indent {
+            self.children.insert(index + 1, Node::Text(indent));
+        }
+    }
+
+    /// Remove every child element matching the predicate, along with its indentation
+    pub fn remove_children<F: Fn(&Element) -> bool>(&mut self, predicate: F) {
+        let mut index = 0;
+        while index < self.children.len() {
+            if matches!(&self.children[index], Node::Element(element) if predicate(element)) {
+                self.children.remove(index);
+                if index > 0
+                    && matches!(&self.children[index - 1], Node::Text(text) if text.trim().is_empty())
+                {
+                    self.children.remove(index - 1);
+                    index -= 1;
+                }
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Get the whitespace that precedes the last child element, if any
+    fn child_indent(&self) -> Option<String> {
+        let mut previous: Option<&Node> = None;
+        let mut indent = None;
+        for child in &self.children {
+            if let (Node::Element(_), Some(Node::Text(text))) = (child, previous)
+                && text.trim().is_empty()
+            {
+                indent = Some(text.clone());
+            }
+            previous = Some(child);
+        }
+
+        indent
+    }
+
+    /// Write the element to the output string
+    fn write(&self, out: &mut String) {
+        match &self.raw_start {
+            Some(raw) => out.push_str(raw),
+            None => {
+                out.push('<');
+                out.push_str(&self.name);
+                for (key, value) in &self.attributes {
+                    out.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+                }
+                out.push_str(if self.self_closing { "/>" } else { ">" });
+            }
+        }
+        if self.self_closing {
+            return;
+        }
+        for child in &self.children {
+            child.write(out);
+        }
+        match &self.raw_end {
+            Some(raw) => out.push_str(raw),
+            None => out.push_str(&format!("</{}>", self.name)),
+        }
+    }
+
+    /// Create an element from a start tag event
+    fn from_start(start: &BytesStart, raw: &str, self_closing: bool) -> color_eyre::Result<Self> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes().with_checks(false) {
+            let attribute = attribute?;
+            let key = String::from_utf8(attribute.key.as_ref().to_vec())?;
+            let value = attribute.unescape_value()?.to_string();
+            attributes.push((key, value));
+        }
+
+        Ok(Element {
+            name: String::from_utf8(start.name().as_ref().to_vec())?,
+            attributes,
+            children: Vec::new(),
+            self_closing,
+            raw_start: Some(raw.to_string()),
+            raw_end: None,
+        })
+    }
+}
+
+impl Node {
+    /// Write the node to the output string
+    fn write(&self, out: &mut String) {
+        match self {
+            Node::Element(element) => element.write(out),
+            Node::Text(raw) | Node::Other(raw) => out.push_str(raw),
+        }
+    }
+}
+
+/// Struct holding a parsed XML document
+#[derive(Clone, Debug)]
+pub struct XmlDocument {
+    /// The top level nodes of the document
+    pub nodes: Vec<Node>,
+}
+
+impl XmlDocument {
+    /// Parse an XML document from a string
+    pub fn parse(source: &str) -> color_eyre::Result<Self> {
+        let mut reader = Reader::from_str(source);
+        reader.config_mut().check_end_names = true;
+
+        // Stack of the elements currently open, the bottom holds the top level nodes
+        let mut stack: Vec<Element> = vec![Element::new("")];
+        let mut last_position = 0;
+        loop {
+            let event = reader.read_event().map_err(|err| {
+                eyre!(
+                    "Invalid XML at position {}: {}",
+                    reader.error_position(),
+                    err
+                )
+            })?;
+            let position = reader.buffer_position() as usize;
+            let raw = &source[last_position..position];
+            last_position = position;
+
+            let parent = stack.last_mut().unwrap();
+            match event {
+                Event::Start(start) => {
+                    stack.push(Element::from_start(&start, raw, false)?);
+                }
+                Event::Empty(start) => {
+                    let element = Element::from_start(&start, raw, true)?;
+                    parent.children.push(Node::Element(element));
+                }
+                Event::End(_) => {
+                    let mut element = stack.pop().unwrap();
+                    element.raw_end = Some(raw.to_string());
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Err(eyre!("Unexpected closing tag {}", raw)),
+                    }
+                }
+                Event::Text(_) => parent.children.push(Node::Text(raw.to_string())),
+                Event::Eof => break,
+                _ => parent.children.push(Node::Other(raw.to_string())),
+            }
+        }
+
+        if stack.len() != 1 {
+            return Err(eyre!("Unclosed element <{}>", stack.last().unwrap().name));
+        }
+        let nodes = stack.pop().unwrap().children;
+        if !nodes.iter().any(|node| matches!(node, Node::Element(_))) {
+            return Err(eyre!("XML document has no root element"));
+        }
+
+        Ok(XmlDocument { nodes })
+    }
+
+    /// Get the root element of the document
+    pub fn root(&self) -> &Element {
+        self.nodes
+            .iter()
+            .find_map(|node| match node {
+                Node::Element(element) => Some(element),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Get the root element of the document mutably
+    pub fn root_mut(&mut self) -> &mut Element {
+        self.nodes
+            .iter_mut()
+            .find_map(|node| match node {
+                Node::Element(element) => Some(element),
+                _ => None,
+            })
+            .unwrap()
+    }
+}
+
+impl Display for XmlDocument {
+    /// Serialize the document, reproducing the source for everything left unedited
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        for node in &self.nodes {
+            node.write(&mut out);
+        }
+
+        f.write_str(&out)
+    }
+}
+
+/// Strip the namespace prefix from a qualified name
+pub fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document mixing a declaration, comments, CDATA, entities, namespaces and tags
+    /// written over several lines
+    const SOURCE: &str = "<?xml version='1.0' encoding='utf-8'?>
+<!-- A comment -->
+<package xmlns=\"http://www.idpf.org/2007/opf\"
+         version = '2.0' >
+  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+    <dc:title   id='title'>Foundation &amp; Empire</dc:title >
+    <dc:description><![CDATA[<p>The second book</p>]]></dc:description>
+    <meta name=\"calibre:series\"
+          content=\"Foundation\" />
+  </metadata>
+  <spine/>
+</package>
+";
+
+    /// Get the `<metadata>` element of the document
+    fn metadata(document: &mut XmlDocument) -> &mut Element {
+        document.root_mut().child_mut("metadata").unwrap()
+    }
+
+    #[test]
+    fn untouched_documents_are_written_back_as_they_are() {
+        let document = XmlDocument::parse(SOURCE).unwrap();
+
+        assert_eq!(document.to_string(), SOURCE);
+    }
+
+    #[test]
+    fn elements_are_read_with_their_names_attributes_and_text() {
+        let document = XmlDocument::parse(SOURCE).unwrap();
+        let root = document.root();
+        assert_eq!(root.attribute("version"), Some("2.0"));
+        let metadata = root.child("metadata").unwrap();
+        let title = metadata.child("title").unwrap();
+        assert_eq!(title.name, "dc:title");
+        assert_eq!(title.prefix(), Some("dc"));
+        assert_eq!(title.attribute("id"), Some("title"));
+        assert_eq!(title.text(), "Foundation & Empire");
+        assert_eq!(
+            metadata.child("description").unwrap().text(),
+            "<p>The second book</p>"
+        );
+    }
+
+    #[test]
+    fn only_edited_elements_change() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        let metadata = metadata(&mut document);
+        metadata
+            .child_mut("title")
+            .unwrap()
+            .set_text("Second Foundation");
+        metadata
+            .child_mut("meta")
+            .unwrap()
+            .set_attribute("content", "Foundation <Saga>");
+
+        // The edited start tag is written again, while the end tag is kept as it was
+        assert_eq!(
+            document.to_string(),
+            SOURCE
+                .replace(
+                    "Foundation &amp; Empire</dc:title >",
+                    "Second Foundation</dc:title >"
+                )
+                .replace(
+                    "<meta name=\"calibre:series\"\n          content=\"Foundation\" />",
+                    "<meta name=\"calibre:series\" content=\"Foundation &lt;Saga&gt;\"/>"
+                )
+        );
+    }
+
+    #[test]
+    fn unchanged_values_keep_the_original_markup() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        let metadata = metadata(&mut document);
+        metadata
+            .child_mut("title")
+            .unwrap()
+            .set_text("Foundation & Empire");
+        let meta = metadata.child_mut("meta").unwrap();
+        meta.set_attribute("content", "Foundation");
+        meta.remove_attribute("scheme");
+
+        assert_eq!(document.to_string(), SOURCE);
+    }
+
+    #[test]
+    fn appended_children_copy_the_indentation_of_their_siblings() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        metadata(&mut document).append_child(Element::new("dc:language").with_text("en"));
+
+        assert_eq!(
+            document.to_string(),
+            SOURCE.replace(
+                "content=\"Foundation\" />\n",
+                "content=\"Foundation\" />\n    <dc:language>en</dc:language>\n"
+            )
+        );
+    }
+
+    #[test]
+    fn first_children_are_indented_one_level_past_the_closing_tag() {
+        let mut document =
+            XmlDocument::parse("<package>\n  <spine>\n  </spine>\n</package>").unwrap();
+        let spine = document.root_mut().child_mut("spine").unwrap();
+        spine.append_child(Element::new("itemref").with_attribute("idref", "text"));
+
+        assert_eq!(
+            document.to_string(),
+            "<package>\n  <spine>\n    <itemref idref=\"text\"/>\n  </spine>\n</package>"
+        );
+    }
+
+    #[test]
+    fn self_closing_elements_gain_an_end_tag_with_their_children() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        let spine = document.root_mut().child_mut("spine").unwrap();
+        spine.append_child(Element::new("itemref").with_attribute("idref", "text"));
+
+        assert_eq!(
+            document.to_string(),
+            SOURCE.replace("<spine/>", "<spine><itemref idref=\"text\"/></spine>")
+        );
+    }
+
+    #[test]
+    fn inserted_children_copy_the_indentation_of_their_siblings() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        metadata(&mut document).insert_child_before(
+            Element::new("dc:creator").with_text("Isaac Asimov"),
+            |element| element.local_name() == "description",
+        );
+
+        assert_eq!(
+            document.to_string(),
+            SOURCE.replace(
+                "    <dc:description>",
+                "    <dc:creator>Isaac Asimov</dc:creator>\n    <dc:description>"
+            )
+        );
+    }
+
+    #[test]
+    fn removed_children_take_their_indentation_with_them() {
+        let mut document = XmlDocument::parse(SOURCE).unwrap();
+        metadata(&mut document).remove_children(|element| element.local_name() == "description");
+
+        assert_eq!(
+            document.to_string(),
+            SOURCE.replace(
+                "\n    <dc:description><![CDATA[<p>The second book</p>]]></dc:description>",
+                ""
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_rejected() {
+        assert!(XmlDocument::parse("<package><metadata></package>").is_err());
+        assert!(XmlDocument::parse("<package>").is_err());
+        assert!(XmlDocument::parse("<?xml version=\"1.0\"?><!-- empty -->").is_err());
+    }
+}